@@ -0,0 +1,188 @@
+//! Times the screen-heavy instructions through the public API, then the
+//! screen on its own against the old one byte per pixel layout.
+//!
+//! Run with `cargo bench`. There is no benchmark framework here on purpose,
+//! it just reports instructions (or draws) per second so changes to
+//! `Screen` can be compared before and after.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use chip_8_emulator::{Chip8, HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, WIDTH};
+
+/// The real `Screen`, which isn't public, built straight from its source.
+#[allow(dead_code)]
+#[path = "../src/chip_8/screen.rs"]
+mod screen;
+
+/// How long to run each benchmark for.
+const DURATION: Duration = Duration::from_secs(2);
+
+/// The sprite the draw benchmarks use, 8x15.
+const SPRITE: [u8; 15] = [
+    0xFF, 0x81, 0xBD, 0xA5, 0xA5, 0xBD, 0x81, 0xFF, 0xFF, 0x81, 0xBD, 0xA5, 0xA5, 0xBD, 0x81,
+];
+
+/// What a screen layout has to do for the benchmarks to compare them.
+trait Layout: Default {
+    /// XORs `sprite` onto the screen at `x`, `y`, clipping at the edges.
+    /// Returns whether any pixel was turned off.
+    fn draw(&mut self, x: u8, y: u8, sprite: &[u8]) -> bool;
+    fn clear(&mut self);
+}
+
+/// How the screen used to be stored: one byte per pixel, drawn a pixel at a
+/// time.
+struct BytePerPixel([u8; (WIDTH * HEIGHT) as usize]);
+
+impl Default for BytePerPixel {
+    fn default() -> Self {
+        Self([0; (WIDTH * HEIGHT) as usize])
+    }
+}
+
+impl Layout for BytePerPixel {
+    fn draw(&mut self, x: u8, y: u8, sprite: &[u8]) -> bool {
+        let mut collided = false;
+        for (row, &byte) in sprite.iter().enumerate() {
+            let y = y as usize + row;
+            if y >= HEIGHT as usize {
+                break;
+            }
+            for column in 0..8 {
+                let x = x as usize + column;
+                if x >= WIDTH as usize {
+                    break;
+                }
+                if (byte >> (7 - column)) & 1 == 1 {
+                    let pixel = &mut self.0[y * WIDTH as usize + x];
+                    *pixel ^= 1;
+                    collided |= *pixel == 0;
+                }
+            }
+        }
+        collided
+    }
+
+    fn clear(&mut self) {
+        self.0.fill(0);
+    }
+}
+
+impl Layout for screen::Screen {
+    fn draw(&mut self, x: u8, y: u8, sprite: &[u8]) -> bool {
+        let mut collided = false;
+        for (row, &byte) in sprite.iter().enumerate() {
+            match self.xor_row(x, y + row as u8, byte as u16, 8, 0b01, false) {
+                Some(row_collided) => collided |= row_collided,
+                None => break,
+            }
+        }
+        collided
+    }
+
+    fn clear(&mut self) {
+        screen::Screen::clear(self);
+    }
+}
+
+/// Runs `step` on a fresh `S` for [`DURATION`] and reports how many times
+/// a second it ran.
+fn bench_layout<S: Layout>(name: &str, mut step: impl FnMut(&mut S, u32)) {
+    let mut screen = S::default();
+
+    let start = Instant::now();
+    let mut steps = 0u32;
+    while start.elapsed() < DURATION {
+        for _ in 0..1000 {
+            step(&mut screen, steps);
+            steps = steps.wrapping_add(1);
+        }
+    }
+    black_box(&mut screen);
+
+    let per_second = steps as f64 / start.elapsed().as_secs_f64();
+    println!("{name:<24} {:>10.0} per second", per_second);
+}
+
+/// Draws the sprite around the screen like the "draw 8x15" program does.
+fn draw_around<S: Layout>(screen: &mut S, step: u32) {
+    let x = (step * 3 % WIDTH) as u8;
+    let y = (step * 5 % HEIGHT) as u8;
+    black_box(screen.draw(x, y, black_box(&SPRITE)));
+}
+
+/// Compares the old layout with the current one, a line for each.
+fn compare_layouts() {
+    println!();
+    bench_layout::<BytePerPixel>("draws, byte per pixel", draw_around);
+    bench_layout::<screen::Screen>("draws, packed rows", draw_around);
+    bench_layout::<BytePerPixel>("clears, byte per pixel", |screen, _| screen.clear());
+    bench_layout::<screen::Screen>("clears, packed rows", |screen, _| screen.clear());
+}
+
+fn bench(name: &str, program: &[u8]) {
+    let mut chip_8 = Chip8::headless();
+    chip_8.initialize().unwrap();
+    chip_8.load_program(program.to_vec()).unwrap();
+    chip_8.quirks.large_sprites = true;
+
+    let start = Instant::now();
+    let mut cycles = 0u64;
+    while start.elapsed() < DURATION {
+        for _ in 0..1000 {
+            chip_8.cycle().unwrap();
+        }
+        cycles += 1000;
+    }
+    black_box(chip_8.screen());
+
+    let per_second = cycles as f64 / start.elapsed().as_secs_f64();
+    println!("{name:<24} {:>10.0} instructions/s", per_second);
+}
+
+fn main() {
+    // Draws a full-height sprite over and over, moving it around the screen
+    // (and past the edges, so clipping gets exercised too).
+    bench(
+        "draw 8x15",
+        &[
+            0xA2, 0x0C, // LD I, 0x20C
+            0xD0, 0x1F, // DRW V0, V1, 15
+            0x70, 0x03, // ADD V0, 0x03
+            0x71, 0x05, // ADD V1, 0x05
+            0x12, 0x02, // JP 0x202
+            0x00, 0x00, // padding
+            0xFF, 0x81, 0xBD, 0xA5, 0xA5, 0xBD, 0x81, 0xFF, // sprite
+            0xFF, 0x81, 0xBD, 0xA5, 0xA5, 0xBD, 0x81,
+        ],
+    );
+
+    // Same thing with 16x16 sprites in high resolution.
+    bench(
+        "draw 16x16 hires",
+        &[
+            0x00, 0xFF, // HIGH
+            0xA2, 0x0E, // LD I, 0x20E
+            0xD0, 0x10, // DRW V0, V1, 0
+            0x70, 0x07, // ADD V0, 0x07
+            0x71, 0x0B, // ADD V1, 0x0B
+            0x12, 0x04, // JP 0x204
+            0x00, 0x00, // padding
+            0xFF, 0xFF, 0x80, 0x01, 0xBF, 0xFD, 0xA0, 0x05, // sprite
+            0xA0, 0x05, 0xBF, 0xFD, 0x80, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x80, 0x01, 0xBF, 0xFD,
+            0xA0, 0x05, 0xA0, 0x05, 0xBF, 0xFD, 0x80, 0x01, 0xFF, 0xFF,
+        ],
+    );
+
+    // Clears the screen as fast as possible.
+    bench(
+        "clear",
+        &[
+            0x00, 0xE0, // CLS
+            0x12, 0x00, // JP 0x200
+        ],
+    );
+
+    compare_layouts();
+}
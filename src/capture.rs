@@ -0,0 +1,146 @@
+//! Saving what's on the screen, for bug reports and sharing.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The longest recording we keep in memory: one minute at the default 60Hz
+/// display rate, less at a higher `--fps`. A high resolution frame is 8KB,
+/// so that's at most about 30MB.
+pub const MAX_RECORDING_FRAMES: usize = 60 * 60;
+
+/// A frame from the emulator scaled up, still one palette index per pixel.
+fn scale_up(frame: &[u8], width: u32, scale: u32) -> Vec<u8> {
+    let mut scaled = Vec::with_capacity(frame.len() * (scale * scale) as usize);
+
+    for row in frame.chunks_exact(width as usize) {
+        for _ in 0..scale {
+            for &pixel in row {
+                for _ in 0..scale {
+                    scaled.push(pixel & 0b11);
+                }
+            }
+        }
+    }
+
+    scaled
+}
+
+/// A frame from the emulator mapped through the palette and scaled up, as
+/// RGBA bytes.
+pub fn to_rgba(frame: &[u8], width: u32, palette: &[[u8; 4]; 4], scale: u32) -> Vec<u8> {
+    scale_up(frame, width, scale)
+        .into_iter()
+        .flat_map(|pixel| palette[pixel as usize])
+        .collect()
+}
+
+/// Writes a frame to a PNG, scaled up so it isn't tiny.
+pub fn save_png(
+    path: &Path,
+    frame: &[u8],
+    (width, height): (u32, u32),
+    palette: &[[u8; 4]; 4],
+    scale: u32,
+) -> Result<(), Box<dyn Error>> {
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        width * scale,
+        height * scale,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&to_rgba(frame, width, palette, scale))?;
+
+    Ok(())
+}
+
+/// A path next to the ROM with a timestamp in the name, so captures never
+/// overwrite each other, like `pong.ch8` -> `pong-1700000000000.png`.
+pub fn timestamped_path(rom: &Path, extension: &str) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default();
+    let stem = rom.file_stem().unwrap_or_default().to_string_lossy();
+
+    rom.with_file_name(format!("{stem}-{millis}.{extension}"))
+}
+
+/// Frames collected while recording, to be saved as an animated GIF.
+#[derive(Debug, Default)]
+pub struct Recording {
+    /// Each frame along with its width, since SUPER-CHIP programs can switch
+    /// resolution partway through.
+    frames: Vec<(Box<[u8]>, u32)>,
+}
+
+impl Recording {
+    /// Adds a frame. Returns false (and drops the frame) once the recording
+    /// is [`MAX_RECORDING_FRAMES`] long.
+    pub fn push(&mut self, frame: &[u8], width: u32) -> bool {
+        if self.frames.len() >= MAX_RECORDING_FRAMES {
+            return false;
+        }
+
+        self.frames.push((frame.into(), width));
+        true
+    }
+
+    /// How many frames have been recorded.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Encodes the frames as a looping GIF that plays back at `fps`. Frames
+    /// are scaled to fit the widest one, so low resolution frames stay the
+    /// same size on screen if the program switches to high resolution.
+    pub fn save_gif(
+        &self,
+        path: &Path,
+        palette: &[[u8; 4]; 4],
+        scale: u32,
+        fps: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some((widest, max_width)) = self.frames.iter().max_by_key(|(_, width)| *width) else {
+            return Err("nothing was recorded".into());
+        };
+        let max_height = widest.len() as u32 / max_width;
+
+        let global_palette: Vec<u8> = palette
+            .iter()
+            .flat_map(|rgba| &rgba[..3])
+            .copied()
+            .collect();
+        // GIFs can't be more than 65535 pixels across.
+        let scaled = |size: u32| {
+            size.checked_mul(scale)
+                .and_then(|size| u16::try_from(size).ok())
+                .ok_or_else(|| format!("a scale of {scale} is too big for a GIF"))
+        };
+        let (width, height) = (scaled(*max_width)?, scaled(max_height)?);
+        let mut encoder = gif::Encoder::new(
+            BufWriter::new(File::create(path)?),
+            width,
+            height,
+            &global_palette,
+        )?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        for (i, (frame, frame_width)) in self.frames.iter().enumerate() {
+            let pixels = scale_up(frame, *frame_width, scale * max_width / frame_width);
+            let mut gif_frame = gif::Frame::from_indexed_pixels(width, height, pixels, None);
+            // GIF delays are in hundredths of a second, so spread the
+            // rounding out instead of always rounding the same way.
+            let delay = |i: usize| (i as u32 * 100 + fps / 2) / fps;
+            gif_frame.delay = (delay(i + 1) - delay(i)) as u16;
+            encoder.write_frame(&gif_frame)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+//! A builder for setting up a [`Chip8`] in one go.
+
+use std::sync::mpsc::{Receiver, Sender};
+
+use super::{
+    memory::MEMORY_SIZE,
+    quirks::{Platform, Quirks},
+    sound::Buzzer,
+    Chip8, Chip8Error, KeyEvent,
+};
+
+/// Sets up a [`Chip8`] with everything it needs before a program is
+/// loaded. [`Chip8::new`] and [`Chip8::headless`] cover the common cases;
+/// this is for when there is more to configure.
+///
+/// Without [`Self::channels`], the emulator is [headless](Chip8::headless).
+/// Like the other constructors, you still have to call
+/// [`Chip8::initialize`] on it before loading a program.
+///
+/// ```
+/// use chip_8_emulator::{quirks::Platform, Chip8};
+///
+/// let mut chip_8 = Chip8::builder()
+///     .platform(Platform::XoChip)
+///     .seed(1234)
+///     .build();
+/// chip_8.initialize().unwrap();
+/// assert_eq!(chip_8.memory_size(), 0x10000);
+/// ```
+#[derive(Debug, Default)]
+pub struct Chip8Builder {
+    quirks: Quirks,
+    seed: Option<u64>,
+    extended_memory: Option<bool>,
+    /// Both are set or neither is.
+    frame_handle: Option<Sender<Box<[u8]>>>,
+    input_handle: Option<Receiver<Result<KeyEvent, Chip8Error>>>,
+}
+
+impl Chip8Builder {
+    /// Starts with the default quirks, no seed and no channels.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses `quirks`, replacing whatever [`Self::platform`] set.
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Uses the quirks of `platform`, see [`Quirks::preset`].
+    pub fn platform(self, platform: Platform) -> Self {
+        self.quirks(Quirks::preset(platform))
+    }
+
+    /// Seeds the random number generator, see [`Chip8::set_seed`].
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// How many bytes of memory programs get. Memory is either 4 KB or
+    /// XO-CHIP's 64 KB, so anything over 4 KB gets 64 KB. This always wins
+    /// over [`Quirks::extended_memory`], whichever order they're called in.
+    pub fn memory_size(mut self, bytes: usize) -> Self {
+        self.extended_memory = Some(bytes > MEMORY_SIZE);
+        self
+    }
+
+    /// Drops the channels set with [`Self::channels`], if any.
+    pub fn headless(mut self) -> Self {
+        self.frame_handle = None;
+        self.input_handle = None;
+        self
+    }
+
+    /// Sends frames out through `frame_handle` and takes input from
+    /// `input_handle`, like [`Chip8::new`]. This also turns the buzzer on.
+    pub fn channels(
+        mut self,
+        frame_handle: Sender<Box<[u8]>>,
+        input_handle: Receiver<Result<KeyEvent, Chip8Error>>,
+    ) -> Self {
+        self.frame_handle = Some(frame_handle);
+        self.input_handle = Some(input_handle);
+        self
+    }
+
+    /// Creates the emulator.
+    pub fn build(self) -> Chip8 {
+        let mut chip_8 = match (self.frame_handle, self.input_handle) {
+            (Some(frame_handle), Some(input_handle)) => Chip8 {
+                frame_handle: Some(frame_handle),
+                input_handle: Some(input_handle),
+                buzzer: Buzzer::new(),
+                ..Default::default()
+            },
+            _ => Chip8::default(),
+        };
+        chip_8.quirks = self.quirks;
+        if let Some(extended_memory) = self.extended_memory {
+            chip_8.quirks.extended_memory = extended_memory;
+        }
+        if let Some(seed) = self.seed {
+            chip_8.set_seed(seed);
+        }
+        chip_8
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::Chip8Builder;
+    use crate::quirks::{Platform, Quirks};
+
+    #[test]
+    fn builds_with_the_settings_given() {
+        let chip_8 = Chip8Builder::new()
+            .platform(Platform::Schip)
+            .memory_size(0x10000)
+            .build();
+        assert_eq!(
+            chip_8.quirks,
+            Quirks {
+                extended_memory: true,
+                ..Quirks::preset(Platform::Schip)
+            }
+        );
+
+        // The memory size wins either way round.
+        let chip_8 = Chip8Builder::new()
+            .memory_size(0x10000)
+            .platform(Platform::Schip)
+            .build();
+        assert!(chip_8.quirks.extended_memory);
+        let chip_8 = Chip8Builder::new()
+            .memory_size(0x1000)
+            .platform(Platform::XoChip)
+            .build();
+        assert!(!chip_8.quirks.extended_memory);
+
+        // The same seed gives the same random numbers.
+        let random = |seed| {
+            let mut chip_8 = Chip8Builder::new().seed(seed).build();
+            chip_8.initialize().unwrap();
+            chip_8.load_program(vec![0xC0, 0xFF]).unwrap();
+            chip_8.cycle().unwrap();
+            chip_8.registers()[0]
+        };
+        assert_eq!(random(7), random(7));
+    }
+}
@@ -0,0 +1,396 @@
+//! Turns assembly back into a program, the other way around from
+//! [`disassemble`](super::disassemble).
+//!
+//! The mnemonics are the ones [`Instruction`] is displayed with, one
+//! instruction per line, so a disassembly assembles back into the same
+//! bytes. On top of that, lines can start with a `label:`, which `JP`,
+//! `CALL` and `LD I` take in place of an address, and `;` starts a comment.
+//!
+//! ```
+//! use chip_8_emulator::assemble;
+//!
+//! let program = assemble(
+//!     "start:
+//!         LD V0, 0x05  ; a counter
+//!         CALL draw
+//!         JP start
+//!      draw:
+//!         LD F, V0
+//!         DRW V0, V0, 5
+//!         RET",
+//! )
+//! .unwrap();
+//! assert_eq!(&program[..6], [0x60, 0x05, 0x22, 0x06, 0x12, 0x00]);
+//! ```
+
+use std::collections::HashMap;
+
+use super::Instruction;
+use crate::chip_8::memory::PROGRAM_OFFSET;
+
+/// Something wrong with a program's assembly.
+#[derive(Clone, Debug, thiserror::Error, PartialEq, Eq)]
+pub enum AssembleError {
+    /// A mnemonic that isn't one of the [`Instruction`] ones.
+    #[error("Line {line}: `{mnemonic}` is not an instruction")]
+    UnknownMnemonic {
+        /// The line number, starting at 1.
+        line: usize,
+        /// The mnemonic.
+        mnemonic: String,
+    },
+    /// A known mnemonic with operands it doesn't take, or a number too big
+    /// for where it's used.
+    #[error("Line {line}: `{text}` has the wrong operands")]
+    InvalidOperands {
+        /// The line number, starting at 1.
+        line: usize,
+        /// The whole instruction.
+        text: String,
+    },
+    /// A label that is used, but never defined.
+    #[error("Line {line}: the label `{label}` is never defined")]
+    UnresolvedLabel {
+        /// The line the label is used on.
+        line: usize,
+        /// The label.
+        label: String,
+    },
+    /// A label defined twice.
+    #[error("Line {line}: the label `{label}` is already defined")]
+    DuplicateLabel {
+        /// The line of the second definition.
+        line: usize,
+        /// The label.
+        label: String,
+    },
+}
+
+/// Assembles `source` into a program, ready for
+/// [`Chip8::load_program`](crate::Chip8::load_program). See the
+/// [module docs](self) for the syntax.
+///
+/// `LD I` with a four digit address, like `LD I, 0x1234`, assembles to
+/// XO-CHIP's `F000 NNNN`, since that's how [`Instruction::SetIndexLong`]
+/// is displayed.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let lines = parse(source)?;
+
+    // Every instruction is one word apart from the long `LD I`, so the
+    // labels can all be found before anything is assembled.
+    let mut labels = HashMap::new();
+    let mut address = PROGRAM_OFFSET as u16;
+    for line in &lines {
+        if let Some(label) = line.label {
+            if labels.insert(label, address).is_some() {
+                return Err(AssembleError::DuplicateLabel {
+                    line: line.number,
+                    label: label.to_string(),
+                });
+            }
+        }
+        if line.mnemonic.is_some() {
+            address = address.wrapping_add(line.size());
+        }
+    }
+
+    let mut program = Vec::new();
+    for line in &lines {
+        if line.mnemonic.is_some() {
+            program.extend(line.assemble(&labels)?.to_bytes());
+        }
+    }
+    Ok(program)
+}
+
+/// One line of source, split up but not assembled yet.
+#[derive(Debug)]
+struct Line<'a> {
+    number: usize,
+    label: Option<&'a str>,
+    /// Upper cased, since mnemonics aren't case sensitive.
+    mnemonic: Option<String>,
+    operands: Vec<&'a str>,
+    /// The instruction as written, for errors.
+    text: &'a str,
+}
+
+fn parse(source: &str) -> Result<Vec<Line<'_>>, AssembleError> {
+    let mut lines = Vec::new();
+    for (index, text) in source.lines().enumerate() {
+        let mut text = text.split(';').next().unwrap_or_default().trim();
+        let mut label = None;
+        if let Some((name, rest)) = text.split_once(':') {
+            let name = name.trim();
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(AssembleError::InvalidOperands {
+                    line: index + 1,
+                    text: text.to_string(),
+                });
+            }
+            label = Some(name);
+            text = rest.trim();
+        }
+
+        let (mnemonic, operands) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let operands = operands
+            .split(',')
+            .map(str::trim)
+            .filter(|operand| !operand.is_empty())
+            .collect();
+        lines.push(Line {
+            number: index + 1,
+            label,
+            mnemonic: (!mnemonic.is_empty()).then(|| mnemonic.to_ascii_uppercase()),
+            operands,
+            text,
+        });
+    }
+    Ok(lines)
+}
+
+/// An operand, like `V3`, `[I]` or `0x200`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operand<'a> {
+    Register(u8),
+    /// A number, and whether it was written with four hex digits.
+    Number(u16, bool),
+    /// One of the special operands, like `I`, `DT` or `[I]`.
+    Name(&'a str),
+    /// Anything else is taken to be a label.
+    Label(&'a str),
+}
+
+fn operand(text: &str) -> Option<Operand<'_>> {
+    let register = text
+        .strip_prefix(['V', 'v'])
+        .filter(|digit| digit.len() == 1)
+        .and_then(|digit| u8::from_str_radix(digit, 16).ok());
+    if let Some(vx) = register {
+        return Some(Operand::Register(vx));
+    }
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        return u16::from_str_radix(hex, 16)
+            .ok()
+            .map(|value| Operand::Number(value, hex.len() == 4));
+    }
+    if text.starts_with(|c: char| c.is_ascii_digit()) {
+        return text.parse().ok().map(|value| Operand::Number(value, false));
+    }
+    const NAMES: [&str; 10] = ["I", "[I]", "DT", "ST", "K", "F", "HF", "R", "B", "PITCH"];
+    Some(
+        match NAMES.iter().find(|name| name.eq_ignore_ascii_case(text)) {
+            Some(name) => Operand::Name(name),
+            None => Operand::Label(text),
+        },
+    )
+}
+
+impl Line<'_> {
+    fn size(&self) -> u16 {
+        let long = self.mnemonic.as_deref() == Some("LD")
+            && matches!(self.operands[..], [index, address]
+                if operand(index) == Some(Operand::Name("I"))
+                    && matches!(operand(address), Some(Operand::Number(_, true))));
+        if long {
+            4
+        } else {
+            2
+        }
+    }
+
+    fn assemble(&self, labels: &HashMap<&str, u16>) -> Result<Instruction, AssembleError> {
+        let invalid = || AssembleError::InvalidOperands {
+            line: self.number,
+            text: self.text.to_string(),
+        };
+        let operands = self
+            .operands
+            .iter()
+            .map(|text| operand(text).ok_or_else(invalid))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Resolves an address, which has to fit in 12 bits.
+        let address = |operand: Operand| match operand {
+            Operand::Number(nnn, _) if nnn <= 0xFFF => Ok(nnn),
+            Operand::Label(label) => match labels.get(label) {
+                Some(&nnn) if nnn <= 0xFFF => Ok(nnn),
+                Some(_) => Err(invalid()),
+                None => Err(AssembleError::UnresolvedLabel {
+                    line: self.number,
+                    label: label.to_string(),
+                }),
+            },
+            _ => Err(invalid()),
+        };
+        let byte = |value: u16| u8::try_from(value).map_err(|_| invalid());
+        let nibble = |value: u16| {
+            byte(value)
+                .ok()
+                .filter(|&value| value <= 0xF)
+                .ok_or_else(invalid)
+        };
+
+        use Operand::{Name, Number, Register};
+        let mnemonic = self.mnemonic.as_deref().unwrap_or_default();
+        let instruction = match (mnemonic, &operands[..]) {
+            ("SYS", &[target]) => Instruction::CallMachineCodeRoutine {
+                nnn: address(target)?,
+            },
+            ("CLS", []) => Instruction::Clear,
+            ("RET", []) => Instruction::Return,
+            ("SCD", &[Number(n, _)]) => Instruction::ScrollDown { n: nibble(n)? },
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("LOW", []) => Instruction::LowResolution,
+            ("HIGH", []) => Instruction::HighResolution,
+            ("JP", &[Register(0), target]) => Instruction::JumpWithPcOffset {
+                nnn: address(target)?,
+            },
+            ("JP", &[target]) => Instruction::Jump {
+                nnn: address(target)?,
+            },
+            ("CALL", &[target]) => Instruction::Call {
+                nnn: address(target)?,
+            },
+            ("SE", &[Register(vx), Register(vy)]) => {
+                Instruction::SkipIfRegisterVxEqualsVy { vx, vy }
+            }
+            ("SE", &[Register(vx), Number(nn, _)]) => {
+                Instruction::SkipIfRegisterEquals { vx, nn: byte(nn)? }
+            }
+            ("SNE", &[Register(vx), Register(vy)]) => {
+                Instruction::SkipIfRegisterVxNotEqualsVy { vx, vy }
+            }
+            ("SNE", &[Register(vx), Number(nn, _)]) => {
+                Instruction::SkipIfRegisterNotEquals { vx, nn: byte(nn)? }
+            }
+            ("ADD", &[Register(vx), Register(vy)]) => Instruction::Add { vx, vy },
+            ("ADD", &[Register(vx), Number(nn, _)]) => {
+                Instruction::AddImmediate { vx, nn: byte(nn)? }
+            }
+            ("ADD", &[Name("I"), Register(vx)]) => Instruction::AddToIndex { vx },
+            ("OR", &[Register(vx), Register(vy)]) => Instruction::BitwiseOr { vx, vy },
+            ("AND", &[Register(vx), Register(vy)]) => Instruction::BitwiseAnd { vx, vy },
+            ("XOR", &[Register(vx), Register(vy)]) => Instruction::BitwiseXor { vx, vy },
+            ("SUB", &[Register(vx), Register(vy)]) => Instruction::Subtract { vx, vy },
+            ("SHR", &[Register(vx), Register(vy)]) => Instruction::RightShift { vx, vy },
+            ("SUBN", &[Register(vx), Register(vy)]) => Instruction::SetVxToVyMinusVx { vx, vy },
+            ("SHL", &[Register(vx), Register(vy)]) => Instruction::LeftShift { vx, vy },
+            ("RND", &[Register(vx), Number(nn, _)]) => Instruction::Random { vx, nn: byte(nn)? },
+            ("DRW", &[Register(vx), Register(vy), Number(n, _)]) => Instruction::Draw {
+                vx,
+                vy,
+                n: nibble(n)?,
+            },
+            ("SKP", &[Register(vx)]) => Instruction::SkipIfKeyPressed { vx },
+            ("SKNP", &[Register(vx)]) => Instruction::SkipIfKeyNotPressed { vx },
+            ("PLANE", &[Number(planes, _)]) => Instruction::SelectPlanes {
+                planes: nibble(planes)?,
+            },
+            ("AUDIO", []) => Instruction::LoadAudioPattern,
+            ("LD", &[Name("I"), Number(nnn, true)]) => Instruction::SetIndexLong { nnn },
+            ("LD", &[Name("I"), target]) => Instruction::SetIndexRegister {
+                nnn: address(target)?,
+            },
+            ("LD", &[Register(vx), Register(vy)]) => Instruction::Copy { vx, vy },
+            ("LD", &[Register(vx), Number(nn, _)]) => {
+                Instruction::SetImmediate { vx, nn: byte(nn)? }
+            }
+            ("LD", &[Register(vx), Name("DT")]) => Instruction::SetVxToDelayTimer { vx },
+            ("LD", &[Register(vx), Name("K")]) => Instruction::AwaitKeyInput { vx },
+            ("LD", &[Register(vx), Name("R")]) => Instruction::LoadFlags { vx },
+            ("LD", &[Register(vx), Name("[I]")]) => Instruction::LoadRegisters { vx },
+            ("LD", &[Name("DT"), Register(vx)]) => Instruction::SetDelayTimer { vx },
+            ("LD", &[Name("ST"), Register(vx)]) => Instruction::SetSoundTimer { vx },
+            ("LD", &[Name("F"), Register(vx)]) => Instruction::SetIndexToFontCharacter { vx },
+            ("LD", &[Name("HF"), Register(vx)]) => Instruction::SetIndexToLargeFontCharacter { vx },
+            ("LD", &[Name("R"), Register(vx)]) => Instruction::SaveFlags { vx },
+            ("LD", &[Name("PITCH"), Register(vx)]) => Instruction::SetPitch { vx },
+            ("LD", &[Name("B"), Register(vx)]) => Instruction::SetIndexToBinaryCodedVx { vx },
+            ("LD", &[Name("[I]"), Register(vx)]) => Instruction::DumpRegisters { vx },
+            ("DW", &[Number(raw, _)]) => Instruction::Unknown { raw },
+            (
+                "SYS" | "CLS" | "RET" | "SCD" | "SCR" | "SCL" | "LOW" | "HIGH" | "JP" | "CALL"
+                | "SE" | "SNE" | "ADD" | "OR" | "AND" | "XOR" | "SUB" | "SHR" | "SUBN" | "SHL"
+                | "RND" | "DRW" | "SKP" | "SKNP" | "PLANE" | "AUDIO" | "LD" | "DW",
+                _,
+            ) => return Err(invalid()),
+            _ => {
+                return Err(AssembleError::UnknownMnemonic {
+                    line: self.number,
+                    mnemonic: mnemonic.to_string(),
+                })
+            }
+        };
+        Ok(instruction)
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::{assemble, AssembleError};
+    use crate::disassemble;
+
+    #[test]
+    fn round_trips_a_disassembly() {
+        let program = [
+            0x00, 0xE0, 0x6A, 0x2F, 0xA2, 0x1A, 0xD0, 0x15, 0x7A, 0xFF, 0x3A, 0x10, 0x12, 0x02,
+            0x8A, 0xB4, 0x8A, 0xBE, 0xB3, 0x00, 0xCA, 0x0F, 0xE1, 0x9E, 0xF2, 0x0A, 0xF3, 0x33,
+            0xF0, 0x00, 0x12, 0x34, 0xF5, 0x65, 0x00, 0xFF, 0x00, 0xC4, 0xFF, 0xFF, 0x22, 0x00,
+            0xF1, 0x01, 0xFA, 0x30, 0x00, 0xEE,
+        ];
+        let listing: Vec<String> = disassemble(&program)
+            .into_iter()
+            .map(|(_, instruction)| instruction.to_string())
+            .collect();
+
+        assert_eq!(assemble(&listing.join("\n")).unwrap(), program);
+    }
+
+    #[test]
+    fn resolves_labels() {
+        let source = "
+            JP main     ; skip over the sprite
+            sprite: DW 0xF090
+            main:
+                LD I, sprite
+                CALL draw
+            draw: DRW V0, V1, 2
+                RET
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x12, 0x04, 0xF0, 0x90, 0xA2, 0x02, 0x22, 0x08, 0xD0, 0x12, 0x00, 0xEE]
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(
+            assemble("CLS\nJMP 0x200"),
+            Err(AssembleError::UnknownMnemonic {
+                line: 2,
+                mnemonic: "JMP".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("CALL nowhere"),
+            Err(AssembleError::UnresolvedLabel {
+                line: 1,
+                label: "nowhere".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("LD V0, 0x100"),
+            Err(AssembleError::InvalidOperands {
+                line: 1,
+                text: "LD V0, 0x100".to_string()
+            })
+        );
+        assert!(matches!(
+            assemble("a: CLS\na: RET"),
+            Err(AssembleError::DuplicateLabel { line: 2, .. })
+        ));
+    }
+}
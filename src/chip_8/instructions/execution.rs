@@ -1,53 +1,195 @@
 //! A module set aside for containing all of the methods on [`Chip8`] that emulate
 //! the execution of each instruction.
 
-use log::error;
+use log::{error, warn};
 
-use crate::{chip_8::Chip8Error, Chip8, HEIGHT, WIDTH};
+use crate::{
+    chip_8::{instructions::Instruction, memory, Chip8Error},
+    Chip8,
+};
+
+/// Roughly how many ordinary instructions' worth of time the COSMAC VIP
+/// spent drawing a sprite `rows` tall at column `x`. It fetched and
+/// XORed each row byte by byte, and shifted every row into place one bit
+/// at a time when X wasn't a multiple of 8. This is counted in quarter
+/// instructions: a couple of instructions to set up, one per row, and a
+/// quarter per bit of shifting.
+fn vip_draw_cost(x: u8, rows: u8) -> u32 {
+    let quarters = 8 + rows as u32 * (4 + (x % 8) as u32);
+    quarters.div_ceil(4)
+}
 
 impl Chip8 {
-    pub fn instruction_clear(&mut self) {
-        self.screen.clear();
+    pub(crate) fn instruction_clear(&mut self) {
+        self.screen.clear_planes();
+    }
+
+    pub(crate) fn instruction_select_planes(&mut self, planes: u8) {
+        self.screen.select_planes(planes);
+    }
+
+    pub(crate) fn instruction_load_audio_pattern(&mut self) -> Result<(), Chip8Error> {
+        let address = self.index_register as usize;
+        self.memory.check_range(address, 16)?;
+
+        let mut pattern = [0; 16];
+        for (i, sample) in pattern.iter_mut().enumerate() {
+            *sample = self.memory.byte(address + i);
+        }
+        self.audio_pattern = Some(pattern);
+        self.update_audio_pattern();
+        Ok(())
+    }
+
+    pub(crate) fn instruction_set_pitch(&mut self, vx: u8) {
+        self.audio_pitch = self.registers[vx as usize];
+        self.update_audio_pattern();
+    }
+
+    pub(crate) fn instruction_scroll_down(&mut self, n: u8) {
+        let lines = self.scroll_amount(n as usize);
+        self.screen.scroll_down(lines);
+    }
+
+    pub(crate) fn instruction_scroll_right(&mut self) {
+        let columns = self.scroll_amount(4);
+        self.screen.scroll_right(columns);
+    }
+
+    pub(crate) fn instruction_scroll_left(&mut self) {
+        let columns = self.scroll_amount(4);
+        self.screen.scroll_left(columns);
+    }
+
+    /// SUPER-CHIP scrolls by high resolution pixels, so in low resolution
+    /// the screen only moves half as far.
+    fn scroll_amount(&self, pixels: usize) -> usize {
+        if self.screen.hires() {
+            pixels
+        } else {
+            pixels / 2
+        }
+    }
+
+    pub(crate) fn instruction_low_resolution(&mut self) {
+        self.screen.set_hires(false);
+    }
+
+    pub(crate) fn instruction_high_resolution(&mut self) {
+        self.screen.set_hires(true);
+    }
+
+    /// Sends the screen to the frontend, if it changed since the last frame
+    /// was sent. The frontend keeps showing the last frame until then.
+    pub(crate) fn send_frame(&mut self) {
+        if !self.screen.take_dirty() {
+            return;
+        }
+        let pixels = self.screen.pixels();
+        if let Some(coalesced) = &mut self.coalesced_frame {
+            coalesced.clone_from(&pixels);
+            self.coalesced_changed = false;
+        }
+        self.send_to_frontend(pixels.into());
+    }
+
+    /// Sends `frame` through the frame channel, if there is one. If the
+    /// frontend dropped its end, we carry on headless rather than failing
+    /// every cycle from then on.
+    fn send_to_frontend(&mut self, frame: Box<[u8]>) {
+        if let Some(frame_handle) = &self.frame_handle {
+            if let Err(e) = frame_handle.send(frame) {
+                error!("Error sending frame {e}, no longer sending frames");
+                self.frame_handle = None;
+            }
+        }
+    }
+
+    /// Passes the screen on after an instruction ran. Usually that's
+    /// [`Self::send_frame`], but while coalescing draws it gets added to
+    /// the frame [`Self::send_coalesced_frame`] sends instead.
+    pub(crate) fn update_frame(&mut self) {
+        let Some(coalesced) = &mut self.coalesced_frame else {
+            return self.send_frame();
+        };
+        if !self.screen.take_dirty() {
+            return;
+        }
+
+        let pixels = self.screen.pixels();
+        // Switching resolution starts the frame over.
+        if coalesced.len() == pixels.len() {
+            for (coalesced, pixel) in coalesced.iter_mut().zip(pixels) {
+                *coalesced |= pixel;
+            }
+        } else {
+            *coalesced = pixels;
+        }
+        self.coalesced_changed = true;
+    }
+
+    /// Sends the frame built up by coalescing draws, if anything changed,
+    /// and starts the next one from what's on screen now.
+    pub(crate) fn send_coalesced_frame(&mut self) {
+        if !std::mem::take(&mut self.coalesced_changed) {
+            return;
+        }
+        let Some(coalesced) = &mut self.coalesced_frame else {
+            return;
+        };
+        let frame = std::mem::replace(coalesced, self.screen.pixels());
+        self.send_to_frontend(frame.into());
     }
 
-    pub fn instruction_return(&mut self) -> Result<(), Chip8Error> {
+    pub(crate) fn instruction_return(&mut self) -> Result<(), Chip8Error> {
         self.program_counter = self.pop()?;
         Ok(())
     }
 
-    pub fn instruction_jump(&mut self, nnn: u16) {
+    pub(crate) fn instruction_jump(&mut self, nnn: u16) {
         self.program_counter = nnn;
     }
 
-    pub fn instruction_call(&mut self, nnn: u16) -> Result<(), Chip8Error> {
+    pub(crate) fn instruction_call(&mut self, nnn: u16) -> Result<(), Chip8Error> {
         self.push(self.program_counter)?;
         self.program_counter = nnn;
         Ok(())
     }
 
-    pub fn instruction_skip_if_register_equals(&mut self, vx: u8, nn: u8) {
+    /// Moves the PC past the next instruction, for the skip instructions.
+    /// XO-CHIP's `F000 NNNN` is twice as long as the rest, so it takes an
+    /// extra word.
+    fn skip_next_instruction(&mut self) {
+        let next = self.program_counter as usize;
+        let long = self.memory.check_range(next, 2).is_ok()
+            && self.memory.word(next) == Instruction::LONG_PREFIX;
+        let size = if long { 4 } else { 2 };
+        self.program_counter = self.program_counter.wrapping_add(size);
+    }
+
+    pub(crate) fn instruction_skip_if_register_equals(&mut self, vx: u8, nn: u8) {
         if self.registers[vx as usize] == nn {
-            self.program_counter += 2;
+            self.skip_next_instruction();
         }
     }
 
-    pub fn instruction_skip_if_register_not_equals(&mut self, vx: u8, nn: u8) {
+    pub(crate) fn instruction_skip_if_register_not_equals(&mut self, vx: u8, nn: u8) {
         if self.registers[vx as usize] != nn {
-            self.program_counter += 2;
+            self.skip_next_instruction();
         }
     }
 
-    pub fn instruction_skip_if_register_vx_equals_vy(&mut self, vx: u8, vy: u8) {
+    pub(crate) fn instruction_skip_if_register_vx_equals_vy(&mut self, vx: u8, vy: u8) {
         if self.registers[vx as usize] == self.registers[vy as usize] {
-            self.program_counter += 2;
+            self.skip_next_instruction();
         }
     }
 
-    pub fn instruction_set_immediate(&mut self, vx: u8, nn: u8) {
+    pub(crate) fn instruction_set_immediate(&mut self, vx: u8, nn: u8) {
         self.registers[vx as usize] = nn;
     }
 
-    pub fn instruction_add_immediate(&mut self, vx: u8, nn: u8) {
+    pub(crate) fn instruction_add_immediate(&mut self, vx: u8, nn: u8) {
         let wrapped_sum = self.registers[vx as usize].wrapping_add(nn);
 
         let overflow_ocurred = self.registers[vx as usize].checked_add(nn).is_none();
@@ -56,23 +198,33 @@ impl Chip8 {
         self.registers[0xF] = overflow_ocurred as u8;
     }
 
-    pub fn instruction_copy(&mut self, vx: u8, vy: u8) {
+    pub(crate) fn instruction_copy(&mut self, vx: u8, vy: u8) {
         self.registers[vx as usize] = self.registers[vy as usize]
     }
 
-    pub fn instruction_bitwise_or(&mut self, vx: u8, vy: u8) {
-        self.registers[vx as usize] |= self.registers[vy as usize]
+    pub(crate) fn instruction_bitwise_or(&mut self, vx: u8, vy: u8) {
+        self.registers[vx as usize] |= self.registers[vy as usize];
+        self.reset_vf_after_logic();
     }
 
-    pub fn instruction_bitwise_and(&mut self, vx: u8, vy: u8) {
-        self.registers[vx as usize] &= self.registers[vy as usize]
+    pub(crate) fn instruction_bitwise_and(&mut self, vx: u8, vy: u8) {
+        self.registers[vx as usize] &= self.registers[vy as usize];
+        self.reset_vf_after_logic();
     }
 
-    pub fn instruction_bitwise_xor(&mut self, vx: u8, vy: u8) {
-        self.registers[vx as usize] ^= self.registers[vy as usize]
+    pub(crate) fn instruction_bitwise_xor(&mut self, vx: u8, vy: u8) {
+        self.registers[vx as usize] ^= self.registers[vy as usize];
+        self.reset_vf_after_logic();
     }
 
-    pub fn instruction_add(&mut self, vx: u8, vy: u8) {
+    /// The COSMAC VIP's logic instructions clobbered VF as a side effect.
+    fn reset_vf_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.registers[0xF] = 0;
+        }
+    }
+
+    pub(crate) fn instruction_add(&mut self, vx: u8, vy: u8) {
         let wrapped_sum = self.registers[vx as usize].wrapping_add(self.registers[vy as usize]);
 
         let overflow_ocurred = self.registers[vx as usize]
@@ -83,7 +235,7 @@ impl Chip8 {
         self.registers[0xF] = overflow_ocurred as u8;
     }
 
-    pub fn instruction_subtract(&mut self, vx: u8, vy: u8) {
+    pub(crate) fn instruction_subtract(&mut self, vx: u8, vy: u8) {
         let wrapped_sum = self.registers[vx as usize].wrapping_sub(self.registers[vy as usize]);
 
         let underflow_occurred = self.registers[vx as usize]
@@ -94,13 +246,18 @@ impl Chip8 {
         self.registers[0xF] = underflow_occurred as u8;
     }
 
-    pub fn instruction_right_shift(&mut self, vx: u8) {
+    pub(crate) fn instruction_right_shift(&mut self, vx: u8, vy: u8) {
+        if self.quirks.shift_uses_vy {
+            self.registers[vx as usize] = self.registers[vy as usize];
+        }
+
         let least_significant = self.registers[vx as usize] & 0b0000_0001;
-        self.registers[0xF] = least_significant;
         self.registers[vx as usize] >>= 1;
+        // VF is set last in case VX is VF.
+        self.registers[0xF] = least_significant;
     }
 
-    pub fn instruction_set_vx_to_vy_minus_vx(&mut self, vx: u8, vy: u8) {
+    pub(crate) fn instruction_set_vx_to_vy_minus_vx(&mut self, vx: u8, vy: u8) {
         let wrapped_sum = self.registers[vy as usize].wrapping_sub(self.registers[vx as usize]);
 
         let underflow_occured = self.registers[vy as usize]
@@ -111,168 +268,1695 @@ impl Chip8 {
         self.registers[0xF] = underflow_occured as u8;
     }
 
-    pub fn instruction_left_shift(&mut self, vx: u8) {
-        let most_significant = self.registers[vx as usize] & 0b1000_0000;
-        self.registers[0xF] = most_significant;
+    pub(crate) fn instruction_left_shift(&mut self, vx: u8, vy: u8) {
+        if self.quirks.shift_uses_vy {
+            self.registers[vx as usize] = self.registers[vy as usize];
+        }
+
+        let most_significant = self.registers[vx as usize] >> 7;
         self.registers[vx as usize] <<= 1;
+        // VF is set last in case VX is VF.
+        self.registers[0xF] = most_significant;
     }
 
-    pub fn instruction_skip_if_register_vx_not_equals_vy(&mut self, vx: u8, vy: u8) {
+    pub(crate) fn instruction_skip_if_register_vx_not_equals_vy(&mut self, vx: u8, vy: u8) {
         if self.registers[vx as usize] != self.registers[vy as usize] {
-            self.program_counter += 2;
+            self.skip_next_instruction();
         }
     }
 
-    pub fn instruction_set_index_register(&mut self, nnn: u16) {
+    pub(crate) fn instruction_set_index_register(&mut self, nnn: u16) {
         self.index_register = nnn;
     }
-    pub fn instruction_jump_with_pc_offset(&mut self, nnn: u16) {
-        self.program_counter = self.registers[0x0 as usize] as u16 + nnn;
+    pub(crate) fn instruction_jump_with_pc_offset(&mut self, nnn: u16) {
+        // In the SUPER-CHIP version X is the highest nibble of NNN.
+        let vx = if self.quirks.jump_uses_vx {
+            (nnn >> 8) as usize
+        } else {
+            0x0
+        };
+        self.program_counter = self.registers[vx] as u16 + nnn;
     }
-    pub fn instruction_random(&mut self, vx: u8, nn: u8) {
-        self.registers[vx as usize] = rand::Rng::gen_range(&mut rand::thread_rng(), 0..=255) & nn
+    pub(crate) fn instruction_random(&mut self, vx: u8, nn: u8) {
+        self.registers[vx as usize] = rand::Rng::gen_range(&mut self.rng.0, 0..=255) & nn
     }
 
-    pub fn instruction_draw(&mut self, vx: u8, vy: u8, n: u8) {
+    pub(crate) fn instruction_draw(&mut self, vx: u8, vy: u8, n: u8) -> Result<(), Chip8Error> {
         // Initialize VF
         self.registers[0xF] = 0;
 
-        let mut x = self.registers[vx as usize] % WIDTH as u8;
-        let mut y = self.registers[vy as usize] % HEIGHT as u8;
+        // The starting coordinate wraps around, but the rest of the sprite
+        // gets clipped at the edges (unless the wrap quirk is on).
+        let width = self.screen.width() as u8;
+        let height = self.screen.height() as u8;
+        let x = self.registers[vx as usize] % width;
+        let y = self.registers[vy as usize] % height;
 
-        for row in 0..n {
-            let sprite_byte = self
-                .memory
-                .byte(self.index_register as usize + row as usize);
-
-            // We iterate through the bits in the byte from left to right,
-            // where each corresponds with an x value.
-            for shift in (0..=7).rev() {
-                let needs_invert = ((sprite_byte >> shift) & 0b0000_0001) == 1;
-
-                // If we have a bit at this position, flip
-                // the corresponding pixel. If we turned this
-                // pixel off (and it used to be on), then
-                // set VF to 1.
-                if needs_invert {
-                    let new_state = self.screen.invert(x, y);
-
-                    if !new_state {
-                        self.registers[0xF] = 1;
-                    }
-                }
+        // SUPER-CHIP draws DXY0 as a 16x16 sprite, two bytes per row.
+        let (sprite_width, rows) = if n == 0 && self.quirks.large_sprites {
+            (16, 16)
+        } else {
+            (8, n)
+        };
+
+        // XO-CHIP draws to every selected plane, each with its own sprite
+        // data one after another in memory. Check all of it before drawing
+        // anything, so a sprite that runs off the end isn't half drawn.
+        let sprite_size = rows as usize * (sprite_width as usize / 8);
+        let planes = (self.screen.planes() & 0b11).count_ones() as usize;
+        let mut address = self.index_register as usize;
+        self.memory.check_range(address, planes * sprite_size)?;
+        for plane in [0b01, 0b10] {
+            if self.screen.planes() & plane == 0 {
+                continue;
+            }
+
+            if self.draw_plane(x, y, sprite_width, rows, address, plane) {
+                self.registers[0xF] = 1;
+            }
+            address += sprite_size;
+        }
 
-                // Increment x
-                x += 1;
+        if self.quirks.display_wait {
+            self.waiting_for_vblank = true;
+        }
+        if self.quirks.draw_timing {
+            self.cycle_cost = vip_draw_cost(x, rows);
+        }
+        Ok(())
+    }
+
+    /// Draws one plane of a sprite starting at `address`. Returns true if
+    /// any pixel got turned off.
+    fn draw_plane(
+        &mut self,
+        x: u8,
+        y: u8,
+        sprite_width: u8,
+        rows: u8,
+        address: usize,
+        plane: u8,
+    ) -> bool {
+        let height = self.screen.height() as u8;
+        let mut collision = false;
 
-                // End early if we are at the end of the screen.
-                if x == WIDTH as u8 {
+        for row in 0..rows {
+            let mut pixel_y = y + row;
+            if pixel_y >= height {
+                if !self.quirks.wrap_sprites {
                     break;
                 }
+                pixel_y %= height;
             }
 
-            // Reset x to original value
-            x = self.registers[vx as usize] % WIDTH as u8;
-
-            // Increment y for every row
-            y += 1;
+            let sprite_row = if sprite_width == 16 {
+                self.memory.word(address + 2 * row as usize)
+            } else {
+                self.memory.byte(address + row as usize) as u16
+            };
 
-            // End early if we are at the bottom of the screen.
-            if y == HEIGHT as u8 {
-                break;
+            if self.screen.xor_row(
+                x,
+                pixel_y,
+                sprite_row,
+                sprite_width,
+                plane,
+                self.quirks.wrap_sprites,
+            ) == Some(true)
+            {
+                collision = true;
             }
         }
-        if let Some(frame_handle) = &self.frame_handle {
-            frame_handle
-                .send(Box::new(self.screen.get().clone()))
-                .inspect_err(|e| error!("Error sending frame {e}"))
-                .unwrap();
-        }
+
+        collision
     }
 
-    pub fn instruction_skip_if_key_pressed(&mut self, vx: u8) {
-        if let Some(keycode) = self.key_pressed {
-            if keycode == self.registers[vx as usize] {
-                self.program_counter += 2;
-            }
+    pub(crate) fn instruction_skip_if_key_pressed(&mut self, vx: u8) {
+        if self.keypad.is_pressed(self.registers[vx as usize]) {
+            self.skip_next_instruction();
         }
     }
 
-    pub fn instruction_skip_if_key_not_pressed(&mut self, vx: u8) {
-        if let Some(keycode) = self.key_pressed {
-            if keycode != self.registers[vx as usize] {
-                return;
-            }
+    pub(crate) fn instruction_skip_if_key_not_pressed(&mut self, vx: u8) {
+        if !self.keypad.is_pressed(self.registers[vx as usize]) {
+            self.skip_next_instruction();
         }
-
-        self.program_counter += 2;
     }
 
-    pub fn instruction_set_vx_to_delay_timer(&mut self, vx: u8) {
-        self.registers[vx as usize] = self.sound_timer.0
+    pub(crate) fn instruction_set_vx_to_delay_timer(&mut self, vx: u8) {
+        self.registers[vx as usize] = self.delay_timer.value();
     }
 
-    pub fn instruction_await_key_input(&mut self, vx: u8) {
-        if self.key_pressed.is_none() {
-            self.program_counter -= 2;
-            return;
+    /// Like the COSMAC VIP, the key only counts once it is released. Taking
+    /// it on the press instead makes a held key satisfy several `FX0A`s in a
+    /// row. Until then we keep running this instruction again, while the
+    /// timers keep counting down.
+    pub(crate) fn instruction_await_key_input(&mut self, vx: u8) {
+        match self.awaited_key {
+            Some(key) if !self.keypad.is_pressed(key) => {
+                self.registers[vx as usize] = key;
+                self.awaited_key = None;
+            }
+            Some(_) => self.program_counter = self.program_counter.wrapping_sub(2),
+            None => {
+                self.awaited_key = self.keypad.pressed().next();
+                self.program_counter = self.program_counter.wrapping_sub(2);
+            }
         }
+    }
 
-        self.registers[vx as usize] = self.key_pressed.unwrap();
+    pub(crate) fn instruction_set_delay_timer(&mut self, vx: u8) {
+        self.delay_timer.set(self.registers[vx as usize]);
     }
 
-    pub fn instruction_set_delay_timer(&mut self, vx: u8) {
-        self.delay_timer.0 = self.registers[vx as usize]
+    pub(crate) fn instruction_set_sound_timer(&mut self, vx: u8) {
+        self.sound_timer.set(self.registers[vx as usize]);
     }
 
-    pub fn instruction_set_sound_timer(&mut self, vx: u8) {
-        self.sound_timer.0 = self.registers[vx as usize]
+    pub(crate) fn instruction_add_to_index(&mut self, vx: u8) {
+        self.index_register = self
+            .index_register
+            .wrapping_add(self.registers[vx as usize] as u16);
+
+        // Most interpreters ignore overflow and don't touch VF.
+        if self.quirks.index_overflow_sets_vf {
+            self.registers[0xF] = (self.index_register > 0xFFF) as u8;
+        }
     }
 
-    pub fn instruction_add_to_index(&mut self, vx: u8) {
-        //Says to ignore overflow and not set the VF register
-        self.index_register += self.registers[vx as usize] as u16
+    pub(crate) fn instruction_set_index_to_font_character(&mut self, vx: u8) {
+        // Each character is 5 bytes, and only the lowest nibble counts.
+        let character = (self.registers[vx as usize] & 0xF) as usize;
+        self.index_register = (memory::FONT_SET_OFFSET + character * 5) as u16;
     }
 
-    pub fn instruction_set_index_to_font_character(&mut self, vx: u8) {
-        self.index_register = self.registers[vx as usize] as u16
+    pub(crate) fn instruction_set_index_to_large_font_character(&mut self, vx: u8) {
+        // Large characters are 10 bytes each.
+        let character = (self.registers[vx as usize] & 0xF) as usize;
+        self.index_register = (memory::LARGE_FONT_SET_OFFSET + character * 10) as u16;
     }
 
-    pub fn instruction_set_index_to_binary_coded_vx(&mut self, vx: u8) {
-        self.memory.set_byte(
+    pub(crate) fn instruction_set_index_to_binary_coded_vx(
+        &mut self,
+        vx: u8,
+    ) -> Result<(), Chip8Error> {
+        self.memory.check_range(self.index_register as usize, 3)?;
+
+        self.write_byte(
             { self.index_register } as usize,
             self.registers[vx as usize] / 100,
         );
-        self.memory.set_byte(
+        self.write_byte(
             { self.index_register + 1 } as usize,
             { self.registers[vx as usize] / 10 } % 10,
         );
-        self.memory.set_byte({ self.index_register + 2 } as usize, {
+        self.write_byte({ self.index_register + 2 } as usize, {
             self.registers[vx as usize] % 10
         });
+        Ok(())
     }
 
-    pub fn instruction_dump_registers(&mut self, vx: u8) {
+    /// Writes a byte for the program, keeping track of it for
+    /// [`Chip8::set_code_guard`].
+    fn write_byte(&mut self, address: usize, byte: u8) {
+        self.memory.set_byte(address, byte);
+        if let Some(code_writes) = &mut self.code_writes {
+            code_writes.insert(address as u16);
+        }
+    }
+
+    pub(crate) fn instruction_save_flags(&mut self, vx: u8) {
+        let count = vx.min(7) as usize + 1;
+        self.rpl_flags[..count].copy_from_slice(&self.registers[..count]);
+    }
+
+    pub(crate) fn instruction_load_flags(&mut self, vx: u8) {
+        let count = vx.min(7) as usize + 1;
+        self.registers[..count].copy_from_slice(&self.rpl_flags[..count]);
+    }
+
+    pub(crate) fn instruction_dump_registers(&mut self, vx: u8) -> Result<(), Chip8Error> {
+        self.memory
+            .check_range(self.index_register as usize, vx as usize + 1)?;
+
         for i in 0x0..=vx {
-            self.memory.set_byte(
+            self.write_byte(
                 { self.index_register + i as u16 } as usize,
                 self.registers[i as usize],
             );
         }
+
+        if self.quirks.load_store_increments_i {
+            self.index_register = self.index_register.wrapping_add(vx as u16 + 1);
+        }
+        Ok(())
     }
 
-    pub fn instruction_load_registers(&mut self, vx: u8) {
+    pub(crate) fn instruction_load_registers(&mut self, vx: u8) -> Result<(), Chip8Error> {
+        self.memory
+            .check_range(self.index_register as usize, vx as usize + 1)?;
+
         for i in 0x0..=vx {
             self.registers[i as usize] = self
                 .memory
                 .byte({ self.index_register + i as u16 } as usize)
         }
+
+        if self.quirks.load_store_increments_i {
+            self.index_register = self.index_register.wrapping_add(vx as u16 + 1);
+        }
+        Ok(())
     }
 
-    pub fn instruction_unknown(&mut self) {
-        unimplemented!()
+    /// Only runs with [`Chip8::set_lenient`] on, and does nothing but warn.
+    pub(crate) fn instruction_unknown(&mut self, raw: u16) {
+        if !self.warned_unknown {
+            let address = self.program_counter.wrapping_sub(2);
+            warn!("Skipping invalid opcode 0x{raw:04X} at 0x{address:03X}");
+            self.warned_unknown = true;
+        }
     }
 }
 
 #[cfg(test)]
-mod test_super {}
+mod test_super {
+    use std::time::Duration;
+
+    use crate::{
+        Chip8, Chip8Error, CodeWarning, CycleOutcome, DelayTimer, Instruction, KeyEvent, RunState,
+        SoundTimer, FONT_SET, STACK_DEPTH, TIMER_INTERVAL, WIDTH,
+    };
+
+    /// Creates a headless emulator with `program` loaded in.
+    fn load(program: &[u8]) -> Chip8 {
+        let mut chip_8 = Chip8::headless();
+        chip_8.initialize().unwrap();
+        chip_8.load_program(program.to_vec()).unwrap();
+        chip_8
+    }
+
+    fn run(chip_8: &mut Chip8, cycles: usize) {
+        for _ in 0..cycles {
+            chip_8.cycle().unwrap();
+        }
+    }
+
+    #[test]
+    fn draws_sprite() {
+        let mut chip_8 = load(&[
+            0x60, 0x00, // LD V0, 0x00
+            0xA2, 0x08, // LD I, 0x208
+            0xD0, 0x02, // DRW V0, V0, 2
+            0x12, 0x06, // JP 0x206
+            0xF0, 0x90, // Sprite data
+        ]);
+        run(&mut chip_8, 3);
+
+        assert_eq!(chip_8.screen()[0..8], [1, 1, 1, 1, 0, 0, 0, 0]);
+        let second_row = WIDTH as usize;
+        assert_eq!(
+            chip_8.screen()[second_row..second_row + 8],
+            [1, 0, 0, 1, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn sets_vf_only_when_this_draw_turns_a_pixel_off() {
+        let mut chip_8 = load(&[
+            0x6F, 0x05, // LD VF, 0x05
+            0xA2, 0x14, // LD I, 0x214
+            0xD0, 0x12, // DRW V0, V1, 2
+            0xA2, 0x16, // LD I, 0x216
+            0xD0, 0x12, // DRW V0, V1, 2
+            0x60, 0x10, // LD V0, 0x10
+            0xD0, 0x12, // DRW V0, V1, 2
+            0x12, 0x0E, // JP 0x20E
+            0x00, 0x00, 0x00, 0x00, //
+            0xF0, 0x90, // Sprite data
+            0x00, 0x80, // Only overlaps on the second row
+        ]);
+
+        // Into empty space, which clears whatever VF had before.
+        run(&mut chip_8, 3);
+        assert_eq!(chip_8.registers()[0xF], 0);
+
+        // Any row turning a pixel off counts, not just the first.
+        run(&mut chip_8, 2);
+        assert_eq!(chip_8.registers()[0xF], 1);
+        assert_eq!(chip_8.screen()[WIDTH as usize], 0);
+
+        // The next draw starts over, rather than keeping the last collision.
+        run(&mut chip_8, 2);
+        assert_eq!(chip_8.registers()[0xF], 0);
+    }
+
+    #[test]
+    fn skips_when_key_pressed() {
+        let mut chip_8 = load(&[
+            0x60, 0x05, // LD V0, 0x05
+            0xE0, 0x9E, // SKP V0
+            0x61, 0x01, // LD V1, 0x01
+            0x62, 0x01, // LD V2, 0x01
+        ]);
+        chip_8.set_key(0x5, true);
+        run(&mut chip_8, 3);
+
+        assert_eq!(chip_8.registers[0x1], 0);
+        assert_eq!(chip_8.registers[0x2], 1);
+    }
+
+    #[test]
+    fn call_pushes_return_address() {
+        let mut chip_8 = load(&[
+            0x22, 0x04, // CALL 0x204
+            0x00, 0x00, //
+            0x22, 0x08, // CALL 0x208
+        ]);
+        run(&mut chip_8, 2);
+
+        assert_eq!(chip_8.program_counter(), 0x208);
+        assert_eq!(chip_8.stack(), [0x202, 0x206]);
+    }
+
+    #[test]
+    fn pauses_before_breakpoint() {
+        let mut chip_8 = load(&[
+            0x60, 0x01, // LD V0, 0x01
+            0x61, 0x01, // LD V1, 0x01
+            0x62, 0x01, // LD V2, 0x01
+        ]);
+        chip_8.add_breakpoint(0x202);
+        run(&mut chip_8, 2);
+
+        assert_eq!(chip_8.run_state, RunState::Paused);
+        assert_eq!(chip_8.program_counter(), 0x202);
+        assert_eq!(chip_8.registers()[0x1], 0);
+
+        // Resuming runs the instruction at the breakpoint.
+        chip_8.run_state = RunState::Running;
+        run(&mut chip_8, 2);
+        assert_eq!(chip_8.registers()[0x1], 1);
+        assert_eq!(chip_8.registers()[0x2], 1);
+    }
+
+    #[test]
+    fn shift_quirk() {
+        let program = [
+            0x60, 0x01, // LD V0, 0x01
+            0x61, 0x81, // LD V1, 0x81
+            0x80, 0x16, // SHR V0, V1
+            0x82, 0x1E, // SHL V2, V1
+        ];
+
+        let mut chip_8 = load(&program);
+        run(&mut chip_8, 4);
+        assert_eq!(chip_8.registers()[0x0], 0x00);
+        assert_eq!(chip_8.registers()[0x2], 0x00);
+        assert_eq!(chip_8.registers()[0xF], 0);
+
+        let mut chip_8 = load(&program);
+        chip_8.quirks.shift_uses_vy = true;
+        run(&mut chip_8, 4);
+        assert_eq!(chip_8.registers()[0x0], 0x40);
+        assert_eq!(chip_8.registers()[0x2], 0x02);
+        assert_eq!(chip_8.registers()[0xF], 1);
+    }
+
+    #[test]
+    fn load_store_quirk() {
+        let program = [
+            0x60, 0x11, // LD V0, 0x11
+            0x61, 0x22, // LD V1, 0x22
+            0x62, 0x33, // LD V2, 0x33
+            0xA3, 0x00, // LD I, 0x300
+            0xF2, 0x55, // LD [I], V2
+            0xF1, 0x65, // LD V1, [I]
+        ];
+
+        let mut chip_8 = load(&program);
+        run(&mut chip_8, 6);
+        assert_eq!(&chip_8.memory()[0x300..0x303], &[0x11, 0x22, 0x33]);
+        assert_eq!(chip_8.index(), 0x300);
+        assert_eq!(&chip_8.registers()[..3], &[0x11, 0x22, 0x33]);
+
+        let mut chip_8 = load(&program);
+        chip_8.quirks.load_store_increments_i = true;
+        run(&mut chip_8, 6);
+        assert_eq!(&chip_8.memory()[0x300..0x303], &[0x11, 0x22, 0x33]);
+        assert_eq!(chip_8.index(), 0x305);
+        // The load started at 0x303, which is still zeroed.
+        assert_eq!(&chip_8.registers()[..3], &[0x00, 0x00, 0x33]);
+    }
+
+    #[test]
+    fn jump_with_offset_quirk() {
+        let program = [
+            0x60, 0x04, // LD V0, 0x04
+            0x63, 0x10, // LD V3, 0x10
+            0xB3, 0x00, // JP V0, 0x300
+        ];
+
+        let mut chip_8 = load(&program);
+        run(&mut chip_8, 3);
+        assert_eq!(chip_8.program_counter(), 0x304);
+
+        let mut chip_8 = load(&program);
+        chip_8.quirks.jump_uses_vx = true;
+        run(&mut chip_8, 3);
+        assert_eq!(chip_8.program_counter(), 0x310);
+    }
+
+    #[test]
+    fn index_overflow_quirk() {
+        let program = [
+            0x6F, 0x05, // LD VF, 0x05
+            0x60, 0x02, // LD V0, 0x02
+            0xAF, 0xFF, // LD I, 0xFFF
+            0xF0, 0x1E, // ADD I, V0
+        ];
+
+        let mut chip_8 = load(&program);
+        run(&mut chip_8, 4);
+        assert_eq!(chip_8.index(), 0x1001);
+        assert_eq!(chip_8.registers()[0xF], 0x05);
+
+        let mut chip_8 = load(&program);
+        chip_8.quirks.index_overflow_sets_vf = true;
+        run(&mut chip_8, 4);
+        assert_eq!(chip_8.index(), 0x1001);
+        assert_eq!(chip_8.registers()[0xF], 1);
+    }
+
+    #[test]
+    fn logic_quirk() {
+        for opcode in [0x01, 0x02, 0x03] {
+            let program = [
+                0x6F,
+                0x05, // LD VF, 0x05
+                0x60,
+                0x0C, // LD V0, 0x0C
+                0x61,
+                0x0A, // LD V1, 0x0A
+                0x80,
+                0x10 | opcode, // OR/AND/XOR V0, V1
+            ];
+
+            let mut chip_8 = load(&program);
+            run(&mut chip_8, 4);
+            assert_eq!(chip_8.registers()[0xF], 0x05);
+
+            let mut chip_8 = load(&program);
+            chip_8.quirks.logic_resets_vf = true;
+            run(&mut chip_8, 4);
+            assert_eq!(chip_8.registers()[0xF], 0);
+        }
+    }
+
+    #[test]
+    fn display_wait_quirk() {
+        let program = [
+            0xD0, 0x01, // DRW V0, V0, 1
+            0x60, 0x01, // LD V0, 0x01
+        ];
+
+        let mut chip_8 = load(&program);
+        chip_8.quirks.display_wait = true;
+        run(&mut chip_8, 4);
+        assert_eq!(chip_8.program_counter(), 0x202);
+
+        chip_8.update_timers(crate::TIMER_INTERVAL);
+        run(&mut chip_8, 1);
+        assert_eq!(chip_8.program_counter(), 0x204);
+    }
+
+    #[test]
+    fn clips_or_wraps_sprites_at_edge() {
+        let program = [
+            0x60, 0x3C, // LD V0, 0x3C
+            0x61, 0x00, // LD V1, 0x00
+            0xA2, 0x0A, // LD I, 0x20A
+            0xD0, 0x11, // DRW V0, V1, 1
+            0x12, 0x08, // JP 0x208
+            0xFF, // sprite
+        ];
+        let row = |chip_8: &Chip8| chip_8.screen()[..WIDTH as usize].to_vec();
+
+        let mut chip_8 = load(&program);
+        run(&mut chip_8, 4);
+        let pixels = row(&chip_8);
+        assert!(pixels[60..64].iter().all(|&pixel| pixel == 1));
+        assert!(pixels[..60].iter().all(|&pixel| pixel == 0));
+
+        let mut chip_8 = load(&program);
+        chip_8.quirks.wrap_sprites = true;
+        run(&mut chip_8, 4);
+        let pixels = row(&chip_8);
+        assert!(pixels[60..64].iter().all(|&pixel| pixel == 1));
+        assert!(pixels[..4].iter().all(|&pixel| pixel == 1));
+        assert!(pixels[4..60].iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn wraps_the_start_of_sprites_past_the_edge() {
+        let program = |x: u8, y: u8| {
+            [
+                0x60, x, // LD V0, x
+                0x61, y, // LD V1, y
+                0xA2, 0x0A, // LD I, 0x20A
+                0xD0, 0x12, // DRW V0, V1, 2
+                0x12, 0x08, // JP 0x208
+                0xC0, 0xC0, // sprite
+            ]
+        };
+        let lit = |chip_8: &Chip8| -> Vec<(usize, usize)> {
+            let width = chip_8.screen_size().0 as usize;
+            let screen = chip_8.screen();
+            (0..screen.len())
+                .filter(|&i| screen[i] == 1)
+                .map(|i| (i % width, i / width))
+                .collect()
+        };
+
+        // (68, 37) starts at (4, 5), even with clipping on.
+        let mut chip_8 = load(&program(68, 37));
+        run(&mut chip_8, 4);
+        assert_eq!(lit(&chip_8), [(4, 5), (5, 5), (4, 6), (5, 6)]);
+
+        // (255, 255) starts at (63, 31), and the rest gets clipped.
+        let mut chip_8 = load(&program(0xFF, 0xFF));
+        run(&mut chip_8, 4);
+        assert_eq!(lit(&chip_8), [(63, 31)]);
+
+        // Or wrapped around, with the quirk.
+        let mut chip_8 = load(&program(0xFF, 0xFF));
+        chip_8.quirks.wrap_sprites = true;
+        run(&mut chip_8, 4);
+        assert_eq!(lit(&chip_8), [(0, 0), (63, 0), (0, 31), (63, 31)]);
+    }
+
+    #[test]
+    fn overflows_stack() {
+        // CALL 0x200, forever.
+        let mut chip_8 = load(&[0x22, 0x00]);
+        run(&mut chip_8, STACK_DEPTH as usize);
+        assert_eq!(chip_8.stack().len(), STACK_DEPTH as usize);
+
+        assert!(matches!(chip_8.cycle(), Err(Chip8Error::StackOverflow)));
+    }
+
+    #[test]
+    fn underflows_stack() {
+        let mut chip_8 = load(&[
+            0x00, 0xEE, // RET
+        ]);
+
+        assert!(matches!(chip_8.cycle(), Err(Chip8Error::StackUnderflow)));
+    }
+
+    #[test]
+    fn dump_past_end_of_memory() {
+        let mut chip_8 = load(&[
+            0xAF, 0xFE, // LD I, 0xFFE
+            0xF3, 0x55, // LD [I], V3
+        ]);
+        run(&mut chip_8, 1);
+
+        assert!(matches!(
+            chip_8.cycle(),
+            Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000 })
+        ));
+    }
+
+    #[test]
+    fn seeded_random_is_reproducible() {
+        let program = [
+            0xC0, 0xFF, // RND V0, 0xFF
+            0xC1, 0xFF, // RND V1, 0xFF
+            0xC2, 0xFF, // RND V2, 0xFF
+            0xC3, 0xFF, // RND V3, 0xFF
+        ];
+        let run_seeded = |seed| {
+            let mut chip_8 = load(&program);
+            chip_8.set_seed(seed);
+            run(&mut chip_8, 4);
+            chip_8.registers()[..4].to_vec()
+        };
+
+        assert_eq!(run_seeded(1234), run_seeded(1234));
+        assert_ne!(run_seeded(1234), run_seeded(4321));
+    }
+
+    #[test]
+    fn switches_resolution() {
+        let mut chip_8 = load(&[
+            0x00, 0xFF, // HIGH
+            0x60, 0x64, // LD V0, 0x64
+            0xA2, 0x0A, // LD I, 0x20A
+            0xD0, 0x01, // DRW V0, V0, 1
+            0x00, 0xFE, // LOW
+            0x80, // sprite
+        ]);
+
+        run(&mut chip_8, 4);
+        assert_eq!(chip_8.screen_size(), (128, 64));
+        // x = 100 fits on the wider screen, y = 100 wraps to 36.
+        assert_eq!(chip_8.screen()[36 * 128 + 100], 1);
+        assert_eq!(
+            chip_8.screen().iter().filter(|&&pixel| pixel == 1).count(),
+            1
+        );
+
+        run(&mut chip_8, 1);
+        assert_eq!(chip_8.screen_size(), (WIDTH, 32));
+        assert!(chip_8.screen().iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn scrolls_screen() {
+        let lit = |chip_8: &Chip8| {
+            let (width, _) = chip_8.screen_size();
+            chip_8
+                .screen()
+                .iter()
+                .enumerate()
+                .filter(|(_, &pixel)| pixel == 1)
+                .map(|(i, _)| (i as u32 % width, i as u32 / width))
+                .collect::<Vec<_>>()
+        };
+        let program = |mode| {
+            [
+                0x00, mode, // HIGH or LOW
+                0xA2, 0x0C, // LD I, 0x20C
+                0xD0, 0x01, // DRW V0, V0, 1
+                0x00, 0xFB, // SCR
+                0x00, 0xC3, // SCD 3
+                0x00, 0xFC, // SCL
+                0xC0, // sprite
+            ]
+        };
+
+        let mut chip_8 = load(&program(0xFF));
+        run(&mut chip_8, 4);
+        assert_eq!(lit(&chip_8), [(4, 0), (5, 0)]);
+        run(&mut chip_8, 1);
+        assert_eq!(lit(&chip_8), [(4, 3), (5, 3)]);
+        run(&mut chip_8, 1);
+        assert_eq!(lit(&chip_8), [(0, 3), (1, 3)]);
+
+        // Low resolution scrolls half as far.
+        let mut chip_8 = load(&program(0xFE));
+        run(&mut chip_8, 4);
+        assert_eq!(lit(&chip_8), [(2, 0), (3, 0)]);
+        run(&mut chip_8, 1);
+        assert_eq!(lit(&chip_8), [(2, 1), (3, 1)]);
+        run(&mut chip_8, 1);
+        assert_eq!(lit(&chip_8), [(0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn draws_large_sprite() {
+        // A 16x16 outline of a square.
+        let mut sprite = vec![0xFF, 0xFF];
+        for _ in 0..14 {
+            sprite.extend([0x80, 0x01]);
+        }
+        sprite.extend([0xFF, 0xFF]);
+
+        let mut program = vec![
+            0x00, 0xFF, // HIGH
+            0xA2, 0x08, // LD I, 0x208
+            0xD0, 0x00, // DRW V0, V0, 0
+            0xD0, 0x00, // DRW V0, V0, 0
+        ];
+        program.extend(&sprite);
+
+        let mut chip_8 = load(&program);
+        chip_8.quirks.large_sprites = true;
+        run(&mut chip_8, 3);
+
+        let screen = chip_8.screen();
+        for y in 0..16 {
+            for x in 0..16 {
+                let edge = x == 0 || x == 15 || y == 0 || y == 15;
+                assert_eq!(screen[y * 128 + x], edge as u8, "pixel ({x}, {y})");
+            }
+        }
+        assert_eq!(screen.iter().filter(|&&pixel| pixel == 1).count(), 60);
+        assert_eq!(chip_8.registers()[0xF], 0);
+
+        // Drawing it again erases it and collides.
+        run(&mut chip_8, 1);
+        assert!(chip_8.screen().iter().all(|&pixel| pixel == 0));
+        assert_eq!(chip_8.registers()[0xF], 1);
+    }
+
+    #[test]
+    fn points_index_at_font_characters() {
+        let mut chip_8 = load(&[
+            0x60, 0x0A, // LD V0, 0x0A
+            0xF0, 0x29, // LD F, V0
+            0xF0, 0x30, // LD HF, V0
+        ]);
+
+        run(&mut chip_8, 2);
+        assert_eq!(chip_8.index(), 0x050 + 0xA * 5);
+        assert_eq!(
+            &chip_8.memory()[chip_8.index() as usize..][..5],
+            &[0xF0, 0x90, 0xF0, 0x90, 0x90]
+        );
+
+        run(&mut chip_8, 1);
+        assert_eq!(chip_8.index(), 0x0A0 + 0xA * 10);
+        assert_eq!(chip_8.memory()[chip_8.index() as usize], 0x3C);
+    }
+
+    #[test]
+    fn flags_survive_reset() {
+        let program = [
+            0x60, 0x11, // LD V0, 0x11
+            0x61, 0x22, // LD V1, 0x22
+            0x62, 0x33, // LD V2, 0x33
+            0xF2, 0x75, // LD R, V2
+        ];
+        let mut chip_8 = load(&program);
+        run(&mut chip_8, 4);
+
+        chip_8.initialize().unwrap();
+        chip_8
+            .load_program(vec![
+                0xF1, 0x85, // LD V1, R
+            ])
+            .unwrap();
+        assert_eq!(&chip_8.registers()[..3], &[0, 0, 0]);
+
+        run(&mut chip_8, 1);
+        assert_eq!(&chip_8.registers()[..3], &[0x11, 0x22, 0]);
+    }
+
+    #[test]
+    fn draws_to_selected_planes() {
+        let mut chip_8 = load(&[
+            0xA2, 0x0E, // LD I, 0x20E
+            0xD0, 0x01, // DRW V0, V0, 1
+            0xF2, 0x01, // PLANE 2
+            0xD0, 0x01, // DRW V0, V0, 1
+            0xF3, 0x01, // PLANE 3
+            0xD0, 0x01, // DRW V0, V0, 1
+            0x00, 0xE0, // CLS
+            0xF0, 0x0F, // sprites for planes 1 and 2
+        ]);
+
+        run(&mut chip_8, 4);
+        assert_eq!(&chip_8.screen()[..8], &[3, 3, 3, 3, 0, 0, 0, 0]);
+
+        // Both planes are drawn from consecutive sprites, and turning off
+        // pixels in either plane collides.
+        run(&mut chip_8, 2);
+        assert_eq!(&chip_8.screen()[..8], &[2; 8]);
+        assert_eq!(chip_8.registers()[0xF], 1);
+
+        // Clearing only touches the selected planes.
+        chip_8.screen.select_planes(0b01);
+        run(&mut chip_8, 1);
+        assert_eq!(&chip_8.screen()[..8], &[2; 8]);
+    }
+
+    #[test]
+    fn only_sends_changed_frames() {
+        let (frame_sender, frame_receiver) = std::sync::mpsc::channel();
+        let (_input_sender, input_receiver) = std::sync::mpsc::channel();
+        let mut chip_8 = Chip8::new(frame_sender, input_receiver);
+        chip_8.initialize().unwrap();
+        // Draw the 0 character once, then spin forever.
+        chip_8
+            .load_program(vec![0xF0, 0x29, 0xD0, 0x05, 0x12, 0x04])
+            .unwrap();
+        assert_eq!(frame_receiver.try_iter().count(), 1);
+
+        run(&mut chip_8, 10);
+        let frames: Vec<_> = frame_receiver.try_iter().collect();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(&frames[0][..8], &[1, 1, 1, 1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn points_at_the_loaded_font() {
+        let mut chip_8 = Chip8::headless();
+        assert!(matches!(
+            chip_8.load_font(&FONT_SET),
+            Err(Chip8Error::InterpreterMemoryIsUninitialized)
+        ));
+
+        // A font where every digit is a solid block.
+        let font = [0xF0; 80];
+        chip_8.initialize().unwrap();
+        chip_8.load_font(&font).unwrap();
+        chip_8
+            .load_program(vec![
+                0x60, 0x0A, // LD V0, 0x0A
+                0xF0, 0x29, // LD F, V0
+            ])
+            .unwrap();
+        run(&mut chip_8, 2);
+        let index = chip_8.index() as usize;
+        assert_eq!(chip_8.memory()[index..index + 5], [0xF0; 5]);
+
+        // Resetting keeps the font.
+        chip_8.reset().unwrap();
+        assert_eq!(chip_8.memory()[index..index + 5], [0xF0; 5]);
+    }
+
+    #[test]
+    fn fails_to_draw_sprites_past_the_end_of_memory() {
+        let mut chip_8 = load(&[
+            0xAF, 0xFF, // LD I, 0xFFF
+            0xD0, 0x1F, // DRW V0, V1, 15
+        ]);
+        run(&mut chip_8, 1);
+        assert!(matches!(
+            chip_8.cycle(),
+            Err(Chip8Error::MemoryOutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn checks_every_plane_fits_in_memory_before_drawing() {
+        let mut chip_8 = Chip8::headless();
+        chip_8.quirks.extended_memory = true;
+        chip_8.initialize().unwrap();
+        chip_8
+            .load_program(vec![
+                0xF0, 0x00, 0xFF, 0xF0, // LD I, 0xFFF0
+                0xF3, 0x01, // PLANE 3
+                0xD0, 0x1F, // DRW V0, V1, 15
+            ])
+            .unwrap();
+
+        // The first plane's 15 rows fit, the second plane's don't.
+        run(&mut chip_8, 2);
+        assert!(matches!(
+            chip_8.cycle(),
+            Err(Chip8Error::MemoryOutOfBounds { .. })
+        ));
+        assert!(chip_8.screen().iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn keeps_running_after_the_frontend_drops_its_frames() {
+        let (frame_sender, frame_receiver) = std::sync::mpsc::channel();
+        let (_input_sender, input_receiver) = std::sync::mpsc::channel();
+        let mut chip_8 = Chip8::new(frame_sender, input_receiver);
+        chip_8.initialize().unwrap();
+        chip_8
+            .load_program(vec![
+                0xD0, 0x05, // DRW V0, V0, 5
+                0x00, 0xE0, // CLS
+            ])
+            .unwrap();
+        drop(frame_receiver);
+        run(&mut chip_8, 2);
+    }
+
+    #[test]
+    fn sends_a_blank_frame_after_clearing() {
+        let (frame_sender, frame_receiver) = std::sync::mpsc::channel();
+        let (_input_sender, input_receiver) = std::sync::mpsc::channel();
+        let mut chip_8 = Chip8::new(frame_sender, input_receiver);
+        chip_8.initialize().unwrap();
+        chip_8
+            .load_program(vec![
+                0xF0, 0x29, // LD F, V0
+                0xD0, 0x05, // DRW V0, V0, 5
+                0x6F, 0x07, // LD VF, 0x07
+                0x00, 0xE0, // CLS
+                0x12, 0x08, // JP 0x208
+            ])
+            .unwrap();
+        run(&mut chip_8, 3);
+        let index = chip_8.index();
+        frame_receiver.try_iter().for_each(drop);
+
+        run(&mut chip_8, 3);
+        let frames: Vec<_> = frame_receiver.try_iter().collect();
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].iter().all(|&pixel| pixel == 0));
+
+        // Nothing but the screen changes.
+        assert_eq!(chip_8.registers()[0xF], 0x07);
+        assert_eq!(chip_8.index(), index);
+    }
+
+    #[test]
+    fn waits_for_key_release() {
+        let mut chip_8 = load(&[
+            0x60, 0x05, // LD V0, 0x05
+            0xF0, 0x15, // LD DT, V0
+            0xF3, 0x0A, // LD V3, K
+        ]);
+        run(&mut chip_8, 3);
+        assert_eq!(chip_8.program_counter(), 0x204);
+
+        // Holding the key down doesn't count yet, no matter how long.
+        chip_8.set_key(0x7, true);
+        run(&mut chip_8, 3);
+        assert_eq!(chip_8.program_counter(), 0x204);
+        assert_eq!(chip_8.registers()[3], 0);
+
+        // The timers don't stop while we wait.
+        chip_8.update_timers(crate::TIMER_INTERVAL);
+        assert_eq!(chip_8.delay_timer.0, 4);
+
+        chip_8.set_key(0x7, false);
+        run(&mut chip_8, 1);
+        assert_eq!(chip_8.program_counter(), 0x206);
+        assert_eq!(chip_8.registers()[3], 0x7);
+    }
+
+    #[test]
+    fn reports_cycle_outcomes() {
+        let mut chip_8 = load(&[
+            0x00, 0xE0, // CLS
+            0xF0, 0x29, // LD F, V0
+            0xD0, 0x05, // DRW V0, V0, 5
+            0xD0, 0x05, // DRW V0, V0, 5
+            0x61, 0x05, // LD V1, 0x05
+            0xF1, 0x18, // LD ST, V1
+            0xF1, 0x18, // LD ST, V1
+            0xF2, 0x0A, // LD V2, K
+        ]);
+        chip_8.add_breakpoint(0x202);
+
+        let outcomes: Vec<_> = (0..9).map(|_| chip_8.cycle().unwrap()).collect();
+        assert_eq!(
+            outcomes,
+            [
+                CycleOutcome::ScreenCleared,
+                CycleOutcome::Breakpoint(0x202),
+                CycleOutcome::Executed,
+                CycleOutcome::Drew { collision: false },
+                CycleOutcome::Drew { collision: true },
+                CycleOutcome::Executed,
+                CycleOutcome::SoundStarted,
+                CycleOutcome::Executed,
+                CycleOutcome::WaitingForKey,
+            ]
+        );
+    }
+
+    #[test]
+    fn loads_program_from_reader() {
+        let mut chip_8 = Chip8::headless();
+        chip_8.initialize().unwrap();
+        chip_8.load_program_from_reader(&[0x6A, 0x2F][..]).unwrap();
+        run(&mut chip_8, 1);
+        assert_eq!(chip_8.registers()[0xA], 0x2F);
+
+        // One byte too many to fit between 0x200 and the end of memory.
+        let too_big = vec![0; 0xE01];
+        assert!(matches!(
+            chip_8.load_program_from_reader(&too_big[..]),
+            Err(Chip8Error::RomTooLarge {
+                size: 0xE01,
+                max: 0xE00
+            })
+        ));
+        assert_eq!(chip_8.program().as_ref(), &[0x6A, 0x2F]);
+    }
+
+    #[test]
+    fn pauses_on_watches() {
+        let mut chip_8 = load(&[
+            0x61, 0x05, // LD V1, 0x05
+            0x62, 0x07, // LD V2, 0x07
+            0x62, 0x07, // LD V2, 0x07
+            0xA3, 0x00, // LD I, 0x300
+            0xF2, 0x55, // LD [I], V2
+        ]);
+        chip_8.add_register_watch(0x2);
+        chip_8.add_memory_watch(0x301).unwrap();
+        assert!(chip_8.add_memory_watch(0x1000).is_err());
+
+        run(&mut chip_8, 1);
+        assert_eq!(
+            chip_8.cycle().unwrap(),
+            CycleOutcome::RegisterChanged {
+                vx: 0x2,
+                old: 0,
+                new: 0x07
+            }
+        );
+        assert_eq!(chip_8.run_state, RunState::Paused);
+
+        // Setting a register to the value it already has isn't a change.
+        chip_8.run_state = RunState::Running;
+        assert_eq!(chip_8.cycle().unwrap(), CycleOutcome::Executed);
+
+        run(&mut chip_8, 1);
+        assert_eq!(
+            chip_8.cycle().unwrap(),
+            CycleOutcome::MemoryChanged {
+                address: 0x301,
+                old: 0,
+                new: 0x05
+            }
+        );
+    }
+
+    #[test]
+    fn renders_rgba() {
+        const FG: [u8; 4] = [0x33, 0xFF, 0x33, 0xFF];
+        const BG: [u8; 4] = [0, 0, 0, 0xFF];
+
+        // The top row of the 0 character is 0xF0.
+        let mut chip_8 = load(&[
+            0xF0, 0x29, // LD F, V0
+            0xD0, 0x01, // DRW V0, V0, 1
+        ]);
+        run(&mut chip_8, 2);
+
+        let mut out = vec![0; WIDTH as usize * 32 * 4];
+        chip_8.render_rgba(FG, BG, &mut out).unwrap();
+        assert_eq!(&out[..4 * 4], FG.repeat(4));
+        assert_eq!(&out[4 * 4..8 * 4], BG.repeat(4));
+
+        assert!(matches!(
+            chip_8.render_rgba(FG, BG, &mut out[4..]),
+            Err(Chip8Error::InvalidBufferSize {
+                expected: 8192,
+                actual: 8188
+            })
+        ));
+    }
+
+    #[test]
+    fn reset_keeps_program() {
+        let mut chip_8 = Chip8::headless();
+        assert!(matches!(chip_8.reset(), Err(Chip8Error::ProgramNotLoaded)));
+
+        let mut chip_8 = load(&[
+            0x6A, 0x2F, // LD VA, 0x2F
+            0x22, 0x06, // CALL 0x206
+            0x00, 0x00, // (never reached)
+            0x12, 0x06, // JP 0x206
+        ]);
+        run(&mut chip_8, 3);
+        assert_eq!(chip_8.registers()[0xA], 0x2F);
+
+        chip_8.reset().unwrap();
+        assert_eq!(chip_8.registers()[0xA], 0);
+        assert_eq!(chip_8.program_counter(), 0x200);
+
+        run(&mut chip_8, 1);
+        assert_eq!(chip_8.registers()[0xA], 0x2F);
+    }
+
+    #[test]
+    fn loads_at_other_addresses() {
+        let mut chip_8 = Chip8::headless();
+        chip_8.initialize().unwrap();
+        assert!(matches!(
+            chip_8.load_program_at(vec![0x00, 0xE0], 0x1FE),
+            Err(Chip8Error::InvalidLoadAddress { addr: 0x1FE })
+        ));
+        assert!(matches!(
+            chip_8.load_program_at(vec![0; 3], 0xFFE),
+            Err(Chip8Error::RomTooLarge { size: 3, max: 2 })
+        ));
+
+        chip_8
+            .load_program_at(
+                vec![
+                    0x6A, 0x2F, // LD VA, 0x2F
+                ],
+                0x600,
+            )
+            .unwrap();
+        assert_eq!(chip_8.program_counter(), 0x600);
+        run(&mut chip_8, 1);
+        assert_eq!(chip_8.registers()[0xA], 0x2F);
+
+        chip_8.reset().unwrap();
+        assert_eq!(chip_8.program_counter(), 0x600);
+    }
+
+    #[test]
+    fn reports_invalid_opcode_address() {
+        let mut chip_8 = load(&[
+            0x00, 0xE0, // CLS
+            0x80, 0x08, // invalid
+        ]);
+        run(&mut chip_8, 1);
+
+        let error = chip_8.cycle().unwrap_err();
+        assert!(matches!(
+            error,
+            Chip8Error::InvalidInstruction {
+                instruction: 0x8008,
+                address: Some(0x202)
+            }
+        ));
+        assert_eq!(error.to_string(), "Invalid opcode 0x8008 at 0x202");
+    }
+
+    #[test]
+    fn guards_against_running_data() {
+        let program = [
+            0x60, 0x60, // LD V0, 0x60
+            0x61, 0x05, // LD V1, 0x05
+            0xA3, 0x00, // LD I, 0x300
+            0xF1, 0x55, // LD [I], V1
+            0x13, 0x00, // JP 0x300
+        ];
+        let mut chip_8 = load(&program);
+        run(&mut chip_8, 5);
+        assert_eq!(chip_8.cycle().unwrap(), CycleOutcome::Executed);
+
+        let mut chip_8 = load(&program);
+        chip_8.set_code_guard(true);
+        run(&mut chip_8, 5);
+        assert_eq!(
+            chip_8.cycle().unwrap(),
+            CycleOutcome::CodeWarning {
+                address: 0x300,
+                warning: CodeWarning::ModifiedCode
+            }
+        );
+
+        // The same thing, but below 0x200.
+        let mut program = program;
+        program[4..6].copy_from_slice(&[0xA1, 0x00]);
+        program[8..10].copy_from_slice(&[0x11, 0x00]);
+        let mut chip_8 = load(&program);
+        chip_8.set_code_guard(true);
+        run(&mut chip_8, 5);
+        assert_eq!(
+            chip_8.cycle().unwrap(),
+            CycleOutcome::CodeWarning {
+                address: 0x100,
+                warning: CodeWarning::ReservedMemory
+            }
+        );
+
+        // There's no byte after the last address to check.
+        let mut chip_8 = load(&program);
+        chip_8.set_code_guard(true);
+        assert_eq!(chip_8.check_code(0xFFFF), None);
+    }
+
+    #[test]
+    fn times_draws_like_the_vip() {
+        let program = [
+            0x60, 0x03, // LD V0, 3
+            0xD1, 0x15, // DRW V1, V1, 5
+            0xD0, 0x05, // DRW V0, V0, 5
+        ];
+        let mut chip_8 = load(&program);
+        let costs: Vec<_> = (0..3)
+            .map(|_| {
+                chip_8.cycle().unwrap();
+                chip_8.cycle_cost()
+            })
+            .collect();
+        assert_eq!(costs, [1, 1, 1]);
+
+        let mut chip_8 = load(&program);
+        chip_8.quirks.draw_timing = true;
+        let costs: Vec<_> = (0..3)
+            .map(|_| {
+                chip_8.cycle().unwrap();
+                chip_8.cycle_cost()
+            })
+            .collect();
+        assert_eq!(costs, [1, 7, 11]);
+    }
+
+    #[test]
+    fn freezes_timers_while_paused() {
+        let mut chip_8 = load(&[
+            0x60, 0x20, // LD V0, 0x20
+            0xF0, 0x15, // LD DT, V0
+            0xF0, 0x18, // LD ST, V0
+        ]);
+        run(&mut chip_8, 3);
+
+        chip_8.run_state = RunState::Paused;
+        chip_8.update_timers(Duration::from_secs(1));
+        assert_eq!((chip_8.delay_timer.0, chip_8.sound_timer.0), (0x20, 0x20));
+
+        chip_8.run_state = RunState::Running;
+        chip_8.update_timers(Duration::from_millis(101));
+        assert_eq!((chip_8.delay_timer.0, chip_8.sound_timer.0), (0x1A, 0x1A));
+    }
+
+    #[test]
+    fn returns_errors_sent_by_the_frontend() {
+        let (frame_sender, _frame_receiver) = std::sync::mpsc::channel();
+        let (input_sender, input_receiver) = std::sync::mpsc::channel();
+        let mut chip_8 = Chip8::new(frame_sender, input_receiver);
+        chip_8.initialize().unwrap();
+        chip_8.load_program(vec![0x12, 0x00]).unwrap();
+
+        input_sender.send(Err(Chip8Error::StackOverflow)).unwrap();
+        assert!(matches!(chip_8.cycle(), Err(Chip8Error::StackOverflow)));
+
+        // Hanging up just leaves the keys alone.
+        input_sender.send(Ok(KeyEvent::Pressed(0x5))).unwrap();
+        drop(input_sender);
+        run(&mut chip_8, 2);
+        assert!(chip_8.keypad.is_pressed(0x5));
+    }
+
+    #[test]
+    fn extends_memory() {
+        let program = [
+            0xAF, 0xFF, // LD I, 0xFFF
+            0x60, 0x02, // LD V0, 2
+            0xF0, 0x1E, // ADD I, V0
+            0xF0, 0x55, // LD [I], V0
+        ];
+        let mut chip_8 = load(&program);
+        run(&mut chip_8, 3);
+        assert!(matches!(
+            chip_8.cycle(),
+            Err(Chip8Error::MemoryOutOfBounds { addr: 0x1001 })
+        ));
+
+        let mut chip_8 = Chip8::headless();
+        chip_8.quirks.extended_memory = true;
+        chip_8.initialize().unwrap();
+        chip_8.load_program(program.to_vec()).unwrap();
+        assert_eq!(chip_8.memory().len(), 0x10000);
+        run(&mut chip_8, 4);
+        assert_eq!(chip_8.memory()[0x1001], 2);
+
+        // Save states keep the bigger memory.
+        let state = chip_8.save_state();
+        let mut restored = load(&[]);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.memory(), chip_8.memory());
+    }
+
+    #[test]
+    fn wraps_i_after_storing_at_the_top_of_extended_memory() {
+        let mut chip_8 = Chip8::headless();
+        chip_8.quirks.extended_memory = true;
+        chip_8.quirks.load_store_increments_i = true;
+        chip_8.initialize().unwrap();
+        chip_8
+            .load_program(vec![
+                0xF0, 0x00, 0xFF, 0xF0, // LD I, 0xFFF0
+                0xFF, 0x55, // LD [I], VF
+                0xF0, 0x00, 0xFF, 0xF0, // LD I, 0xFFF0
+                0xFF, 0x65, // LD VF, [I]
+            ])
+            .unwrap();
+        run(&mut chip_8, 2);
+        assert_eq!(chip_8.index(), 0);
+        run(&mut chip_8, 2);
+        assert_eq!(chip_8.index(), 0);
+    }
+
+    #[test]
+    fn rejects_reading_a_program_past_the_end_of_memory() {
+        let mut chip_8 = Chip8::headless();
+        chip_8.quirks.extended_memory = true;
+        chip_8.initialize().unwrap();
+        chip_8.load_program_at(vec![0x00, 0xE0], 0x2000).unwrap();
+
+        chip_8.quirks.extended_memory = false;
+        assert!(matches!(
+            chip_8.load_program_from_reader(&[0x00, 0xE0][..]),
+            Err(Chip8Error::InvalidLoadAddress { addr: 0x2000 })
+        ));
+    }
+
+    #[test]
+    fn waits_for_a_key_at_the_top_of_extended_memory() {
+        let mut chip_8 = Chip8::headless();
+        chip_8.quirks.extended_memory = true;
+        chip_8.initialize().unwrap();
+        chip_8.load_program_at(vec![0xF0, 0x0A], 0xFFFE).unwrap(); // LD V0, K
+        assert_eq!(chip_8.cycle().unwrap(), CycleOutcome::WaitingForKey);
+        assert_eq!(chip_8.program_counter(), 0xFFFE);
+    }
+
+    #[test]
+    fn loads_long_index() {
+        let mut chip_8 = load(&[
+            0xF0, 0x00, 0xBE, 0xEF, // LD I, 0xBEEF
+            0x30, 0x00, // SE V0, 0
+            0xF0, 0x00, 0x12, 0x34, // LD I, 0x1234 (skipped)
+            0x00, 0xE0, // CLS
+        ]);
+        run(&mut chip_8, 1);
+        assert_eq!(chip_8.index_register, 0xBEEF);
+        assert_eq!(chip_8.program_counter, 0x204);
+
+        // Skips jump over both words.
+        run(&mut chip_8, 1);
+        assert_eq!(chip_8.program_counter, 0x20A);
+        assert_eq!(chip_8.index_register, 0xBEEF);
+    }
+
+    #[test]
+    fn steps_a_frame() {
+        let mut chip_8 = load(&[
+            0x60, 0x20, // LD V0, 0x20
+            0xF0, 0x15, // LD DT, V0
+            0x71, 0x01, // ADD V1, 1
+            0x12, 0x04, // JP 0x204
+        ]);
+        chip_8.run_state = RunState::Paused;
+        assert_eq!(chip_8.step_frame(12).unwrap(), 12);
+        assert_eq!(chip_8.cycle_count(), 12);
+        assert_eq!(chip_8.registers()[1], 5);
+        assert_eq!(chip_8.delay_timer.0, 0x1F);
+
+        // Breakpoints end the frame early, before the timers tick.
+        chip_8.add_breakpoint(0x206);
+        assert_eq!(chip_8.step_frame(12).unwrap(), 2);
+        assert_eq!(chip_8.program_counter(), 0x206);
+        assert_eq!(chip_8.delay_timer.0, 0x1F);
+    }
+
+    #[test]
+    fn coalesces_draws_until_the_timers_tick() {
+        let (frame_sender, frame_receiver) = std::sync::mpsc::channel();
+        let (_input_sender, input_receiver) = std::sync::mpsc::channel();
+        let mut chip_8 = Chip8::new(frame_sender, input_receiver);
+        chip_8.initialize().unwrap();
+        chip_8.set_coalesce_draws(true);
+        chip_8
+            .load_program(vec![
+                0xF0, 0x29, // LD F, V0
+                0xD0, 0x15, // DRW V0, V1, 5
+                0xD0, 0x15, // DRW V0, V1, 5 (erases it)
+                0x60, 0x08, // LD V0, 8
+                0xD0, 0x15, // DRW V0, V1, 5
+                0x12, 0x0A, // JP 0x20A
+            ])
+            .unwrap();
+        assert_eq!(frame_receiver.try_iter().count(), 1);
+
+        run(&mut chip_8, 6);
+        assert_eq!(frame_receiver.try_iter().count(), 0);
+
+        // The erase never shows up, just both places the 0 was drawn.
+        chip_8.update_timers(Duration::from_millis(17));
+        let frames: Vec<_> = frame_receiver.try_iter().collect();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(&frames[0][..12], &[1, 1, 1, 1, 0, 0, 0, 0, 1, 1, 1, 1]);
+
+        chip_8.update_timers(Duration::from_millis(17));
+        assert_eq!(frame_receiver.try_iter().count(), 0);
+    }
+
+    #[test]
+    fn reads_the_delay_timer() {
+        let mut chip_8 = load(&[
+            0x60, 0x05, // LD V0, 5
+            0xF0, 0x15, // LD DT, V0
+            0x61, 0x09, // LD V1, 9
+            0xF1, 0x18, // LD ST, V1
+            0xF2, 0x07, // LD V2, DT
+        ]);
+        run(&mut chip_8, 5);
+        assert_eq!(chip_8.delay_timer.value(), 5);
+        assert_eq!(chip_8.sound_timer.value(), 9);
+        assert_eq!(chip_8.registers()[2], 5);
+    }
+
+    #[test]
+    fn timers_stop_at_zero() {
+        let mut delay_timer = DelayTimer(1);
+        delay_timer.decrement();
+        delay_timer.decrement();
+        assert_eq!(delay_timer.value(), 0);
+        let mut sound_timer = SoundTimer(0);
+        sound_timer.decrement();
+        assert_eq!(sound_timer.value(), 0);
+
+        // A game polling the delay timer sees it stay at 0.
+        let mut chip_8 = load(&[
+            0x60, 0x01, // LD V0, 1
+            0xF0, 0x15, // LD DT, V0
+            0xF1, 0x07, // LD V1, DT
+        ]);
+        chip_8.registers[1] = 0xAA;
+        run(&mut chip_8, 2);
+        chip_8.update_timers(Duration::from_secs(1));
+        run(&mut chip_8, 1);
+        assert_eq!(chip_8.registers()[1], 0);
+    }
+
+    #[test]
+    fn hashes_the_screen_the_same_with_the_same_seed() {
+        let program = [
+            0xC0, 0x3F, // RND V0, 0x3F
+            0xC1, 0x1F, // RND V1, 0x1F
+            0xF0, 0x29, // LD F, V0
+            0xD0, 0x15, // DRW V0, V1, 5
+        ];
+        let hash = |seed| {
+            let mut chip_8 = load(&program);
+            chip_8.set_seed(seed);
+            run(&mut chip_8, 4);
+            chip_8.screen_hash()
+        };
+
+        assert_eq!(hash(7), hash(7));
+        assert_ne!(hash(7), Chip8::headless().screen_hash());
+    }
+
+    #[test]
+    fn pads_odd_length_roms() {
+        // 0x12 followed by the padding byte is JP 0x200.
+        let mut chip_8 = load(&[0x12]);
+        run(&mut chip_8, 3);
+        assert_eq!(chip_8.program_counter(), 0x200);
+    }
+
+    #[test]
+    fn fetching_past_the_end_of_memory_fails() {
+        let mut chip_8 = load(&[0x1F, 0xFF]); // JP 0xFFF
+        run(&mut chip_8, 1);
+        // The second byte of the word would be at 0x1000.
+        assert!(matches!(
+            chip_8.cycle(),
+            Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000 })
+        ));
+    }
+
+    #[test]
+    fn holds_keys_across_cycles() {
+        let mut chip_8 = load(&[
+            0x60, 0x04, // LD V0, 4
+            0xE0, 0x9E, // SKP V0
+            0x12, 0x02, // JP 0x202
+            0xE0, 0xA1, // SKNP V0
+            0x12, 0x06, // JP 0x206
+            0x12, 0x0A, // JP 0x20A
+        ]);
+        chip_8.set_key(0x4, true);
+        run(&mut chip_8, 2);
+        assert_eq!(chip_8.program_counter(), 0x206);
+
+        // The key stays down until it's released.
+        run(&mut chip_8, 4);
+        assert!(chip_8.keypad.is_pressed(0x4));
+        assert_eq!(chip_8.program_counter(), 0x206);
+
+        chip_8.set_key(0x4, false);
+        run(&mut chip_8, 1);
+        assert_eq!(chip_8.program_counter(), 0x20A);
+    }
+
+    #[test]
+    fn registers_keys_pressed_together() {
+        let (frame_sender, _frame_receiver) = std::sync::mpsc::channel();
+        let (input_sender, input_receiver) = std::sync::mpsc::channel();
+        let mut chip_8 = Chip8::new(frame_sender, input_receiver);
+        chip_8.initialize().unwrap();
+        chip_8
+            .load_program(vec![
+                0x60, 0x02, // LD V0, 2
+                0x61, 0x08, // LD V1, 8
+                0xE0, 0x9E, // SKP V0
+                0x12, 0x04, // JP 0x204
+                0xE1, 0xA1, // SKNP V1
+                0x12, 0x0A, // JP 0x20A
+                0x12, 0x0C, // JP 0x20C
+            ])
+            .unwrap();
+
+        for event in [KeyEvent::Pressed(0x2), KeyEvent::Pressed(0x8)] {
+            input_sender.send(Ok(event)).unwrap();
+        }
+        run(&mut chip_8, 5);
+        assert!(chip_8.keypad.is_pressed(0x2));
+        assert!(chip_8.keypad.is_pressed(0x8));
+        // Both skips saw their key held, so we ended up in the first loop.
+        assert_eq!(chip_8.program_counter(), 0x20A);
+
+        // Letting go of one leaves the other held.
+        input_sender.send(Ok(KeyEvent::Released(0x2))).unwrap();
+        run(&mut chip_8, 1);
+        assert!(!chip_8.keypad.is_pressed(0x2));
+        assert!(chip_8.keypad.is_pressed(0x8));
+    }
+
+    #[test]
+    fn skips_machine_code_unless_strict() {
+        let program = [
+            0x03, 0x45, // SYS 0x345
+            0x60, 0x01, // LD V0, 1
+        ];
+        let mut chip_8 = load(&program);
+        run(&mut chip_8, 2);
+        assert_eq!(chip_8.registers()[0], 1);
+
+        let mut chip_8 = load(&program);
+        chip_8.set_strict_machine_code(true);
+        assert!(matches!(
+            chip_8.cycle(),
+            Err(Chip8Error::ProgramNotCompatible)
+        ));
+    }
+
+    #[test]
+    fn skips_invalid_opcodes_only_when_lenient() {
+        let program = [
+            0xFF, 0xFF, // DW 0xFFFF
+            0x60, 0x01, // LD V0, 1
+        ];
+        let mut chip_8 = load(&program);
+        assert!(matches!(
+            chip_8.cycle(),
+            Err(Chip8Error::InvalidInstruction {
+                instruction: 0xFFFF,
+                address: Some(0x200)
+            })
+        ));
+
+        let mut chip_8 = load(&program);
+        chip_8.set_lenient(true);
+        assert_eq!(chip_8.cycle().unwrap(), CycleOutcome::Executed);
+        run(&mut chip_8, 1);
+        assert_eq!(chip_8.registers()[0], 1);
+    }
+
+    #[test]
+    fn reports_jumps_to_self_as_idle() {
+        // JP 0x202, then JP 0x202 forever.
+        let mut chip_8 = load(&[0x12, 0x02, 0x12, 0x02]);
+        assert_eq!(chip_8.cycle().unwrap(), CycleOutcome::Executed);
+        assert_eq!(chip_8.cycle().unwrap(), CycleOutcome::Idle);
+        assert_eq!(chip_8.cycle().unwrap(), CycleOutcome::Idle);
+        assert_eq!(chip_8.program_counter(), 0x202);
+
+        assert_eq!(chip_8.time_to_next_tick(), TIMER_INTERVAL);
+        chip_8.update_timers(TIMER_INTERVAL / 4);
+        assert_eq!(
+            chip_8.time_to_next_tick(),
+            TIMER_INTERVAL - TIMER_INTERVAL / 4
+        );
+    }
+
+    #[test]
+    fn stores_the_audio_pattern_and_pitch() {
+        let mut chip_8 = load(&[
+            0xA2, 0x08, // LD I, 0x208
+            0xF0, 0x02, // AUDIO
+            0x63, 0x70, // LD V3, 0x70
+            0xF3, 0x3A, // LD PITCH, V3
+            0xFF, 0x00, 0xF0, 0x0F, 0xAA, 0x55, 0x00, 0x00, // Pattern
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, //
+        ]);
+        assert_eq!(chip_8.audio_pattern(), None);
+        assert_eq!(chip_8.audio_pitch(), crate::sound::DEFAULT_PITCH);
+
+        run(&mut chip_8, 4);
+        assert_eq!(
+            chip_8.audio_pattern(),
+            Some(&[
+                0xFF, 0x00, 0xF0, 0x0F, 0xAA, 0x55, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x01
+            ])
+        );
+        assert_eq!(chip_8.audio_pitch(), 0x70);
+        assert_eq!(crate::sound::pattern_rate(64 + 48), 8000.0);
+    }
+
+    #[test]
+    fn decodes_the_loaded_program() {
+        let chip_8 = load(&[
+            0x00, 0xE0, // CLS
+            0xFF, 0xFF, // Data
+            0xF0, 0x00, 0x12, 0x34, // LD I, 0x1234
+        ]);
+        let instructions: Vec<_> = chip_8.instructions().collect();
+        assert_eq!(instructions.len(), 3);
+        assert!(matches!(instructions[0], (0x200, Ok(Instruction::Clear))));
+        assert!(matches!(
+            instructions[1],
+            (
+                0x202,
+                Err(Chip8Error::InvalidInstruction {
+                    instruction: 0xFFFF,
+                    address: Some(0x202)
+                })
+            )
+        ));
+        assert!(matches!(
+            instructions[2],
+            (0x204, Ok(Instruction::SetIndexLong { nnn: 0x1234 }))
+        ));
+    }
+
+    #[test]
+    fn tracks_which_addresses_ran() {
+        let program = [
+            0x12, 0x04, // JP 0x204
+            0xFF, 0xFF, // Data
+            0xF0, 0x00, 0x12, 0x34, // LD I, 0x1234
+        ];
+        let mut chip_8 = load(&program);
+        run(&mut chip_8, 2);
+        assert_eq!(chip_8.coverage(), None);
+
+        let mut chip_8 = load(&program);
+        chip_8.set_coverage(true);
+        run(&mut chip_8, 2);
+        let coverage = chip_8.coverage().unwrap();
+        assert_eq!(coverage.len(), chip_8.memory_size());
+        assert_eq!(
+            &coverage[0x200..0x209],
+            &[true, true, false, false, true, true, true, true, false]
+        );
+    }
+
+    #[test]
+    fn counts_what_the_program_did() {
+        // Draw the same sprite twice, which collides the second time, from
+        // inside two nested calls.
+        let mut chip_8 = load(&[
+            0xA2, 0x00, // LD I, 0x200
+            0x22, 0x06, // CALL 0x206
+            0x12, 0x04, // JP 0x204
+            0x22, 0x0A, // CALL 0x20A
+            0x00, 0xEE, // RET
+            0xD0, 0x05, // DRW V0, V0, 5
+            0xD0, 0x05, // DRW V0, V0, 5
+            0x00, 0xEE, // RET
+        ]);
+        run(&mut chip_8, 8);
+        let stats = chip_8.stats();
+        assert_eq!(stats.instructions, 8);
+        assert_eq!(stats.draws, 2);
+        assert_eq!(stats.collisions, 1);
+        assert_eq!(stats.max_stack_depth, 2);
+
+        chip_8.reset().unwrap();
+        assert_eq!(chip_8.stats(), Default::default());
+    }
+}
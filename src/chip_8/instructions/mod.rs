@@ -1,9 +1,11 @@
 //! This module relates to opcode processing and formatting.
-use super::Chip8Error;
-use crate::{Chip8, HEIGHT, WIDTH};
+use super::{memory::PROGRAM_OFFSET, quirks::Platform, Chip8Error};
 
+mod assembler;
 pub mod execution;
 
+pub use assembler::{assemble, AssembleError};
+
 /// A representation of all the CHIP-8 opcodes.
 ///
 /// The names of the opcodes are unofficial and made by me. This means
@@ -22,19 +24,44 @@ pub mod execution;
 /// - PC : Program Counter
 /// - I : 16bit register (For memory address) (Similar to void pointer);
 /// - VN: One of the 16 available variables. N may be 0 to F (hexadecimal);
-#[derive(Debug)]
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Instruction {
     /// Represented by 0NNN.
     ///
     /// This will remain unimplemented as it was used to pause
     /// the chip-8 interpreter and run hardware specific code,
-    /// which was not used for most games.
-    #[allow(dead_code)]
-    CallMachineCodeRoutine,
+    /// which was not used for most games. It's skipped over with a
+    /// warning, unless [`Chip8::set_strict_machine_code`] is on.
+    ///
+    /// [`Chip8::set_strict_machine_code`]: crate::Chip8::set_strict_machine_code
+    CallMachineCodeRoutine { nnn: u16 },
     /// Represented by `00E0`.
     ///
     /// Clears the screen.
     Clear,
+    /// Represented by `00CN`.
+    ///
+    /// Scrolls the screen down by N lines (SUPER-CHIP).
+    ScrollDown { n: u8 },
+    /// Represented by `00FB`.
+    ///
+    /// Scrolls the screen right by 4 pixels (SUPER-CHIP).
+    ScrollRight,
+    /// Represented by `00FC`.
+    ///
+    /// Scrolls the screen left by 4 pixels (SUPER-CHIP).
+    ScrollLeft,
+    /// Represented by `00FE`.
+    ///
+    /// Switches to the 64x32 low resolution mode and clears the screen
+    /// (SUPER-CHIP).
+    LowResolution,
+    /// Represented by `00FF`.
+    ///
+    /// Switches to the 128x64 high resolution mode and clears the screen
+    /// (SUPER-CHIP).
+    HighResolution,
     /// Represented by `00EE`.
     ///
     /// Returns from subroutine by popping the new program
@@ -99,15 +126,20 @@ pub enum Instruction {
     /// Represented by `8XY6`
     ///
     /// Stores the least significant bit in VF and bitshifts the value
-    /// right by 1.
-    RightShift { vx: u8 },
+    /// right by 1. Depending on [`Quirks::shift_uses_vy`](crate::quirks::Quirks),
+    /// VY is copied into VX first.
+    RightShift { vx: u8, vy: u8 },
     /// Represented by `8XY7`
     ///
     /// Sets VX = VY - VX. VF is set to 1 if there is an underflow, and
     /// is set to 0 if there is not.
     SetVxToVyMinusVx { vx: u8, vy: u8 },
-    /// Represented by `8XYE```
-    LeftShift { vx: u8 },
+    /// Represented by `8XYE`
+    ///
+    /// Stores the most significant bit in VF and bitshifts the value
+    /// left by 1. Depending on [`Quirks::shift_uses_vy`](crate::quirks::Quirks),
+    /// VY is copied into VX first.
+    LeftShift { vx: u8, vy: u8 },
     /// Represented by 9XY0.
     ///
     /// Skips over the instruction if register VX != VY.
@@ -116,9 +148,16 @@ pub enum Instruction {
     ///
     /// Sets the index register to NNN.
     SetIndexRegister { nnn: u16 },
+    /// Represented by `F000 NNNN`.
+    ///
+    /// Sets the index register to the full 16 bit address in the word
+    /// after it, for XO-CHIP's 64 KB of memory. This is the only
+    /// instruction that takes up 4 bytes, see [`Instruction::LONG_PREFIX`].
+    SetIndexLong { nnn: u16 },
     /// Represented by `BNNN`.
     ///
-    /// Sets the program counter to V0 + NNN
+    /// Sets the program counter to V0 + NNN, or VX + XNN depending on
+    /// [`Quirks::jump_uses_vx`](crate::quirks::Quirks).
     JumpWithPcOffset { nnn: u16 },
     /// Represented by `CXNN`.
     ///
@@ -131,6 +170,9 @@ pub enum Instruction {
     /// height of N pixels. Each row of 8 pixels is read as bit coded (so 1 byte per row),
     /// starting from the memory location in the index register. VF is set to 1 if any
     /// screen pixels are flipped from set to unset when the sprite is drawn, and 0 otherwise.
+    ///
+    /// With [`Quirks::large_sprites`](crate::quirks::Quirks), `DXY0` draws a
+    /// 16x16 sprite with 2 bytes per row instead.
     Draw { vx: u8, vy: u8, n: u8 },
     /// Represented by `EX9E`.
     ///
@@ -140,6 +182,22 @@ pub enum Instruction {
     ///
     /// Skip next instruction if the key stored in VX is not pressed.
     SkipIfKeyNotPressed { vx: u8 },
+    /// Represented by `FN01`.
+    ///
+    /// Selects the display planes that drawing and clearing affect, as a
+    /// bitmask in N (XO-CHIP).
+    SelectPlanes { planes: u8 },
+    /// Represented by `F002`.
+    ///
+    /// Loads the 16 bytes at I into the audio pattern, 128 1-bit samples
+    /// that the buzzer plays instead of its tone (XO-CHIP). See
+    /// [`Quirks::audio_pattern`](crate::quirks::Quirks).
+    LoadAudioPattern,
+    /// Represented by `FX3A`.
+    ///
+    /// Sets the rate the audio pattern is played at from VX, where 64 is
+    /// 4000 samples a second (XO-CHIP).
+    SetPitch { vx: u8 },
     /// Represented by `FX07`.
     ///
     /// Sets VX to the value of the delay timer.
@@ -165,12 +223,25 @@ pub enum Instruction {
     /// Sets the index register to the memory location for the character
     /// stored in VX.
     SetIndexToFontCharacter { vx: u8 },
+    /// Represented by `FX30`.
+    ///
+    /// Sets the index register to the memory location for the large
+    /// (8x10) character stored in VX (SUPER-CHIP).
+    SetIndexToLargeFontCharacter { vx: u8 },
     /// Represented by `FX33`.
     ///
     /// Stores the binary-coded decimal representation of VX, with the
     /// hundreds digit in memory at location in I, the tens digit at
     /// location I+1, and the ones digit at location I+2
     SetIndexToBinaryCodedVx { vx: u8 },
+    /// Represented by `FX75`.
+    ///
+    /// Saves V0 to VX (X up to 7) in the RPL user flags (SUPER-CHIP).
+    SaveFlags { vx: u8 },
+    /// Represented by `FX85`.
+    ///
+    /// Loads V0 to VX (X up to 7) from the RPL user flags (SUPER-CHIP).
+    LoadFlags { vx: u8 },
     /// Represented by `FX55`.
     ///
     /// Stores the registers from V0 to VX (including VX) in memory, starting at
@@ -183,12 +254,65 @@ pub enum Instruction {
     LoadRegisters { vx: u8 },
     /// A value that does not represent any instruction.
     ///
-    /// If a raw instruction parses into this, it is
-    /// erroneous.
-    Unknown,
+    /// [`Instruction::new`] never returns this, it returns an error
+    /// instead. [`disassemble`] uses this for words that are most likely
+    /// data, and with [`Chip8::set_lenient`] on, running into one skips
+    /// it with a warning.
+    ///
+    /// [`Chip8::set_lenient`]: crate::Chip8::set_lenient
+    Unknown { raw: u16 },
 }
 
 impl Instruction {
+    /// The first word of `F000 NNNN`. [`Instruction::new`] only sees one
+    /// word, so it can't decode this one and returns an error; whatever is
+    /// decoding has to read the next word and make an
+    /// [`Instruction::SetIndexLong`] from it.
+    pub const LONG_PREFIX: u16 = 0xF000;
+
+    /// How many bytes the instruction takes up in memory.
+    pub fn size(&self) -> u16 {
+        match self {
+            Self::SetIndexLong { .. } => 4,
+            _ => 2,
+        }
+    }
+
+    /// The first platform that had this instruction: [`Platform::Schip`]
+    /// or [`Platform::XoChip`] for their extensions, and
+    /// [`Platform::Chip8`] for everything else.
+    pub fn platform(&self) -> Platform {
+        match self {
+            Self::ScrollDown { .. }
+            | Self::ScrollRight
+            | Self::ScrollLeft
+            | Self::LowResolution
+            | Self::HighResolution
+            | Self::SetIndexToLargeFontCharacter { .. }
+            | Self::SaveFlags { .. }
+            | Self::LoadFlags { .. } => Platform::Schip,
+            Self::SetIndexLong { .. }
+            | Self::SelectPlanes { .. }
+            | Self::LoadAudioPattern
+            | Self::SetPitch { .. } => Platform::XoChip,
+            _ => Platform::Chip8,
+        }
+    }
+
+    /// Decodes a raw instruction word. This doesn't need a [`Chip8`], so it
+    /// can be used on its own by assemblers and disassemblers.
+    ///
+    /// ```
+    /// use chip_8_emulator::Instruction;
+    ///
+    /// assert_eq!(Instruction::new(0x00E0).unwrap(), Instruction::Clear);
+    /// assert_eq!(
+    ///     Instruction::new(0x6A2F).unwrap(),
+    ///     Instruction::SetImmediate { vx: 0xA, nn: 0x2F }
+    /// );
+    /// ```
+    ///
+    /// [`Chip8`]: crate::Chip8
     pub fn new(raw: u16) -> Result<Instruction, Chip8Error> {
         // We extract the first nibble of the raw u16,
         // which helps us create a match tree to figure out
@@ -207,19 +331,18 @@ impl Instruction {
         let n = (raw & 0x000F) as u8;
 
         let instruction = match first_nibble {
-            0x0 => {
-                let last_byte = raw & 0x00FF;
-
-                match last_byte {
-                    0xE0 => Self::Clear,
-                    0xEE => Self::Return,
-                    // 0NNN is technically an instruction, but we do not
-                    // want to implement it because it runs machine-specific
-                    // instructions and is not compatible with every
-                    // CHIP-8 machine.
-                    _ => return Err(Chip8Error::ProgramNotCompatible),
-                }
-            }
+            0x0 => match raw {
+                0x00E0 => Self::Clear,
+                0x00EE => Self::Return,
+                0x00C0..=0x00CF => Self::ScrollDown { n },
+                0x00FB => Self::ScrollRight,
+                0x00FC => Self::ScrollLeft,
+                0x00FE => Self::LowResolution,
+                0x00FF => Self::HighResolution,
+                // Everything else is a call to a machine code routine,
+                // which we can decode but not run.
+                _ => Self::CallMachineCodeRoutine { nnn },
+            },
             0x1 => Self::Jump { nnn },
             0x2 => Self::Call { nnn },
             0x3 => Self::SkipIfRegisterEquals { vx, nn },
@@ -237,10 +360,15 @@ impl Instruction {
                     0x3 => Self::BitwiseXor { vx, vy },
                     0x4 => Self::Add { vx, vy },
                     0x5 => Self::Subtract { vx, vy },
-                    0x6 => Self::RightShift { vx },
+                    0x6 => Self::RightShift { vx, vy },
                     0x7 => Self::SetVxToVyMinusVx { vx, vy },
-                    0xE => Self::LeftShift { vx },
-                    _ => return Err(Chip8Error::InvalidInstruction { instruction: raw }),
+                    0xE => Self::LeftShift { vx, vy },
+                    _ => {
+                        return Err(Chip8Error::InvalidInstruction {
+                            instruction: raw,
+                            address: None,
+                        })
+                    }
                 }
             }
             0x9 => Self::SkipIfRegisterVxNotEqualsVy { vx, vy },
@@ -254,28 +382,281 @@ impl Instruction {
                 match last_byte {
                     0x9E => Self::SkipIfKeyPressed { vx },
                     0xA1 => Self::SkipIfKeyNotPressed { vx },
-                    _ => return Err(Chip8Error::InvalidInstruction { instruction: raw }),
+                    _ => {
+                        return Err(Chip8Error::InvalidInstruction {
+                            instruction: raw,
+                            address: None,
+                        })
+                    }
                 }
             }
             0xF => {
                 let last_byte = (raw & 0x00FF) as u8;
 
                 match last_byte {
+                    0x01 => Self::SelectPlanes { planes: vx },
+                    0x02 if vx == 0 => Self::LoadAudioPattern,
                     0x07 => Self::SetVxToDelayTimer { vx },
                     0x0A => Self::AwaitKeyInput { vx },
                     0x15 => Self::SetDelayTimer { vx },
                     0x18 => Self::SetSoundTimer { vx },
                     0x1E => Self::AddToIndex { vx },
                     0x29 => Self::SetIndexToFontCharacter { vx },
+                    0x30 => Self::SetIndexToLargeFontCharacter { vx },
+                    0x3A => Self::SetPitch { vx },
+                    0x75 => Self::SaveFlags { vx },
+                    0x85 => Self::LoadFlags { vx },
                     0x33 => Self::SetIndexToBinaryCodedVx { vx },
                     0x55 => Self::DumpRegisters { vx },
                     0x65 => Self::LoadRegisters { vx },
-                    _ => return Err(Chip8Error::InvalidInstruction { instruction: raw }),
+                    _ => {
+                        return Err(Chip8Error::InvalidInstruction {
+                            instruction: raw,
+                            address: None,
+                        })
+                    }
                 }
             }
-            _ => return Err(Chip8Error::InvalidInstruction { instruction: raw }),
+            _ => {
+                return Err(Chip8Error::InvalidInstruction {
+                    instruction: raw,
+                    address: None,
+                })
+            }
         };
 
         Ok(instruction)
     }
+
+    /// Decodes an instruction from its two bytes, in the order they are
+    /// stored in memory (CHIP-8 is big-endian).
+    ///
+    /// ```
+    /// use chip_8_emulator::Instruction;
+    ///
+    /// let rom = [0xD0, 0x15, 0x12, 0x00];
+    /// assert_eq!(
+    ///     Instruction::decode_bytes(rom[0], rom[1]).unwrap(),
+    ///     Instruction::Draw { vx: 0x0, vy: 0x1, n: 5 }
+    /// );
+    /// assert_eq!(
+    ///     Instruction::decode_bytes(rom[2], rom[3]).unwrap().to_string(),
+    ///     "JP 0x200"
+    /// );
+    /// ```
+    pub fn decode_bytes(high: u8, low: u8) -> Result<Instruction, Chip8Error> {
+        Self::new(u16::from_be_bytes([high, low]))
+    }
+
+    /// Encodes the instruction back into the bytes it's stored as, the
+    /// other way around from [`Instruction::decode_bytes`].
+    /// [`Instruction::Unknown`] gives back its raw word.
+    ///
+    /// ```
+    /// use chip_8_emulator::Instruction;
+    ///
+    /// let draw = Instruction::Draw { vx: 0x0, vy: 0x1, n: 5 };
+    /// assert_eq!(draw.to_bytes(), [0xD0, 0x15]);
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let x = |vx: u8| (vx as u16) << 8;
+        let xy = |vx: u8, vy: u8| x(vx) | (vy as u16) << 4;
+        let word = match *self {
+            Self::CallMachineCodeRoutine { nnn } => nnn,
+            Self::Clear => 0x00E0,
+            Self::Return => 0x00EE,
+            Self::ScrollDown { n } => 0x00C0 | n as u16,
+            Self::ScrollRight => 0x00FB,
+            Self::ScrollLeft => 0x00FC,
+            Self::LowResolution => 0x00FE,
+            Self::HighResolution => 0x00FF,
+            Self::Jump { nnn } => 0x1000 | nnn,
+            Self::Call { nnn } => 0x2000 | nnn,
+            Self::SkipIfRegisterEquals { vx, nn } => 0x3000 | x(vx) | nn as u16,
+            Self::SkipIfRegisterNotEquals { vx, nn } => 0x4000 | x(vx) | nn as u16,
+            Self::SkipIfRegisterVxEqualsVy { vx, vy } => 0x5000 | xy(vx, vy),
+            Self::SetImmediate { vx, nn } => 0x6000 | x(vx) | nn as u16,
+            Self::AddImmediate { vx, nn } => 0x7000 | x(vx) | nn as u16,
+            Self::Copy { vx, vy } => 0x8000 | xy(vx, vy),
+            Self::BitwiseOr { vx, vy } => 0x8001 | xy(vx, vy),
+            Self::BitwiseAnd { vx, vy } => 0x8002 | xy(vx, vy),
+            Self::BitwiseXor { vx, vy } => 0x8003 | xy(vx, vy),
+            Self::Add { vx, vy } => 0x8004 | xy(vx, vy),
+            Self::Subtract { vx, vy } => 0x8005 | xy(vx, vy),
+            Self::RightShift { vx, vy } => 0x8006 | xy(vx, vy),
+            Self::SetVxToVyMinusVx { vx, vy } => 0x8007 | xy(vx, vy),
+            Self::LeftShift { vx, vy } => 0x800E | xy(vx, vy),
+            Self::SkipIfRegisterVxNotEqualsVy { vx, vy } => 0x9000 | xy(vx, vy),
+            Self::SetIndexRegister { nnn } => 0xA000 | nnn,
+            Self::SetIndexLong { nnn } => {
+                let [high, low] = nnn.to_be_bytes();
+                let [prefix_high, prefix_low] = Self::LONG_PREFIX.to_be_bytes();
+                return vec![prefix_high, prefix_low, high, low];
+            }
+            Self::JumpWithPcOffset { nnn } => 0xB000 | nnn,
+            Self::Random { vx, nn } => 0xC000 | x(vx) | nn as u16,
+            Self::Draw { vx, vy, n } => 0xD000 | xy(vx, vy) | n as u16,
+            Self::SkipIfKeyPressed { vx } => 0xE09E | x(vx),
+            Self::SkipIfKeyNotPressed { vx } => 0xE0A1 | x(vx),
+            Self::SelectPlanes { planes } => 0xF001 | x(planes),
+            Self::LoadAudioPattern => 0xF002,
+            Self::SetPitch { vx } => 0xF03A | x(vx),
+            Self::SetVxToDelayTimer { vx } => 0xF007 | x(vx),
+            Self::AwaitKeyInput { vx } => 0xF00A | x(vx),
+            Self::SetDelayTimer { vx } => 0xF015 | x(vx),
+            Self::SetSoundTimer { vx } => 0xF018 | x(vx),
+            Self::AddToIndex { vx } => 0xF01E | x(vx),
+            Self::SetIndexToFontCharacter { vx } => 0xF029 | x(vx),
+            Self::SetIndexToLargeFontCharacter { vx } => 0xF030 | x(vx),
+            Self::SetIndexToBinaryCodedVx { vx } => 0xF033 | x(vx),
+            Self::DumpRegisters { vx } => 0xF055 | x(vx),
+            Self::LoadRegisters { vx } => 0xF065 | x(vx),
+            Self::SaveFlags { vx } => 0xF075 | x(vx),
+            Self::LoadFlags { vx } => 0xF085 | x(vx),
+            Self::Unknown { raw } => raw,
+        };
+        word.to_be_bytes().to_vec()
+    }
+}
+
+impl std::fmt::Display for Instruction {
+    /// Formats the instruction as assembly, using the mnemonics from
+    /// [Cowgod's technical reference](http://devernay.free.fr/hacks/chip8/C8TECH10.HTM).
+    /// Registers are written as `VX`, and addresses and constants in hex.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::CallMachineCodeRoutine { nnn } => write!(f, "SYS 0x{nnn:03X}"),
+            Self::Clear => write!(f, "CLS"),
+            Self::Return => write!(f, "RET"),
+            Self::ScrollDown { n } => write!(f, "SCD {n}"),
+            Self::ScrollRight => write!(f, "SCR"),
+            Self::ScrollLeft => write!(f, "SCL"),
+            Self::LowResolution => write!(f, "LOW"),
+            Self::HighResolution => write!(f, "HIGH"),
+            Self::Jump { nnn } => write!(f, "JP 0x{nnn:03X}"),
+            Self::Call { nnn } => write!(f, "CALL 0x{nnn:03X}"),
+            Self::SkipIfRegisterEquals { vx, nn } => write!(f, "SE V{vx:X}, 0x{nn:02X}"),
+            Self::SkipIfRegisterNotEquals { vx, nn } => write!(f, "SNE V{vx:X}, 0x{nn:02X}"),
+            Self::SkipIfRegisterVxEqualsVy { vx, vy } => write!(f, "SE V{vx:X}, V{vy:X}"),
+            Self::SetImmediate { vx, nn } => write!(f, "LD V{vx:X}, 0x{nn:02X}"),
+            Self::AddImmediate { vx, nn } => write!(f, "ADD V{vx:X}, 0x{nn:02X}"),
+            Self::Copy { vx, vy } => write!(f, "LD V{vx:X}, V{vy:X}"),
+            Self::BitwiseOr { vx, vy } => write!(f, "OR V{vx:X}, V{vy:X}"),
+            Self::BitwiseAnd { vx, vy } => write!(f, "AND V{vx:X}, V{vy:X}"),
+            Self::BitwiseXor { vx, vy } => write!(f, "XOR V{vx:X}, V{vy:X}"),
+            Self::Add { vx, vy } => write!(f, "ADD V{vx:X}, V{vy:X}"),
+            Self::Subtract { vx, vy } => write!(f, "SUB V{vx:X}, V{vy:X}"),
+            Self::RightShift { vx, vy } => write!(f, "SHR V{vx:X}, V{vy:X}"),
+            Self::SetVxToVyMinusVx { vx, vy } => write!(f, "SUBN V{vx:X}, V{vy:X}"),
+            Self::LeftShift { vx, vy } => write!(f, "SHL V{vx:X}, V{vy:X}"),
+            Self::SkipIfRegisterVxNotEqualsVy { vx, vy } => write!(f, "SNE V{vx:X}, V{vy:X}"),
+            Self::SetIndexRegister { nnn } => write!(f, "LD I, 0x{nnn:03X}"),
+            Self::SetIndexLong { nnn } => write!(f, "LD I, 0x{nnn:04X}"),
+            Self::JumpWithPcOffset { nnn } => write!(f, "JP V0, 0x{nnn:03X}"),
+            Self::Random { vx, nn } => write!(f, "RND V{vx:X}, 0x{nn:02X}"),
+            Self::Draw { vx, vy, n } => write!(f, "DRW V{vx:X}, V{vy:X}, {n}"),
+            Self::SkipIfKeyPressed { vx } => write!(f, "SKP V{vx:X}"),
+            Self::SkipIfKeyNotPressed { vx } => write!(f, "SKNP V{vx:X}"),
+            Self::SelectPlanes { planes } => write!(f, "PLANE {planes}"),
+            Self::LoadAudioPattern => write!(f, "AUDIO"),
+            Self::SetPitch { vx } => write!(f, "LD PITCH, V{vx:X}"),
+            Self::SetVxToDelayTimer { vx } => write!(f, "LD V{vx:X}, DT"),
+            Self::AwaitKeyInput { vx } => write!(f, "LD V{vx:X}, K"),
+            Self::SetDelayTimer { vx } => write!(f, "LD DT, V{vx:X}"),
+            Self::SetSoundTimer { vx } => write!(f, "LD ST, V{vx:X}"),
+            Self::AddToIndex { vx } => write!(f, "ADD I, V{vx:X}"),
+            Self::SetIndexToFontCharacter { vx } => write!(f, "LD F, V{vx:X}"),
+            Self::SetIndexToLargeFontCharacter { vx } => write!(f, "LD HF, V{vx:X}"),
+            Self::SaveFlags { vx } => write!(f, "LD R, V{vx:X}"),
+            Self::LoadFlags { vx } => write!(f, "LD V{vx:X}, R"),
+            Self::SetIndexToBinaryCodedVx { vx } => write!(f, "LD B, V{vx:X}"),
+            Self::DumpRegisters { vx } => write!(f, "LD [I], V{vx:X}"),
+            Self::LoadRegisters { vx } => write!(f, "LD V{vx:X}, [I]"),
+            Self::Unknown { raw } => write!(f, "DW 0x{raw:04X}"),
+        }
+    }
+}
+
+/// Decodes a whole program, pairing each instruction with the address it
+/// would be loaded at (starting from 0x200).
+///
+/// Programs usually contain sprites and other data as well as code, so
+/// any word that isn't a valid instruction becomes [`Instruction::Unknown`]
+/// (printed as `DW 0xNNNN`) rather than stopping the disassembly. A
+/// trailing odd byte is treated as if it were followed by 0x00, as it
+/// would be in memory.
+pub fn disassemble(bytes: &[u8]) -> Vec<(u16, Instruction)> {
+    let word = |offset: usize| {
+        let byte = |offset: usize| *bytes.get(offset).unwrap_or(&0) as u16;
+        (byte(offset) << 8) | byte(offset + 1)
+    };
+
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let raw = word(offset);
+        let instruction = if raw == Instruction::LONG_PREFIX && offset + 2 < bytes.len() {
+            Instruction::SetIndexLong {
+                nnn: word(offset + 2),
+            }
+        } else {
+            Instruction::new(raw).unwrap_or(Instruction::Unknown { raw })
+        };
+
+        instructions.push(((PROGRAM_OFFSET + offset) as u16, instruction));
+        offset += instruction.size() as usize;
+    }
+    instructions
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::{disassemble, Instruction};
+    use crate::quirks::Platform;
+
+    fn format_raw(raw: u16) -> String {
+        Instruction::new(raw).unwrap().to_string()
+    }
+
+    #[test]
+    fn formats_as_assembly() {
+        assert_eq!(format_raw(0x00E0), "CLS");
+        assert_eq!(format_raw(0x12A0), "JP 0x2A0");
+        assert_eq!(format_raw(0x6320), "LD V3, 0x20");
+        assert_eq!(format_raw(0x8AB4), "ADD VA, VB");
+        assert_eq!(format_raw(0xA050), "LD I, 0x050");
+        assert_eq!(format_raw(0xD125), "DRW V1, V2, 5");
+        assert_eq!(format_raw(0xF20A), "LD V2, K");
+        assert_eq!(format_raw(0xFF65), "LD VF, [I]");
+        // Only 00E0 clears, the rest of 0NNN calls machine code.
+        assert_eq!(format_raw(0x01E0), "SYS 0x1E0");
+    }
+
+    #[test]
+    fn disassembles_data_as_words() {
+        let listing: Vec<String> = disassemble(&[0x00, 0xE0, 0xFF, 0xFF, 0x12])
+            .into_iter()
+            .map(|(address, instruction)| format!("0x{address:03X} {instruction}"))
+            .collect();
+
+        assert_eq!(listing, ["0x200 CLS", "0x202 DW 0xFFFF", "0x204 JP 0x200"]);
+
+        let listing: Vec<String> = disassemble(&[0xF0, 0x00, 0x12, 0x34, 0x00, 0xE0])
+            .into_iter()
+            .map(|(address, instruction)| format!("0x{address:03X} {instruction}"))
+            .collect();
+        assert_eq!(listing, ["0x200 LD I, 0x1234", "0x204 CLS"]);
+    }
+
+    #[test]
+    fn knows_which_platform_added_an_instruction() {
+        let platforms: Vec<Platform> = disassemble(&[0x00, 0xE0, 0x00, 0xFF, 0xF2, 0x01])
+            .into_iter()
+            .map(|(_, instruction)| instruction.platform())
+            .collect();
+        assert_eq!(
+            platforms,
+            [Platform::Chip8, Platform::Schip, Platform::XoChip]
+        );
+    }
 }
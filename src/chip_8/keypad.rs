@@ -1,73 +1,324 @@
-use winit::{
-    event::{Event, VirtualKeyCode},
-    event_loop::ControlFlow,
-};
+//! Maps the keyboard to the CHIP-8 keypad and emulator hotkeys.
+
+use winit::{event::VirtualKeyCode, event_loop::ControlFlow};
 use winit_input_helper::WinitInputHelper;
 
-use super::Chip8Error;
+use super::{Chip8Error, KeyEvent, Keypad};
 
-//fix the lib errors idk, somehow we need to
-//layout based on the chip 8 tutorial blog
-pub fn handle_keyboard_input(
-    input: &WinitInputHelper,
-    control_flow: &mut ControlFlow,
-) -> Result<Option<u8>, Chip8Error> {
-    if input.key_held(VirtualKeyCode::Escape) || input.close_requested() {
-        *control_flow = ControlFlow::Exit;
-        //Doesn't matter what we return;
-        return Ok(Some(0x0));
-    }
+/// Keys that control the emulator itself, rather than being passed on to
+/// the CHIP-8 keypad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hotkey {
+    /// Pauses or resumes emulation.
+    TogglePause,
+    /// Runs a single instruction while paused.
+    Step,
+    /// Runs a single 60Hz frame while paused.
+    StepFrame,
+    /// Saves the machine state to a file.
+    SaveState,
+    /// Loads the machine state saved with [`Hotkey::SaveState`].
+    LoadState,
+    /// Saves a PNG of the screen.
+    Screenshot,
+    /// Starts recording a GIF, or stops and saves it.
+    ToggleRecording,
+    /// Switches between a window and borderless fullscreen.
+    ToggleFullscreen,
+    /// Swaps the colors of lit and unlit pixels.
+    SwapColors,
+    /// Shows or hides the readout of the CPU over the picture.
+    ToggleOverlay,
+    /// Prints the addresses that have run so far, see
+    /// [`Chip8::coverage`](crate::Chip8::coverage).
+    DumpCoverage,
+    /// Mutes or unmutes the buzzer, without pausing emulation.
+    ToggleMute,
+    /// Stops showing new frames (keeping the last one on screen) or goes
+    /// back to showing them, without pausing emulation.
+    ToggleFreezeDisplay,
+}
 
-    if input.key_held(VirtualKeyCode::Key1) {
-        //println!("1 held"); <-- Debug
-        return Ok(Some(0x0));
-    }
+impl Hotkey {
+    const ALL: [Hotkey; 13] = [
+        Hotkey::TogglePause,
+        Hotkey::Step,
+        Hotkey::StepFrame,
+        Hotkey::SaveState,
+        Hotkey::LoadState,
+        Hotkey::Screenshot,
+        Hotkey::ToggleRecording,
+        Hotkey::ToggleFullscreen,
+        Hotkey::SwapColors,
+        Hotkey::ToggleOverlay,
+        Hotkey::DumpCoverage,
+        Hotkey::ToggleMute,
+        Hotkey::ToggleFreezeDisplay,
+    ];
 
-    if input.key_held(VirtualKeyCode::Key2) {
-        return Ok(Some(0x1));
+    fn key(self) -> VirtualKeyCode {
+        match self {
+            Self::TogglePause => VirtualKeyCode::P,
+            Self::Step => VirtualKeyCode::N,
+            Self::StepFrame => VirtualKeyCode::M,
+            Self::SaveState => VirtualKeyCode::F5,
+            Self::LoadState => VirtualKeyCode::F9,
+            Self::Screenshot => VirtualKeyCode::F12,
+            Self::ToggleRecording => VirtualKeyCode::F10,
+            Self::ToggleFullscreen => VirtualKeyCode::F11,
+            Self::SwapColors => VirtualKeyCode::F6,
+            Self::ToggleOverlay => VirtualKeyCode::F3,
+            Self::DumpCoverage => VirtualKeyCode::F7,
+            Self::ToggleMute => VirtualKeyCode::F8,
+            Self::ToggleFreezeDisplay => VirtualKeyCode::F4,
+        }
     }
+}
 
-    if input.key_held(VirtualKeyCode::Key3) {
-        return Ok(Some(0x2));
-    }
-    if input.key_held(VirtualKeyCode::Key4) {
-        return Ok(Some(0x3));
-    }
-    if input.key_held(VirtualKeyCode::Q) {
-        return Ok(Some(0x4));
-    }
-    if input.key_held(VirtualKeyCode::W) {
-        return Ok(Some(0x5));
-    }
-    if input.key_held(VirtualKeyCode::E) {
-        return Ok(Some(0x6));
-    }
-    if input.key_held(VirtualKeyCode::R) {
-        return Ok(Some(0x7));
+/// Returns every hotkey that was just pressed down (holding a hotkey
+/// doesn't repeat it).
+pub fn handle_hotkeys(input: &WinitInputHelper) -> Vec<Hotkey> {
+    Hotkey::ALL
+        .into_iter()
+        .filter(|hotkey| input.key_pressed(hotkey.key()))
+        .collect()
+}
+
+/// True while the rewind key (Backspace) is held down.
+pub fn rewind_held(input: &WinitInputHelper) -> bool {
+    input.key_held(VirtualKeyCode::Back)
+}
+
+/// True while the fast-forward key (Space) is held down.
+pub fn turbo_held(input: &WinitInputHelper) -> bool {
+    input.key_held(VirtualKeyCode::Space)
+}
+
+/// The keyboard keys that can be used in a keymap file, by name. Digits
+/// can also be written on their own, so `1` is the same as `Key1`.
+#[rustfmt::skip]
+const NAMED_KEYS: [VirtualKeyCode; 74] = {
+    use VirtualKeyCode::*;
+    [
+        Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+        NumpadAdd, NumpadSubtract, NumpadMultiply, NumpadDivide, NumpadDecimal, NumpadEnter,
+        Up, Down, Left, Right, Space, Return, LShift, RShift, LControl, RControl, LAlt, RAlt,
+        Comma, Period, Slash, Semicolon, Apostrophe, Minus, Equals, LBracket, RBracket, Backslash,
+    ]
+};
+
+/// Something wrong with a keymap file.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum KeymapError {
+    /// A line that isn't blank, a comment, or `key = value`.
+    #[error("Line {line}: expected something like `A = Z`")]
+    Syntax {
+        /// The line number, starting at 1.
+        line: usize,
+    },
+    /// The left hand side isn't a hex digit.
+    #[error("Line {line}: `{key}` is not a CHIP-8 key (0 to F)")]
+    InvalidKey {
+        /// The line number, starting at 1.
+        line: usize,
+        /// What was written instead.
+        key: String,
+    },
+    /// The right hand side isn't a keyboard key we know of.
+    #[error("Line {line}: `{name}` is not a keyboard key")]
+    UnknownKeyboardKey {
+        /// The line number, starting at 1.
+        line: usize,
+        /// What was written instead.
+        name: String,
+    },
+    /// A CHIP-8 key was never given a keyboard key.
+    #[error("CHIP-8 key {key:X} is not assigned to a keyboard key")]
+    Unassigned {
+        /// The CHIP-8 key.
+        key: u8,
+    },
+}
+
+/// Which keyboard key each of the 16 CHIP-8 keys is bound to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap {
+    keys: [VirtualKeyCode; 16],
+}
+
+impl Default for Keymap {
+    /// The usual layout, where the left side of a QWERTY keyboard stands in
+    /// for the COSMAC VIP keypad:
+    ///
+    /// ```text
+    /// 1 2 3 4        1 2 3 C
+    /// Q W E R   ->   4 5 6 D
+    /// A S D F        7 8 9 E
+    /// Z X C V        A 0 B F
+    /// ```
+    fn default() -> Self {
+        use VirtualKeyCode::*;
+        Self {
+            keys: [X, Key1, Key2, Key3, Q, W, E, A, S, D, Z, C, Key4, R, F, V],
+        }
     }
-    if input.key_held(VirtualKeyCode::A) {
-        return Ok(Some(0x8));
+}
+
+impl Keymap {
+    /// Parses a keymap file. Each line binds a CHIP-8 key to a keyboard key,
+    /// like `C = Key4` or `0 = X`, and lines starting with `#` are comments.
+    /// Every CHIP-8 key has to be assigned.
+    pub fn parse(text: &str) -> Result<Self, KeymapError> {
+        let mut keys = [None; 16];
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, name) = line
+                .split_once('=')
+                .ok_or(KeymapError::Syntax { line: line_number })?;
+            let (key, name) = (key.trim(), name.trim());
+
+            let key = u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|&key| key <= 0xF)
+                .ok_or_else(|| KeymapError::InvalidKey {
+                    line: line_number,
+                    key: key.to_string(),
+                })?;
+            let keyboard_key =
+                parse_keyboard_key(name).ok_or_else(|| KeymapError::UnknownKeyboardKey {
+                    line: line_number,
+                    name: name.to_string(),
+                })?;
+            keys[key as usize] = Some(keyboard_key);
+        }
+
+        let mut assigned = [VirtualKeyCode::Key0; 16];
+        for (key, keyboard_key) in keys.into_iter().enumerate() {
+            assigned[key] = keyboard_key.ok_or(KeymapError::Unassigned { key: key as u8 })?;
+        }
+
+        Ok(Self { keys: assigned })
     }
-    if input.key_held(VirtualKeyCode::S) {
-        return Ok(Some(0x9));
+
+    /// The keyboard key bound to `key`.
+    pub fn key(&self, key: u8) -> VirtualKeyCode {
+        self.keys[key as usize]
     }
-    if input.key_held(VirtualKeyCode::D) {
-        return Ok(Some(0xA));
+
+    /// The CHIP-8 key bound to `keyboard_key`, if any.
+    pub fn chip_8_key(&self, keyboard_key: VirtualKeyCode) -> Option<u8> {
+        (0..16).find(|&key| self.key(key) == keyboard_key)
     }
-    if input.key_held(VirtualKeyCode::F) {
-        return Ok(Some(0xB));
+}
+
+/// Looks up a keyboard key by the name a keymap file would use for it,
+/// ignoring case. A single letter or digit is the key with that label.
+pub fn parse_keyboard_key(name: &str) -> Option<VirtualKeyCode> {
+    let name = match name {
+        digit @ ("0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9") => {
+            format!("Key{digit}")
+        }
+        name => name.to_string(),
+    };
+
+    NAMED_KEYS
+        .into_iter()
+        .find(|key| format!("{key:?}").eq_ignore_ascii_case(&name))
+}
+
+/// Returns the CHIP-8 keys that went down or came back up since the last
+/// call, and updates `held` to match. `held` is what was held then, so
+/// the events are worked out from which keys are held now and a missed
+/// key up can't leave a key stuck. Holding Tab returns
+/// [`Chip8Error::ProgramRestartRequested`] instead.
+pub fn handle_keyboard_input(
+    input: &WinitInputHelper,
+    keymap: &Keymap,
+    held: &mut Keypad,
+    control_flow: &mut ControlFlow,
+) -> Result<Vec<KeyEvent>, Chip8Error> {
+    if input.key_held(VirtualKeyCode::Escape) || input.close_requested() {
+        *control_flow = ControlFlow::Exit;
+        return Ok(Vec::new());
     }
-    if input.key_held(VirtualKeyCode::Z) {
-        return Ok(Some(0xC));
+
+    let mut now_held = Keypad::default();
+    for key in 0..16 {
+        now_held.set(key, input.key_held(keymap.key(key)));
     }
-    if input.key_held(VirtualKeyCode::X) {
-        return Ok(Some(0xD));
+    if now_held == Keypad::default() && input.key_held(VirtualKeyCode::Tab) {
+        return Err(Chip8Error::ProgramRestartRequested);
     }
-    if input.key_held(VirtualKeyCode::C) {
-        return Ok(Some(0xF));
+
+    let events = held.changes_to(now_held).collect();
+    *held = now_held;
+    Ok(events)
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::{Keymap, KeymapError};
+    use winit::event::VirtualKeyCode;
+
+    const STANDARD: &str = "
+        # The usual layout
+        1 = 1
+        2 = 2
+        3 = 3
+        C = 4
+        4 = Q
+        5 = W
+        6 = E
+        D = R
+        7 = A
+        8 = S
+        9 = D
+        E = F
+        A = Z
+        0 = X
+        B = C
+        F = V
+    ";
+
+    #[test]
+    fn parses_keymap() {
+        assert_eq!(Keymap::parse(STANDARD), Ok(Keymap::default()));
+
+        let keymap = Keymap::parse(&STANDARD.replace("5 = W", "5 = up")).unwrap();
+        assert_eq!(keymap.key(0x5), VirtualKeyCode::Up);
     }
-    if input.key_held(VirtualKeyCode::Tab) {
-        return Err(Chip8Error::ProgramRestartRequested);
+
+    #[test]
+    fn rejects_bad_keymaps() {
+        assert_eq!(
+            Keymap::parse(&STANDARD.replace("F = V", "")),
+            Err(KeymapError::Unassigned { key: 0xF })
+        );
+        assert_eq!(
+            Keymap::parse(&STANDARD.replace("F = V", "G = V")),
+            Err(KeymapError::InvalidKey {
+                line: 18,
+                key: "G".to_string()
+            })
+        );
+        assert_eq!(
+            Keymap::parse(&STANDARD.replace("F = V", "F = Hyper")),
+            Err(KeymapError::UnknownKeyboardKey {
+                line: 18,
+                name: "Hyper".to_string()
+            })
+        );
+        assert_eq!(
+            Keymap::parse(&STANDARD.replace("F = V", "F V")),
+            Err(KeymapError::Syntax { line: 18 })
+        );
     }
-    return Ok(None);
 }
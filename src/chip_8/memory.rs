@@ -1,11 +1,19 @@
+use std::io::Read;
+use std::sync::Arc;
+use std::time::Duration;
+
 use crate::chip_8::{Chip8, Chip8Error, EmulatorState};
 
-use super::{screen::Screen, stack, DelayTimer, SoundTimer};
+use super::{screen::Screen, sound, stack, DelayTimer, SoundTimer, Stats};
 
 /// The address where our program starts in memory
 pub(crate) const PROGRAM_OFFSET: usize = 0x200;
 pub(crate) const FONT_SET_OFFSET: usize = 0x050;
+/// The large font goes right after the small one.
+pub(crate) const LARGE_FONT_SET_OFFSET: usize = FONT_SET_OFFSET + FONT_SET.len();
 pub(crate) const MEMORY_SIZE: usize = 0x1000;
+/// How much memory XO-CHIP has. See [`Quirks::extended_memory`](crate::quirks::Quirks::extended_memory).
+pub(crate) const EXTENDED_MEMORY_SIZE: usize = 0x10000;
 
 /// The default font set used in the CHIP-8 interpreter.
 /// It works by treating the first 4 bits of each byte as pixels,
@@ -15,7 +23,9 @@ pub(crate) const MEMORY_SIZE: usize = 0x1000;
 /// This [website](https://multigesture.net/articles/how-to-write-an-emulator-chip-8-interpreter/)
 /// was used for the table, as well as a demonstration of how
 /// this works.
-const FONT_SET: [u8; 80] = [
+///
+/// A good place to start from for [`Chip8::load_font`].
+pub const FONT_SET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -34,23 +44,64 @@ const FONT_SET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+/// The SUPER-CHIP font, which is 8 pixels wide and 10 tall. SUPER-CHIP only
+/// had the digits, the letters are the same ones most modern interpreters
+/// use.
+const LARGE_FONT_SET: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x3C, 0x7E, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
 /// Regions:
 /// - 0x000-0x1FF is used for the CHIP-8 interpreter (used for the stack
-/// in this implementation).
+///   in this implementation).
 /// - 0x050-0x0A0 is used for the built-in pixel font set.
-/// - 0x200-0xFFF is used for the program ROM and scratch RAM.
+/// - 0x0A0-0x140 is used for the large SUPER-CHIP font set.
+/// - 0x200-0xFFF is used for the program ROM and scratch RAM, and goes up
+///   to 0xFFFF with XO-CHIP's extended memory.
 ///
-/// Has a capacity of [`MEMORY_SIZE`] bytes.
+/// Has a capacity of [`MEMORY_SIZE`] bytes by default.
 #[derive(Debug)]
-pub(crate) struct Memory([u8; MEMORY_SIZE]);
+pub(crate) struct Memory(Box<[u8]>);
 
 impl Default for Memory {
     fn default() -> Self {
-        Self([0; MEMORY_SIZE])
+        Self::new(MEMORY_SIZE)
     }
 }
 
 impl Memory {
+    /// Creates `size` bytes of zeroed memory.
+    pub(crate) fn new(size: usize) -> Self {
+        Self(vec![0; size].into())
+    }
+
+    /// How many bytes of memory there are.
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Grows or shrinks memory to `size` bytes, keeping whatever fits.
+    pub(crate) fn resize(&mut self, size: usize) {
+        let mut memory = std::mem::take(&mut self.0).into_vec();
+        memory.resize(size, 0);
+        self.0 = memory.into();
+    }
+
     /// Retrieves a byte from memory address.
     pub(crate) fn byte(&self, address: usize) -> u8 {
         self.0[address]
@@ -75,15 +126,36 @@ impl Memory {
         self.0[address + 1] = (word & 0xFF) as u8
     }
 
-    /// Loads the font set into the first 80 bytes of memory.
-    pub(crate) fn load_font_set(&mut self) -> Result<(), Chip8Error> {
-        // We load it in starting at where the program counter initializes to.
-        let mut current_memory_address = FONT_SET_OFFSET;
+    /// Makes sure the `len` bytes starting at `address` are all in memory.
+    /// The error holds the first address that isn't.
+    pub(crate) fn check_range(&self, address: usize, len: usize) -> Result<(), Chip8Error> {
+        if address + len > self.len() {
+            return Err(Chip8Error::MemoryOutOfBounds {
+                addr: address.max(self.len()),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// All of memory as a slice.
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
 
-        for byte in FONT_SET {
-            self.set_byte(current_memory_address, byte);
+    /// All of memory as a mutable slice.
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
 
-            current_memory_address += 1;
+    /// Loads `font` as the small font, and the large font set, into
+    /// interpreter memory.
+    pub(crate) fn load_font_set(&mut self, font: &[u8; 80]) -> Result<(), Chip8Error> {
+        for (i, &byte) in font.iter().enumerate() {
+            self.set_byte(FONT_SET_OFFSET + i, byte);
+        }
+        for (i, byte) in LARGE_FONT_SET.into_iter().enumerate() {
+            self.set_byte(LARGE_FONT_SET_OFFSET + i, byte);
         }
 
         Ok(())
@@ -95,31 +167,41 @@ impl Chip8 {
     /// You can now load a program with [`Self::load_program`].
     pub fn initialize(&mut self) -> Result<(), Chip8Error> {
         // Clear memory
-        self.memory = Memory::default();
+        self.memory = Memory::new(self.memory_size());
+        if let Some(coverage) = &mut self.coverage {
+            coverage.resize(self.memory.len(), false);
+        }
 
         // Clear screen
         self.screen = Screen::default();
 
         self.registers = [0; 16];
         self.index_register = 0;
-        self.program_counter = PROGRAM_OFFSET as u16;
+        self.program_counter = self.load_address();
 
         // Set the stack pointer to the value just under the stack, so that the
         // next push starts at bottom of the stack window.
-        self.stack_pointer = stack::STACK_WINDOW_BOTTOM + 1;
+        self.stack_pointer = stack::STACK_WINDOW_BOTTOM;
 
         self.delay_timer = DelayTimer::default();
         self.sound_timer = SoundTimer::default();
-        self.key_pressed = None;
-
-        if let Some(frame_handle) = &self.frame_handle {
-            frame_handle
-                .send(Box::new(self.screen.get().clone()))
-                .unwrap();
+        self.timer_accumulator = Duration::ZERO;
+        self.waiting_for_vblank = false;
+        self.idle = false;
+        self.stats = Stats::default();
+        if let Some(seed) = self.seed {
+            self.set_seed(seed);
         }
-        self.needs_program_restart = false;
+        self.keypad.release_all();
+        self.awaited_key = None;
+        self.audio_pattern = None;
+        self.audio_pitch = sound::DEFAULT_PITCH;
+        self.update_audio_pattern();
 
-        self.memory.load_font_set()?;
+        self.send_frame();
+
+        self.memory
+            .load_font_set(self.font.as_ref().unwrap_or(&FONT_SET))?;
 
         self.emulator_state
             .change_states(EmulatorState::InterpreterMemoryInitialized)?;
@@ -130,30 +212,148 @@ impl Chip8 {
         Ok(())
     }
 
+    /// Replaces the small font that `FX29` points `I` at with `font`, five
+    /// rows for each of the digits 0 to F like [`FONT_SET`] (which it's
+    /// easiest to start from). Requires that [`Self::initialize`] has been
+    /// called. The font sticks, so resetting or initializing again keeps
+    /// it.
+    pub fn load_font(&mut self, font: &[u8; 80]) -> Result<(), Chip8Error> {
+        if self.emulator_state == EmulatorState::InterpreterMemoryUninitialized {
+            return Err(Chip8Error::InterpreterMemoryIsUninitialized);
+        }
+
+        self.font = Some(*font);
+        self.memory.load_font_set(font)
+    }
+
     /// Loads a program into memory from raw bytes. Requires that [`Self::initialize`]
     /// has been called. You can now start emulation cycles with [`Self::cycle`].
     ///
     /// To load a new program, simply call [`Self::load_program`] again..
-    pub fn load_program(&mut self, program_bytes: Vec<u8>) -> Result<(), Chip8Error> {
+    ///
+    /// The program is kept around (see [`Self::program`]), so passing in an
+    /// `Arc<[u8]>` lets it be reloaded without copying it.
+    ///
+    /// The program goes at 0x200, unless [`Self::load_program_at`] picked a
+    /// different address. Fails without changing anything if the program
+    /// doesn't fit between there and the end of memory.
+    ///
+    /// Everything after the program is zeroed, so a ROM with an odd number
+    /// of bytes is padded out with a 0x00 and its last instruction still
+    /// reads as a whole word.
+    pub fn load_program(&mut self, program_bytes: impl Into<Arc<[u8]>>) -> Result<(), Chip8Error> {
+        self.load_program_at(program_bytes, self.load_address())
+    }
+
+    /// Like [`Self::load_program`], but puts the program at `address` and
+    /// starts running it from there. ETI-660 programs start at 0x600, for
+    /// example. The address sticks, so resetting (or loading another
+    /// program) keeps using it.
+    ///
+    /// The address has to be between 0x200 and the end of memory, since the
+    /// fonts and the stack live below 0x200.
+    pub fn load_program_at(
+        &mut self,
+        program_bytes: impl Into<Arc<[u8]>>,
+        address: u16,
+    ) -> Result<(), Chip8Error> {
+        let start = address as usize;
+        let memory_size = self.memory_size();
+        if !(PROGRAM_OFFSET..memory_size).contains(&start) {
+            return Err(Chip8Error::InvalidLoadAddress { addr: address });
+        }
+        let program_bytes = program_bytes.into();
+        let max = memory_size - start;
+        if program_bytes.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                size: program_bytes.len(),
+                max,
+            });
+        }
+
         self.emulator_state
             .change_states(EmulatorState::ProgramLoaded)?;
+        // The quirks may have changed since memory was set up. Everything
+        // past the fonts gets cleared below anyway.
+        self.memory.resize(memory_size);
+        self.program = program_bytes;
+        self.load_address = Some(address);
+        self.program_counter = address;
+        if let Some(code_writes) = &mut self.code_writes {
+            code_writes.clear();
+        }
+        self.code_warning = None;
+        self.warned_machine_code = false;
+        self.warned_unknown = false;
 
-        // We load it in starting at the program offset.
-        let mut current_memory_address = PROGRAM_OFFSET;
-
-        for byte in program_bytes {
-            self.memory.set_byte(current_memory_address, byte);
+        // We load it in starting at the load address.
+        let current_memory_address = start + self.program.len();
 
-            current_memory_address += 1;
+        for (i, &byte) in self.program.iter().enumerate() {
+            self.memory.set_byte(start + i, byte);
         }
 
         // We clear out the rest of the bytes and variables as well so that
         // nothing interferes with this program (under the assumption that this
         // can be called multiple times to switch programs).
-        for address in current_memory_address..MEMORY_SIZE {
+        for address in (PROGRAM_OFFSET..start).chain(current_memory_address..memory_size) {
             self.memory.set_byte(address, 0);
         }
 
         Ok(())
     }
+
+    /// How many bytes of memory programs get: 4 KB, or 64 KB with
+    /// [`Quirks::extended_memory`](crate::quirks::Quirks::extended_memory).
+    /// This takes effect the next time a program is loaded.
+    pub fn memory_size(&self) -> usize {
+        if self.quirks.extended_memory {
+            EXTENDED_MEMORY_SIZE
+        } else {
+            MEMORY_SIZE
+        }
+    }
+
+    /// Where programs are loaded and start running from. This is 0x200
+    /// unless [`Self::load_program_at`] picked something else.
+    pub fn load_address(&self) -> u16 {
+        self.load_address.unwrap_or(PROGRAM_OFFSET as u16)
+    }
+
+    /// Reads a program from `reader` (like stdin, or a file inside an
+    /// archive) and loads it with [`Self::load_program`].
+    ///
+    /// Stops reading once the program is too big to fit in memory, so
+    /// handing it something that isn't a ROM fails quickly.
+    pub fn load_program_from_reader<R: Read>(&mut self, reader: R) -> Result<(), Chip8Error> {
+        // The quirks may have shrunk memory below the load address since
+        // it was set.
+        let address = self.load_address();
+        let max_len = self
+            .memory_size()
+            .checked_sub(address as usize)
+            .ok_or(Chip8Error::InvalidLoadAddress { addr: address })?;
+        let mut program = Vec::new();
+        reader.take(max_len as u64 + 1).read_to_end(&mut program)?;
+
+        self.load_program(program)
+    }
+
+    /// Starts the loaded program over: the registers, stack, timers and
+    /// screen are cleared and the program counter goes back to the start,
+    /// but the program stays loaded.
+    pub fn reset(&mut self) -> Result<(), Chip8Error> {
+        if self.emulator_state != EmulatorState::ProgramLoaded {
+            return Err(Chip8Error::ProgramNotLoaded);
+        }
+
+        let program = self.program.clone();
+        self.initialize()?;
+        self.load_program(program)
+    }
+
+    /// The program that was last loaded with [`Self::load_program`].
+    pub fn program(&self) -> Arc<[u8]> {
+        self.program.clone()
+    }
 }
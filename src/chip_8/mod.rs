@@ -2,21 +2,53 @@
 
 #![warn(missing_docs, missing_debug_implementations)]
 
-use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+use std::time::Duration;
 
-use self::{instructions::Instruction, screen::Screen, sound::play_buzzer};
-use instructions::execution;
-use memory::Memory;
+use log::{info, warn};
+use rand::{rngs::StdRng, SeedableRng};
 
+use self::{
+    quirks::Quirks,
+    screen::Screen,
+    sound::{Buzzer, Waveform},
+};
+use memory::{Memory, PROGRAM_OFFSET};
+
+pub use builder::Chip8Builder;
+pub use instructions::{assemble, disassemble, AssembleError, Instruction};
+pub use memory::FONT_SET;
+pub use stack::STACK_DEPTH;
+
+mod builder;
 mod instructions;
-pub(crate) mod keypad;
+#[cfg(feature = "desktop")]
+pub mod keypad;
 mod memory;
+#[cfg(feature = "octo")]
+pub mod octo;
+pub mod quirks;
+pub mod replay;
+pub mod rewind;
 mod screen;
-pub(crate) mod sound;
+mod sha1;
+pub mod sound;
 mod stack;
+mod state;
 
+/// The width of the screen in pixels.
 pub const WIDTH: u32 = 64;
+/// The height of the screen in pixels.
 pub const HEIGHT: u32 = 32;
+/// The width of the screen in SUPER-CHIP's high resolution mode.
+pub const HIRES_WIDTH: u32 = 128;
+/// The height of the screen in SUPER-CHIP's high resolution mode.
+pub const HIRES_HEIGHT: u32 = 64;
+
+/// How often the delay and sound timers count down (60Hz).
+pub const TIMER_INTERVAL: Duration = Duration::from_nanos(1_000_000_000 / 60);
 
 /// An error used for errors related to the operation of the CHIP-8 emulator.
 #[allow(missing_docs)]
@@ -32,6 +64,12 @@ pub enum Chip8Error {
     StackOverflow,
     #[error("Stack underflow")]
     StackUnderflow,
+    /// Used when a save state can't be loaded.
+    #[error("Invalid save state: {reason}")]
+    InvalidSaveState { reason: &'static str },
+    /// Used when an instruction reads or writes past the end of memory.
+    #[error("Memory access out of bounds at 0x{addr:04X}")]
+    MemoryOutOfBounds { addr: usize },
     #[error("Program Restart Requested")]
     ProgramRestartRequested,
     /// Triggered when the emulator encounters instruction 0NNN.
@@ -40,24 +78,147 @@ pub enum Chip8Error {
     #[error("Program not compatible")]
     ProgramNotCompatible,
     /// Used when the raw word does not translate to an instruction,
-    /// like 0xFFFF.
-    #[error("Invalid Instruction 0x{instruction:04X}")]
-    InvalidInstruction { instruction: u16 },
+    /// like 0xFFFF. `address` is where it was read from, when running.
+    #[error("Invalid opcode 0x{instruction:04X}{}", at_address(.address))]
+    InvalidInstruction {
+        instruction: u16,
+        address: Option<u16>,
+    },
+    /// Used when a program doesn't fit in memory after the program offset.
+    #[error("ROM is {size} bytes, but only {max} bytes fit in memory")]
+    RomTooLarge { size: usize, max: usize },
+    /// Used when a buffer passed to [`Chip8::render_rgba`] doesn't fit the
+    /// screen.
+    #[error("Buffer is {actual} bytes, but the screen needs {expected}")]
+    InvalidBufferSize { expected: usize, actual: usize },
+    /// Used when a program is loaded below 0x200 or past the end of memory.
+    #[error("Can't load a program at 0x{addr:04X}")]
+    InvalidLoadAddress { addr: u16 },
+    /// Used when a program can't be read.
+    #[error("Could not read program: {0}")]
+    Io(#[from] std::io::Error),
     /// Used when the execution code for an instruction is unimplemented.
     #[error("Unimplemented instruction {instruction:#?}")]
     UnimplementedInstruction { instruction: Instruction },
 }
 
+/// Formats the " at 0x2A4" part of an error message, if there is an address.
+fn at_address(address: &Option<u16>) -> String {
+    address
+        .map(|address| format!(" at 0x{address:03X}"))
+        .unwrap_or_default()
+}
+
 /// A timer that counts down at 60Hz. If above 0, the timer will be "active"
 /// and count down to 0. At this point, a sound plays.  
 #[derive(Debug, Default, Copy, Clone)]
 pub struct DelayTimer(pub u8);
 
-/// A timer that counts down at 60Hz. If above 0, the timer will be "active"
-/// and count down to 0. At this point, a sound plays.  
+/// A timer that counts down at 60Hz. While above 0, the timer will be "active"
+/// and the buzzer plays until it counts down to 0.
 #[derive(Debug, Default, Copy, Clone)]
 pub struct SoundTimer(pub u8);
 
+/// Whether the frontend should be running the emulator.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RunState {
+    /// Cycles run freely at the configured speed.
+    #[default]
+    Running,
+    /// No cycles run until the emulator is resumed or stepped.
+    Paused,
+    /// Runs exactly one cycle with [`Chip8::step`], then goes back to
+    /// [`RunState::Paused`].
+    Step,
+    /// Runs one 60Hz frame with [`Chip8::step_frame`], then goes back to
+    /// [`RunState::Paused`].
+    StepFrame,
+}
+
+/// What happened during a call to [`Chip8::cycle`], so the frontend can
+/// react without looking at the emulator's internals.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CycleOutcome {
+    /// An instruction ran, and it wasn't any of the ones below.
+    Executed,
+    /// `00E0` cleared the screen.
+    ScreenCleared,
+    /// `DXYN` drew a sprite.
+    Drew {
+        /// True if the sprite turned off a pixel that was on.
+        collision: bool,
+    },
+    /// The sound timer was set while the buzzer was off.
+    SoundStarted,
+    /// `FX0A` is still waiting for a key to be pressed and released.
+    WaitingForKey,
+    /// Nothing ran, because [`Quirks::display_wait`] is waiting for the
+    /// next timer tick after a draw.
+    WaitingForDisplay,
+    /// `1NNN` jumped to itself. Programs do this to wait for the next timer
+    /// tick, so frontends can sleep until then (see
+    /// [`Chip8::time_to_next_tick`]) instead of running it over and over.
+    Idle,
+    /// Nothing ran, because the instruction at this address has a
+    /// breakpoint. The emulator is now paused.
+    Breakpoint(u16),
+    /// The instruction changed a register added with
+    /// [`Chip8::add_register_watch`]. The emulator is now paused.
+    RegisterChanged {
+        /// The register, from 0 to F.
+        vx: u8,
+        /// Its value before the instruction ran.
+        old: u8,
+        /// Its value now.
+        new: u8,
+    },
+    /// The instruction changed a byte added with
+    /// [`Chip8::add_memory_watch`]. The emulator is now paused.
+    MemoryChanged {
+        /// The address of the byte.
+        address: u16,
+        /// Its value before the instruction ran.
+        old: u8,
+        /// Its value now.
+        new: u8,
+    },
+    /// The guard from [`Chip8::set_code_guard`] caught the program counter
+    /// going somewhere it probably shouldn't. The instruction still ran.
+    CodeWarning {
+        /// Where the instruction was read from.
+        address: u16,
+        /// What looks wrong about it.
+        warning: CodeWarning,
+    },
+}
+
+/// Signs that a program is running something that isn't code, reported by
+/// [`CycleOutcome::CodeWarning`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodeWarning {
+    /// The instruction is below 0x200, where the fonts and stack are.
+    ReservedMemory,
+    /// The program wrote the instruction itself this run, with `FX33` or
+    /// `FX55`. Some programs do this on purpose, but it is usually a jump
+    /// into data.
+    ModifiedCode,
+}
+
+/// Counts of what the program has done since it was loaded, from
+/// [`Chip8::stats`]. Handy for seeing how a ROM behaves, or comparing runs
+/// against another interpreter.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// How many instructions have run.
+    pub instructions: u64,
+    /// How many sprites `DXYN` has drawn.
+    pub draws: u64,
+    /// How many of those draws turned off a pixel that was on.
+    pub collisions: u64,
+    /// The most return addresses that were on the stack at once.
+    pub max_stack_depth: u16,
+}
+
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
 enum EmulatorState {
     #[default]
@@ -98,96 +259,697 @@ pub struct Chip8 {
     program_counter: u16,
     /// Points to the top of the stack.
     stack_pointer: u16,
+    /// See [`DelayTimer`] for more information.
     pub delay_timer: DelayTimer,
     /// See [`SoundTimer`] for more information.
     pub sound_timer: SoundTimer,
+    /// Real time that has passed since the timers last counted down.
+    timer_accumulator: Duration,
     emulator_state: EmulatorState,
-    /// The current key that is pressed down.
-    pub key_pressed: Option<u8>,
+    /// See [`Keypad`] for more information.
+    pub keypad: Keypad,
+    /// The key `FX0A` saw pressed, which it takes once it is released.
+    awaited_key: Option<u8>,
     /// If this is true, then we need to redraw the frame.
     pub needs_redraw: bool,
-    pub needs_program_restart: bool,
+    /// See [`RunState`] for more information.
+    pub run_state: RunState,
+    /// See [`Quirks`] for more information.
+    pub quirks: Quirks,
+    /// Addresses that pause the emulator right before the instruction
+    /// there runs.
+    breakpoints: HashSet<u16>,
+    /// The breakpoint we last paused at. Resuming (or stepping) from it runs
+    /// the instruction instead of pausing again.
+    breakpoint_hit: Option<u16>,
+    /// Registers that pause the emulator when they change.
+    register_watches: HashSet<u8>,
+    /// Addresses that pause the emulator when they change, along with their
+    /// value at the start of the current cycle.
+    memory_watches: BTreeMap<u16, u8>,
+    /// Addresses the program has written to since it was loaded, when
+    /// [`Self::set_code_guard`] is on.
+    code_writes: Option<HashSet<u16>>,
+    /// What the code guard last warned about, so running through a whole
+    /// stretch of data only warns once.
+    code_warning: Option<CodeWarning>,
+    /// See [`Self::coverage`].
+    coverage: Option<Vec<bool>>,
+    /// See [`Self::stats`].
+    stats: Stats,
+    /// The font from [`Self::load_font`], which is loaded instead of
+    /// [`FONT_SET`] from then on.
+    font: Option<[u8; 80]>,
+    /// The SUPER-CHIP "RPL user flags", which `FX75` and `FX85` save
+    /// registers to. On the HP-48 these survived turning the calculator
+    /// off, so they aren't cleared when the emulator is initialized.
+    rpl_flags: [u8; 8],
+    /// Set by `DXYN` when [`Quirks::display_wait`] is on, and cleared on the
+    /// next timer tick.
+    waiting_for_vblank: bool,
+    /// Whether the last cycle was a `1NNN` jumping to itself, so we only
+    /// log going idle once.
+    idle: bool,
+    /// While [`Self::set_coalesce_draws`] is on, every pixel lit so far this
+    /// frame, which gets sent on the next timer tick.
+    coalesced_frame: Option<Vec<u8>>,
+    /// Whether the screen changed since the coalesced frame was last sent.
+    coalesced_changed: bool,
+    /// The XO-CHIP audio pattern loaded by `F002`, if there is one yet.
+    audio_pattern: Option<[u8; 16]>,
+    /// The XO-CHIP pitch set by `FX3A`, see [`Self::audio_pitch`].
+    audio_pitch: u8,
+    /// See [`Self::set_strict_machine_code`].
+    strict_machine_code: bool,
+    /// Whether we've warned about skipping a 0NNN since the program was
+    /// loaded. A program that runs off into empty memory hits thousands.
+    warned_machine_code: bool,
+    /// See [`Self::set_lenient`].
+    lenient: bool,
+    /// Like `warned_machine_code`, for invalid opcodes skipped while
+    /// [`Self::set_lenient`] is on.
+    warned_unknown: bool,
+    /// Used by `CXNN`.
+    rng: Rng,
+    /// See [`Self::set_seed`].
+    seed: Option<u64>,
+    /// See [`Self::cycle_count`].
+    cycle_count: u64,
+    /// See [`Self::cycle_cost`].
+    cycle_cost: u32,
+    /// See [`Self::load_address`].
+    load_address: Option<u16>,
+    /// The bytes of the program that was last loaded, so it can be loaded
+    /// again without holding onto another copy.
+    program: Arc<[u8]>,
+    /// See [`Buzzer`] for more information.
+    buzzer: Buzzer,
     frame_handle: Option<Sender<Box<[u8]>>>,
-    input_handle: Option<Receiver<Result<Option<u8>, Chip8Error>>>,
+    input_handle: Option<Receiver<Result<KeyEvent, Chip8Error>>>,
+}
+
+/// A [`StdRng`] that is seeded from entropy by default. Without the
+/// `entropy` feature there is nothing to seed it from, so it always starts
+/// from the same seed.
+#[derive(Debug)]
+struct Rng(StdRng);
+
+impl Default for Rng {
+    #[cfg(feature = "entropy")]
+    fn default() -> Self {
+        Self(StdRng::from_entropy())
+    }
+
+    #[cfg(not(feature = "entropy"))]
+    fn default() -> Self {
+        Self(StdRng::seed_from_u64(0))
+    }
 }
 
 impl Chip8 {
     /// Creates a new emulator with empty memory. You still have to initialize
     /// to with [`Self::initialize`] to load programs.
+    ///
+    /// The frontend sends a [`KeyEvent`] through `input_handle` every time a
+    /// key goes down or comes back up, or an error to stop the emulator.
     pub fn new(
         frame_handle: Sender<Box<[u8]>>,
-        input_handle: Receiver<Result<Option<u8>, Chip8Error>>,
+        input_handle: Receiver<Result<KeyEvent, Chip8Error>>,
     ) -> Self {
-        Self {
-            frame_handle: Some(frame_handle),
-            input_handle: Some(input_handle),
-            ..Default::default()
+        Chip8Builder::new()
+            .channels(frame_handle, input_handle)
+            .build()
+    }
+
+    /// Starts a [`Chip8Builder`], for setting up more than [`Self::new`]
+    /// does in one go.
+    pub fn builder() -> Chip8Builder {
+        Chip8Builder::new()
+    }
+
+    /// Seeds the random number generator used by `CXNN`, so that a run with
+    /// the same seed and the same input always plays out the same way. The
+    /// generator is reseeded with this every time the emulator is
+    /// initialized. Without a seed, it is seeded from entropy (or from a
+    /// fixed seed, without the `entropy` feature).
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+        self.rng = Rng(StdRng::seed_from_u64(seed));
+    }
+
+    /// Creates an emulator without any frame or input channels, and without
+    /// sound. Read the screen with [`Self::screen`] and press keys with
+    /// [`Self::set_key`] instead. Useful for tests and embedding.
+    pub fn headless() -> Self {
+        Chip8Builder::new().build()
+    }
+
+    /// The current contents of the screen, one byte per pixel (see
+    /// [`Self::screen_size`]). A pixel is 1 if it is on and 0 if it is off.
+    /// XO-CHIP programs can also draw to a second plane, which is bit 1, so
+    /// their pixels go up to 3.
+    pub fn screen(&self) -> Vec<u8> {
+        self.screen.pixels()
+    }
+
+    /// A 64 bit FNV-1a hash of [`Self::screen`], for checking a run against
+    /// a known good one without keeping the whole screen around. Set a seed
+    /// with [`Self::set_seed`] first if the program uses random numbers.
+    pub fn screen_hash(&self) -> u64 {
+        const OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+        const PRIME: u64 = 0x0000_0100_0000_01B3;
+        self.screen
+            .pixels()
+            .iter()
+            .fold(OFFSET_BASIS, |hash, &pixel| {
+                (hash ^ pixel as u64).wrapping_mul(PRIME)
+            })
+    }
+
+    /// Fills `out` with the screen as RGBA pixels, row by row from the top
+    /// left, using `fg` for lit pixels and `bg` for the rest. This is for
+    /// frontends that draw into their own surface. `out` has to be exactly
+    /// 4 bytes per pixel at the current [`Self::screen_size`].
+    pub fn render_rgba(&self, fg: [u8; 4], bg: [u8; 4], out: &mut [u8]) -> Result<(), Chip8Error> {
+        let pixels = self.screen.pixels();
+        if out.len() != pixels.len() * 4 {
+            return Err(Chip8Error::InvalidBufferSize {
+                expected: pixels.len() * 4,
+                actual: out.len(),
+            });
+        }
+
+        for (&pixel, rgba) in pixels.iter().zip(out.chunks_exact_mut(4)) {
+            rgba.copy_from_slice(if pixel != 0 { &fg } else { &bg });
+        }
+
+        Ok(())
+    }
+
+    /// The width and height of the screen, which is [`WIDTH`] by [`HEIGHT`]
+    /// unless a SUPER-CHIP program switched to [`HIRES_WIDTH`] by
+    /// [`HIRES_HEIGHT`].
+    pub fn screen_size(&self) -> (u32, u32) {
+        (self.screen.width(), self.screen.height())
+    }
+
+    /// Presses or releases one of the 16 keys on the keypad. Keys above
+    /// 0xF are ignored.
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        self.keypad.set(key, pressed);
+    }
+
+    /// The general purpose registers V0-VF.
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    /// The index register (I).
+    pub fn index(&self) -> u16 {
+        self.index_register
+    }
+
+    /// The address of the next instruction to run.
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    /// All of the emulator's memory, including the font set, the stack and
+    /// the program.
+    pub fn memory(&self) -> &[u8] {
+        self.memory.as_slice()
+    }
+
+    /// Decodes the loaded program, pairing each instruction with its
+    /// address. This reads memory as it is now, so it sees anything the
+    /// program wrote over itself, and stops at the end of the program
+    /// rather than going through all of memory. Words that don't decode
+    /// come back as errors, and decoding carries on after them.
+    pub fn instructions(
+        &self,
+    ) -> impl Iterator<Item = (u16, Result<Instruction, Chip8Error>)> + '_ {
+        let end = self.load_address() as usize + self.program.len();
+        let mut address = self.load_address() as usize;
+        std::iter::from_fn(move || {
+            if address >= end {
+                return None;
+            }
+            let instruction = self.decode(address as u16);
+            let current = address as u16;
+            address += instruction.as_ref().map_or(2, Instruction::size) as usize;
+            Some((current, instruction))
+        })
+    }
+
+    /// Pauses emulation (see [`RunState::Paused`]) when the program counter
+    /// reaches `address`, before the instruction there runs.
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Removes a breakpoint added with [`Self::add_breakpoint`].
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.remove(&address);
+    }
+
+    /// Pauses emulation right after an instruction changes register VX
+    /// (0 to F). [`Self::cycle`] reports the old and new values.
+    pub fn add_register_watch(&mut self, vx: u8) {
+        self.register_watches.insert(vx & 0xF);
+    }
+
+    /// Removes a watch added with [`Self::add_register_watch`].
+    pub fn remove_register_watch(&mut self, vx: u8) {
+        self.register_watches.remove(&(vx & 0xF));
+    }
+
+    /// Pauses emulation right after an instruction changes the byte at
+    /// `address`. Writing the value that is already there doesn't count.
+    /// [`Self::cycle`] reports the old and new values.
+    pub fn add_memory_watch(&mut self, address: u16) -> Result<(), Chip8Error> {
+        self.memory.check_range(address as usize, 1)?;
+        self.memory_watches.insert(address, 0);
+        Ok(())
+    }
+
+    /// Removes a watch added with [`Self::add_memory_watch`].
+    pub fn remove_memory_watch(&mut self, address: u16) {
+        self.memory_watches.remove(&address);
+    }
+
+    /// Reports a [`CycleOutcome::CodeWarning`] when the program counter
+    /// runs into reserved memory or into bytes the program wrote itself.
+    /// This is meant for debugging ROMs with broken jumps, so it is off by
+    /// default.
+    pub fn set_code_guard(&mut self, enabled: bool) {
+        self.code_writes = enabled.then(HashSet::new);
+        self.code_warning = None;
+    }
+
+    /// Starts or stops keeping track of which bytes of memory have run as
+    /// instructions, see [`Self::coverage`]. It's off by default, since it
+    /// costs a little on every cycle. Turning it on starts over.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.coverage = enabled.then(|| vec![false; self.memory.len()]);
+    }
+
+    /// Which bytes of memory have run as instructions since
+    /// [`Self::set_coverage`] was turned on, indexed by address. Together
+    /// with [`Self::instructions`], this tells the code in a ROM apart from
+    /// its data. Restarting keeps what ran before.
+    pub fn coverage(&self) -> Option<&[bool]> {
+        self.coverage.as_deref()
+    }
+
+    /// What the program has done since it was loaded (or the emulator was
+    /// last [reset](Self::reset)).
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Turns coalescing draws on or off. While it's on, frames only go to
+    /// the frontend on timer ticks, with every pixel that was lit at any
+    /// point during the frame. Games that erase a sprite and draw it again
+    /// in the same frame then never show it erased, which hides most of the
+    /// flicker without the blur of fading pixels out.
+    pub fn set_coalesce_draws(&mut self, enabled: bool) {
+        self.coalesced_frame = enabled.then(|| self.screen.pixels());
+        self.coalesced_changed = false;
+    }
+
+    /// Makes `0NNN` fail with [`Chip8Error::ProgramNotCompatible`] instead
+    /// of being skipped. We can't run the machine code it calls, so by
+    /// default it's skipped with a warning, which lets ROMs with a stray
+    /// one get further. If the routine mattered, the ROM may still go
+    /// wrong afterwards.
+    pub fn set_strict_machine_code(&mut self, enabled: bool) {
+        self.strict_machine_code = enabled;
+    }
+
+    /// Skips invalid opcodes with a warning (running them as
+    /// [`Instruction::Unknown`]) instead of failing with
+    /// [`Chip8Error::InvalidInstruction`]. Some ROMs have a stray bad word
+    /// that never mattered on the interpreter they were written for. Off by
+    /// default, since running into data usually means something is wrong.
+    pub fn set_lenient(&mut self, enabled: bool) {
+        self.lenient = enabled;
+    }
+
+    /// What's wrong with running the instruction at `address`, if the code
+    /// guard is on.
+    fn check_code(&self, address: u16) -> Option<CodeWarning> {
+        let code_writes = self.code_writes.as_ref()?;
+        if (address as usize) < PROGRAM_OFFSET {
+            Some(CodeWarning::ReservedMemory)
+        } else if code_writes.contains(&address)
+            || address
+                .checked_add(1)
+                .is_some_and(|next| code_writes.contains(&next))
+        {
+            Some(CodeWarning::ModifiedCode)
+        } else {
+            None
         }
     }
 
+    /// The first watched register or byte that is different from before the
+    /// instruction ran, if any.
+    fn changed_watch(&self, registers_before: &[u8; 16]) -> Option<CycleOutcome> {
+        let register = (0..16)
+            .filter(|vx| self.register_watches.contains(vx))
+            .find(|&vx| registers_before[vx as usize] != self.registers[vx as usize]);
+        if let Some(vx) = register {
+            return Some(CycleOutcome::RegisterChanged {
+                vx,
+                old: registers_before[vx as usize],
+                new: self.registers[vx as usize],
+            });
+        }
+
+        self.memory_watches
+            .iter()
+            .map(|(&address, &old)| (address, old, self.memory.byte(address as usize)))
+            .find(|&(_, old, new)| old != new)
+            .map(|(address, old, new)| CycleOutcome::MemoryChanged { address, old, new })
+    }
+
+    /// Prints the value of every register to stdout.
     pub fn print_all_registers(&self) {
         for i in 0x0..=0xF {
-            print!("Register {i} is {}\n", self.registers[i as usize]);
+            println!("Register {i} is {}", self.registers[i as usize]);
         }
     }
 
+    /// Prints the current instruction word to stdout.
     pub fn print_current_op(&self) {
-        print!("{}\n", self.memory.word(self.index_register as usize));
+        let address = self.program_counter as usize;
+        match self.memory.check_range(address, 2) {
+            Ok(()) => println!("{}", self.memory.word(address)),
+            Err(e) => println!("{e}"),
+        }
     }
     /// Runs a moves the emulator state by one cycle. Requires both the interpreter memory
     /// to be initialized via [`Self::initialize`] and a program to be loaded in with
     /// [`Self::load_program`].
-    pub fn cycle(&mut self) -> Result<(), Chip8Error> {
+    pub fn cycle(&mut self) -> Result<CycleOutcome, Chip8Error> {
         if self.emulator_state != EmulatorState::ProgramLoaded {
             return Err(Chip8Error::ProgramNotLoaded);
         }
-        if let Some(input_reciever) = &self.input_handle {
-            self.key_pressed = match input_reciever.try_recv() {
-                Ok(Ok(x)) => x,
-                Ok(Err(e)) => match e {
-                    Chip8Error::ProgramRestartRequested => {
-                        self.initialize()?;
-                        None
-                    }
-                    _ => panic!("{}", e),
-                },
-                Err(TryRecvError::Empty) => self.key_pressed,
-                _ => panic!("Error receiving keypress."),
+        self.cycle_count += 1;
+        self.cycle_cost = 1;
+        // Take everything the frontend sent since the last cycle, so keys
+        // pressed together all count on this one. Once the frontend hangs
+        // up, the keys stay where they were.
+        while let Some(message) = self
+            .input_handle
+            .as_ref()
+            .and_then(|input_reciever| input_reciever.try_recv().ok())
+        {
+            match message {
+                Ok(event) => self.keypad.apply(event),
+                Err(Chip8Error::ProgramRestartRequested) => self.reset()?,
+                Err(e) => return Err(e),
             }
         }
 
+        if self.waiting_for_vblank {
+            return Ok(CycleOutcome::WaitingForDisplay);
+        }
+
+        let address = self.program_counter;
+        if self.breakpoints.contains(&address) && self.breakpoint_hit != Some(address) {
+            info!("Hit breakpoint at 0x{:03X}", address);
+            self.breakpoint_hit = Some(address);
+            self.run_state = RunState::Paused;
+            return Ok(CycleOutcome::Breakpoint(address));
+        }
+        self.breakpoint_hit = None;
 
-        let raw = self.fetch();
-        let instruction = self.decode(raw)?;
+        // Only warn when we first run into something, rather than for every
+        // instruction after.
+        let last_code_warning = self.code_warning;
+        self.code_warning = self.check_code(address);
+        let code_warning = self.code_warning.filter(|&w| last_code_warning != Some(w));
+        match code_warning {
+            Some(CodeWarning::ReservedMemory) => {
+                warn!("Running reserved memory at 0x{address:03X}")
+            }
+            Some(CodeWarning::ModifiedCode) => {
+                warn!("Running code the program wrote at 0x{address:03X}")
+            }
+            None => {}
+        }
+
+        // Move past the instruction before running it, even if it's
+        // invalid, so that it can be stepped over.
+        let instruction = self.decode(address);
+        self.program_counter = self
+            .program_counter
+            .wrapping_add(instruction.as_ref().map_or(2, Instruction::size));
+        let instruction = match instruction {
+            Err(Chip8Error::InvalidInstruction { instruction, .. }) if self.lenient => {
+                Instruction::Unknown { raw: instruction }
+            }
+            instruction => instruction?,
+        };
+        if let Some(coverage) = &mut self.coverage {
+            let start = address as usize;
+            let end = (start + instruction.size() as usize).min(coverage.len());
+            if let Some(bytes) = coverage.get_mut(start..end) {
+                bytes.fill(true);
+            }
+        }
+        let sound_was_on = self.sound_timer.value() > 0;
+        let registers_before = self.registers;
+        for (&address, value) in &mut self.memory_watches {
+            *value = self.memory.byte(address as usize);
+        }
+        let outcome = match instruction {
+            Instruction::Clear => CycleOutcome::ScreenCleared,
+            Instruction::Draw { .. } => CycleOutcome::Drew { collision: false },
+            Instruction::AwaitKeyInput { .. } => CycleOutcome::WaitingForKey,
+            Instruction::Jump { nnn } if nnn == address => CycleOutcome::Idle,
+            _ => CycleOutcome::Executed,
+        };
+        let idle = outcome == CycleOutcome::Idle;
+        if idle && !self.idle {
+            info!("Idling at 0x{address:03X} until the next timer tick");
+        }
+        self.idle = idle;
         self.execute(instruction)?;
+        self.stats.instructions += 1;
+        if let CycleOutcome::Drew { .. } = outcome {
+            self.stats.draws += 1;
+            self.stats.collisions += u64::from(self.registers[0xF] == 1);
+        }
+        self.update_frame();
 
-        Ok(())
+        // Check the sound timer right away so the tone starts on the same
+        // cycle that sets it.
+        self.update_buzzer();
+
+        if let Some(watch) = self.changed_watch(&registers_before) {
+            info!("Watch triggered at 0x{:03X}: {watch:?}", address);
+            self.run_state = RunState::Paused;
+            return Ok(watch);
+        }
+        if let Some(warning) = code_warning {
+            return Ok(CycleOutcome::CodeWarning { address, warning });
+        }
+
+        Ok(match outcome {
+            CycleOutcome::Drew { .. } => CycleOutcome::Drew {
+                collision: self.registers[0xF] == 1,
+            },
+            // FX0A moves on once it has its key.
+            CycleOutcome::WaitingForKey if self.program_counter != address => {
+                CycleOutcome::Executed
+            }
+            _ if !sound_was_on && self.sound_timer.value() > 0 => CycleOutcome::SoundStarted,
+            outcome => outcome,
+        })
+    }
+
+    /// How many times [`Self::cycle`] has run, including cycles spent
+    /// waiting. This keeps counting across restarts, and is what
+    /// [`replay`] ties key presses to.
+    pub fn cycle_count(&self) -> u64 {
+        self.cycle_count
     }
 
-    /// Fetches the current instruction word and increments the PC by 2.
-    fn fetch(&mut self) -> u16 {
-        let word = self.memory.word(self.program_counter as usize);
+    /// How many instructions' worth of time the last call to [`Self::cycle`]
+    /// took. This is always 1, unless [`Quirks::draw_timing`] made a draw
+    /// take longer. Frontends should wait that many instruction times
+    /// before the next cycle.
+    pub fn cycle_cost(&self) -> u32 {
+        self.cycle_cost
+    }
 
-        // If we increment the PC before we pull an instruction from it,
-        // we're gonna have problems.
-        self.program_counter += 2;
+    /// Counts the timers down by however many 60Hz ticks fit in `elapsed`
+    /// real time. Leftover time is saved for the next call, so the timers
+    /// don't drift no matter how often this is called.
+    ///
+    /// The timers are frozen (and the buzzer is quiet) unless
+    /// [`Self::run_state`] is [`RunState::Running`], so a program doesn't
+    /// see its delay timer run out while paused.
+    pub fn update_timers(&mut self, elapsed: Duration) {
+        if self.run_state != RunState::Running {
+            self.buzzer.set_playing(false);
+            return;
+        }
+        self.timer_accumulator += elapsed;
 
-        word
+        while self.timer_accumulator >= TIMER_INTERVAL {
+            self.timer_accumulator -= TIMER_INTERVAL;
+            self.decrement_timers();
+        }
     }
 
-    /// Decodes the instruction word into an [`Instruction`]
-    fn decode(&self, raw: u16) -> Result<Instruction, Chip8Error> {
-        Instruction::new(raw)
+    /// How long until the timers next count down, going by the real time
+    /// given to [`Self::update_timers`].
+    pub fn time_to_next_tick(&self) -> Duration {
+        TIMER_INTERVAL.saturating_sub(self.timer_accumulator)
     }
 
-    /// Executes the provided instruction.
+    /// Decrements both timers by one tick.
+    fn decrement_timers(&mut self) {
+        // A timer tick is as close as we get to a vertical blank.
+        self.waiting_for_vblank = false;
+        self.send_coalesced_frame();
+        self.delay_timer.decrement();
+        self.sound_timer.decrement();
+        self.update_buzzer();
+    }
+
+    /// Changes the tone played by the buzzer. See [`Buzzer::set_tone`].
+    pub fn set_buzzer_tone(&self, frequency: u32, waveform: Waveform) {
+        self.buzzer.set_tone(frequency, waveform);
+    }
+
+    /// Mutes or unmutes the buzzer, see [`Buzzer::set_muted`]. Programs
+    /// run the same either way.
+    pub fn set_buzzer_muted(&self, muted: bool) {
+        self.buzzer.set_muted(muted);
+    }
+
+    /// Plays the buzzer for as long as the sound timer is active.
+    fn update_buzzer(&self) {
+        self.buzzer.set_playing(self.sound_timer.value() > 0);
+    }
+
+    /// The audio pattern the program loaded with `F002`, if it has.
+    pub fn audio_pattern(&self) -> Option<&[u8; 16]> {
+        self.audio_pattern.as_ref()
+    }
+
+    /// The pitch the program set with `FX3A`. The pattern plays at
+    /// [`sound::pattern_rate`] samples a second for it.
+    pub fn audio_pitch(&self) -> u8 {
+        self.audio_pitch
+    }
+
+    /// Has the buzzer play the audio pattern, if the program loaded one and
+    /// [`Quirks::audio_pattern`] is on, or its own tone otherwise.
+    pub(crate) fn update_audio_pattern(&self) {
+        let pattern = self.audio_pattern.filter(|_| self.quirks.audio_pattern);
+        self.buzzer.set_pattern(pattern, self.audio_pitch);
+    }
+
+    /// Runs a single cycle (see [`Self::cycle`]) and logs the instruction that
+    /// was executed. Used for stepping through a program while paused.
+    pub fn step(&mut self) -> Result<CycleOutcome, Chip8Error> {
+        let address = self.program_counter;
+        let instruction = self.decode(address);
+
+        let outcome = self.cycle()?;
+        // Show each step as it is right away, rather than waiting for a
+        // timer tick that won't come while paused.
+        if let Some(coalesced) = &mut self.coalesced_frame {
+            *coalesced = self.screen.pixels();
+        }
+        self.send_coalesced_frame();
+
+        match instruction {
+            Ok(instruction) => info!("Stepped 0x{address:03X}: {instruction}"),
+            Err(e) => info!("Stepped 0x{address:03X}: {e}"),
+        }
+
+        Ok(outcome)
+    }
+
+    /// Runs one frame's worth of cycles while paused, so the screen changes
+    /// the way it would in a 60th of a second of running. Returns how many
+    /// instructions' worth of time that took.
+    ///
+    /// A frame is complete once `cycles_per_frame` instructions' worth of
+    /// time has gone by (counting slow draws with [`Self::cycle_cost`]),
+    /// which is when the timers tick and the display wait ends. It doesn't
+    /// stop at the first draw, since most games draw several sprites a
+    /// frame and stopping partway shows one half drawn. Breakpoints and
+    /// watches still stop it early, without ticking the timers.
+    pub fn step_frame(&mut self, cycles_per_frame: u32) -> Result<u32, Chip8Error> {
+        let mut ran = 0;
+        while ran < cycles_per_frame {
+            let outcome = self.cycle()?;
+            ran += self.cycle_cost;
+            if let CycleOutcome::Breakpoint(_)
+            | CycleOutcome::RegisterChanged { .. }
+            | CycleOutcome::MemoryChanged { .. } = outcome
+            {
+                info!("Stepped {ran} cycles, stopped by {outcome:?}");
+                return Ok(ran);
+            }
+        }
+        self.decrement_timers();
+
+        info!(
+            "Stepped a frame of {ran} cycles, now at 0x{:03X}",
+            self.program_counter
+        );
+        Ok(ran)
+    }
+
+    /// Decodes the instruction at `address` into an [`Instruction`].
+    fn decode(&self, address: u16) -> Result<Instruction, Chip8Error> {
+        // A computed jump can leave the PC on the last byte of memory, with
+        // the second half of the word past the end.
+        self.memory.check_range(address as usize, 2)?;
+        let raw = self.memory.word(address as usize);
+        if raw == Instruction::LONG_PREFIX {
+            let next = address as usize + 2;
+            self.memory.check_range(next, 2)?;
+            return Ok(Instruction::SetIndexLong {
+                nnn: self.memory.word(next),
+            });
+        }
+
+        Instruction::new(raw).map_err(|e| match e {
+            Chip8Error::InvalidInstruction { instruction, .. } => Chip8Error::InvalidInstruction {
+                instruction,
+                address: Some(address),
+            },
+            e => e,
+        })
+    }
 
+    /// Executes the provided instruction.
     fn execute(&mut self, instruction: Instruction) -> Result<(), Chip8Error> {
         match instruction {
-            Instruction::CallMachineCodeRoutine => {
-                return Err(Chip8Error::UnimplementedInstruction { instruction })
+            Instruction::CallMachineCodeRoutine { nnn } => {
+                if self.strict_machine_code {
+                    return Err(Chip8Error::ProgramNotCompatible);
+                }
+                if !self.warned_machine_code {
+                    warn!("Skipping the machine code routine at 0x{nnn:03X}, which can't be run");
+                    self.warned_machine_code = true;
+                }
             }
             Instruction::Clear => self.instruction_clear(),
+            Instruction::ScrollDown { n } => self.instruction_scroll_down(n),
+            Instruction::ScrollRight => self.instruction_scroll_right(),
+            Instruction::ScrollLeft => self.instruction_scroll_left(),
+            Instruction::LowResolution => self.instruction_low_resolution(),
+            Instruction::HighResolution => self.instruction_high_resolution(),
             Instruction::Return => self.instruction_return()?,
             Instruction::Jump { nnn } => self.instruction_jump(nnn),
             Instruction::Call { nnn } => self.instruction_call(nnn)?,
@@ -208,20 +970,25 @@ impl Chip8 {
             Instruction::BitwiseXor { vx, vy } => self.instruction_bitwise_xor(vx, vy),
             Instruction::Add { vx, vy } => self.instruction_add(vx, vy),
             Instruction::Subtract { vx, vy } => self.instruction_subtract(vx, vy),
-            Instruction::RightShift { vx } => self.instruction_right_shift(vx),
+            Instruction::RightShift { vx, vy } => self.instruction_right_shift(vx, vy),
             Instruction::SetVxToVyMinusVx { vx, vy } => {
                 self.instruction_set_vx_to_vy_minus_vx(vx, vy)
             }
-            Instruction::LeftShift { vx } => self.instruction_left_shift(vx),
+            Instruction::LeftShift { vx, vy } => self.instruction_left_shift(vx, vy),
             Instruction::SkipIfRegisterVxNotEqualsVy { vx, vy } => {
                 self.instruction_skip_if_register_vx_not_equals_vy(vx, vy)
             }
-            Instruction::SetIndexRegister { nnn } => self.instruction_set_index_register(nnn),
+            Instruction::SetIndexRegister { nnn } | Instruction::SetIndexLong { nnn } => {
+                self.instruction_set_index_register(nnn)
+            }
             Instruction::JumpWithPcOffset { nnn } => self.instruction_jump_with_pc_offset(nnn),
             Instruction::Random { vx, nn } => self.instruction_random(vx, nn),
-            Instruction::Draw { vx, vy, n } => self.instruction_draw(vx, vy, n),
+            Instruction::Draw { vx, vy, n } => self.instruction_draw(vx, vy, n)?,
             Instruction::SkipIfKeyPressed { vx } => self.instruction_skip_if_key_pressed(vx),
             Instruction::SkipIfKeyNotPressed { vx } => self.instruction_skip_if_key_not_pressed(vx),
+            Instruction::SelectPlanes { planes } => self.instruction_select_planes(planes),
+            Instruction::LoadAudioPattern => self.instruction_load_audio_pattern()?,
+            Instruction::SetPitch { vx } => self.instruction_set_pitch(vx),
             Instruction::SetVxToDelayTimer { vx } => self.instruction_set_vx_to_delay_timer(vx),
             Instruction::AwaitKeyInput { vx } => self.instruction_await_key_input(vx),
             Instruction::SetDelayTimer { vx } => self.instruction_set_delay_timer(vx),
@@ -230,29 +997,120 @@ impl Chip8 {
             Instruction::SetIndexToFontCharacter { vx } => {
                 self.instruction_set_index_to_font_character(vx)
             }
+            Instruction::SetIndexToLargeFontCharacter { vx } => {
+                self.instruction_set_index_to_large_font_character(vx)
+            }
             Instruction::SetIndexToBinaryCodedVx { vx } => {
-                self.instruction_set_index_to_binary_coded_vx(vx)
+                self.instruction_set_index_to_binary_coded_vx(vx)?
             }
-            Instruction::DumpRegisters { vx } => self.instruction_dump_registers(vx),
-            Instruction::LoadRegisters { vx } => self.instruction_load_registers(vx),
-            Instruction::Unknown => self.instruction_unknown(),
+            Instruction::SaveFlags { vx } => self.instruction_save_flags(vx),
+            Instruction::LoadFlags { vx } => self.instruction_load_flags(vx),
+            Instruction::DumpRegisters { vx } => self.instruction_dump_registers(vx)?,
+            Instruction::LoadRegisters { vx } => self.instruction_load_registers(vx)?,
+            Instruction::Unknown { raw } => self.instruction_unknown(raw),
         }
 
         Ok(())
     }
 }
 impl SoundTimer {
+    /// How many 60Hz ticks are left.
+    pub fn value(&self) -> u8 {
+        self.0
+    }
+
+    /// Starts counting down from `value`, like `FX18`.
+    pub fn set(&mut self, value: u8) {
+        self.0 = value;
+    }
+
+    /// Counts down by one, stopping at 0.
     pub fn decrement(&mut self) {
         if self.0 > 0 {
             self.0 -= 1;
-            play_buzzer();
         }
     }
 }
 impl DelayTimer {
+    /// How many 60Hz ticks are left.
+    pub fn value(&self) -> u8 {
+        self.0
+    }
+
+    /// Starts counting down from `value`, like `FX15`.
+    pub fn set(&mut self, value: u8) {
+        self.0 = value;
+    }
+
+    /// Counts down by one, stopping at 0.
     pub fn decrement(&mut self) {
         if self.0 > 0 {
             self.0 -= 1;
         }
     }
 }
+
+/// A key going down or coming back up, as sent by the frontend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyEvent {
+    /// The key is now held down.
+    Pressed(u8),
+    /// The key is no longer held down.
+    Released(u8),
+}
+
+/// Which of the 16 keys are held down right now, one bit per key, so any
+/// number of them can be held at once. [`KeyEvent`]s from the frontend keep
+/// this up to date, and `EX9E`, `EXA1` and `FX0A` read it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Keypad(u16);
+
+impl Keypad {
+    /// Whether `key` is held down. Keys above 0xF never are.
+    pub fn is_pressed(&self, key: u8) -> bool {
+        key <= 0xF && self.0 & (1 << key) != 0
+    }
+
+    /// Presses or releases `key`. Keys above 0xF are ignored.
+    pub fn set(&mut self, key: u8, pressed: bool) {
+        if key > 0xF {
+            return;
+        }
+        if pressed {
+            self.0 |= 1 << key;
+        } else {
+            self.0 &= !(1 << key);
+        }
+    }
+
+    /// Every key that is held down, lowest first.
+    pub fn pressed(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..=0xF).filter(|&key| self.is_pressed(key))
+    }
+
+    /// Releases every key.
+    pub fn release_all(&mut self) {
+        self.0 = 0;
+    }
+
+    /// Presses or releases a key, as the event says.
+    pub fn apply(&mut self, event: KeyEvent) {
+        match event {
+            KeyEvent::Pressed(key) => self.set(key, true),
+            KeyEvent::Released(key) => self.set(key, false),
+        }
+    }
+
+    /// The events that would take the keys from how they are here to how
+    /// they are in `other`, lowest key first. Frontends that poll which keys
+    /// are held can send these.
+    pub fn changes_to(&self, other: Keypad) -> impl Iterator<Item = KeyEvent> + '_ {
+        (0..=0xF).filter_map(
+            move |key| match (self.is_pressed(key), other.is_pressed(key)) {
+                (false, true) => Some(KeyEvent::Pressed(key)),
+                (true, false) => Some(KeyEvent::Released(key)),
+                _ => None,
+            },
+        )
+    }
+}
@@ -0,0 +1,780 @@
+//! Assembles [Octo](https://github.com/JohnEarnest/Octo) source (`.8o`
+//! files), so a ROM can be run straight from the code it's written in.
+//! Enabled with the `octo` feature.
+//!
+//! This covers the CHIP-8, SUPER-CHIP and XO-CHIP instructions, labels,
+//! `:alias`, `:const`, data bytes and Octo's structured control flow
+//! (`if ... then`, `if ... begin ... else ... end` and `loop ... while ...
+//! again`). Macros, `:calc`, `:org` and the other directives aren't
+//! supported, and neither are the comparisons that need VF (`<`, `>=`...)
+//! or the `scroll-up` and `exit` instructions, which the emulator can't run.
+//!
+//! ```text
+//! : main
+//!     v0 := 0
+//!     loop
+//!         i := hex v0
+//!         sprite v0 v0 5
+//!         v0 += 1
+//!         while v0 != 16
+//!     again
+//! ```
+
+use std::collections::HashMap;
+
+use crate::chip_8::memory::PROGRAM_OFFSET;
+
+/// Something wrong with a program's source.
+#[derive(Clone, Debug, thiserror::Error, PartialEq, Eq)]
+pub enum OctoError {
+    /// A word that doesn't belong where it is.
+    #[error("Line {line}: expected {expected}, found `{found}`")]
+    Unexpected {
+        /// The line number, starting at 1.
+        line: usize,
+        /// What should have been there.
+        expected: &'static str,
+        /// What was there instead.
+        found: String,
+    },
+    /// The source stopped partway through a statement.
+    #[error("Expected {expected}, but the source ended")]
+    UnexpectedEnd {
+        /// What should have come next.
+        expected: &'static str,
+    },
+    /// A label that is jumped to or called, but never defined.
+    #[error("Line {line}: `{name}` is never defined")]
+    UnknownLabel {
+        /// The line the label is used on.
+        line: usize,
+        /// The label.
+        name: String,
+    },
+    /// A label, `:alias` or `:const` with a name that's already taken.
+    #[error("Line {line}: `{name}` is already defined")]
+    DuplicateName {
+        /// The line of the second definition.
+        line: usize,
+        /// The name.
+        name: String,
+    },
+    /// A number too big (or small) for where it's used.
+    #[error("Line {line}: {value} doesn't fit in {bits} bits")]
+    OutOfRange {
+        /// The line number, starting at 1.
+        line: usize,
+        /// The number.
+        value: i64,
+        /// How many bits there are for it.
+        bits: u32,
+    },
+    /// A directive (or comparison, or instruction) that this assembler
+    /// doesn't know how to handle yet.
+    #[error("Line {line}: `{token}` isn't supported")]
+    Unsupported {
+        /// The line number, starting at 1.
+        line: usize,
+        /// The directive.
+        token: String,
+    },
+    /// A `begin` or `loop` without its `end` or `again`.
+    #[error("Line {line}: `{token}` is never closed")]
+    Unclosed {
+        /// The line the block starts on.
+        line: usize,
+        /// `begin` or `loop`.
+        token: &'static str,
+    },
+}
+
+/// Assembles Octo `source` into a program, ready for
+/// [`Chip8::load_program`](crate::Chip8::load_program).
+///
+/// Like Octo, execution starts at the `main` label. If anything comes
+/// before it, the program starts with a jump to it.
+pub fn assemble(source: &str) -> Result<Vec<u8>, OctoError> {
+    let tokens = tokenize(source);
+    let mut assembler = Assembler::default();
+
+    let main_first =
+        matches!(&tokens[..], [colon, main, ..] if colon.text == ":" && main.text == "main");
+    let has_main = tokens
+        .windows(2)
+        .any(|pair| pair[0].text == ":" && pair[1].text == "main");
+    if has_main && !main_first {
+        assembler.emit_address(
+            0x1000,
+            Token {
+                text: "main",
+                line: 1,
+            },
+        )?;
+    }
+
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        assembler.statement(token, &mut tokens)?;
+    }
+    assembler.finish()
+}
+
+/// A word of source and the line it's on.
+#[derive(Clone, Copy, Debug)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+/// Splits the source into words, dropping `#` comments.
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    source
+        .lines()
+        .enumerate()
+        .flat_map(|(index, line)| {
+            let code = line.split('#').next().unwrap_or_default();
+            code.split_whitespace().map(move |text| Token {
+                text,
+                line: index + 1,
+            })
+        })
+        .collect()
+}
+
+/// Parses a decimal, `0x` hex or `0b` binary number, which can be negative.
+fn number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// A test for `if` and `while`.
+#[derive(Clone, Copy, Debug)]
+enum Condition {
+    Equals(u8, u8),
+    NotEquals(u8, u8),
+    RegisterEquals(u8, u8),
+    RegisterNotEquals(u8, u8),
+    Key(u8),
+    NotKey(u8),
+}
+
+impl Condition {
+    /// The instruction that skips the next one when the condition is true.
+    fn skip_when(self) -> u16 {
+        let x = |vx: u8| (vx as u16) << 8;
+        let xy = |vx: u8, vy: u8| x(vx) | (vy as u16) << 4;
+        match self {
+            Self::Equals(vx, nn) => 0x3000 | x(vx) | nn as u16,
+            Self::NotEquals(vx, nn) => 0x4000 | x(vx) | nn as u16,
+            Self::RegisterEquals(vx, vy) => 0x5000 | xy(vx, vy),
+            Self::RegisterNotEquals(vx, vy) => 0x9000 | xy(vx, vy),
+            Self::Key(vx) => 0xE09E | x(vx),
+            Self::NotKey(vx) => 0xE0A1 | x(vx),
+        }
+    }
+
+    /// The instruction that skips the next one when the condition is
+    /// false, which is what `if ... then` needs.
+    fn skip_unless(self) -> u16 {
+        match self {
+            Self::Equals(vx, nn) => Self::NotEquals(vx, nn),
+            Self::NotEquals(vx, nn) => Self::Equals(vx, nn),
+            Self::RegisterEquals(vx, vy) => Self::RegisterNotEquals(vx, vy),
+            Self::RegisterNotEquals(vx, vy) => Self::RegisterEquals(vx, vy),
+            Self::Key(vx) => Self::NotKey(vx),
+            Self::NotKey(vx) => Self::Key(vx),
+        }
+        .skip_when()
+    }
+}
+
+/// A `begin` or `loop` that is still open.
+#[derive(Debug)]
+enum Block {
+    /// `if ... begin`, with the jump past it that `else` or `end` fills in.
+    If { jump: usize, line: usize },
+    /// `else`, with the jump past it that `end` fills in.
+    Else { jump: usize, line: usize },
+    /// `loop`, with where it starts and the jumps out of it from `while`,
+    /// which `again` fills in.
+    Loop {
+        start: u16,
+        exits: Vec<usize>,
+        line: usize,
+    },
+}
+
+/// An address that wasn't known yet when it was used.
+#[derive(Debug)]
+struct Fixup<'a> {
+    /// Where the instruction is in the program.
+    offset: usize,
+    label: Token<'a>,
+    /// Whether the address is `i := long`'s 16 bits, rather than 12 bits
+    /// in the instruction itself.
+    long: bool,
+}
+
+#[derive(Debug, Default)]
+struct Assembler<'a> {
+    program: Vec<u8>,
+    labels: HashMap<&'a str, u16>,
+    constants: HashMap<&'a str, i64>,
+    aliases: HashMap<&'a str, u8>,
+    fixups: Vec<Fixup<'a>>,
+    /// Open blocks, innermost last.
+    blocks: Vec<Block>,
+}
+
+impl<'a> Assembler<'a> {
+    /// The address the next byte goes at.
+    fn here(&self) -> u16 {
+        (PROGRAM_OFFSET + self.program.len()) as u16
+    }
+
+    fn emit(&mut self, word: u16) {
+        self.program.extend_from_slice(&word.to_be_bytes());
+    }
+
+    /// Emits `opcode` with the address of `target` in its low 12 bits,
+    /// filling it in later if `target` is a label that isn't defined yet.
+    fn emit_address(&mut self, opcode: u16, target: Token<'a>) -> Result<(), OctoError> {
+        let address = match self.known_value(target) {
+            Some(value) => Some(check_range(value, 12, target.line)?),
+            None => self.labels.get(target.text).copied(),
+        };
+        match address {
+            Some(address) if address > 0xFFF => {
+                return Err(OctoError::OutOfRange {
+                    line: target.line,
+                    value: address as i64,
+                    bits: 12,
+                })
+            }
+            Some(address) => self.emit(opcode | address),
+            None => {
+                self.fixups.push(Fixup {
+                    offset: self.program.len(),
+                    label: target,
+                    long: false,
+                });
+                self.emit(opcode);
+            }
+        }
+        Ok(())
+    }
+
+    /// Emits the 16 bit address of `target`, for `i := long`.
+    fn emit_long_address(&mut self, target: Token<'a>) -> Result<(), OctoError> {
+        let address = match self.known_value(target) {
+            Some(value) => Some(check_range(value, 16, target.line)?),
+            None => self.labels.get(target.text).copied(),
+        };
+        match address {
+            Some(address) => self.emit(address),
+            None => {
+                self.fixups.push(Fixup {
+                    offset: self.program.len(),
+                    label: target,
+                    long: true,
+                });
+                self.emit(0);
+            }
+        }
+        Ok(())
+    }
+
+    /// Points the jump at `offset` to the current address.
+    fn patch_jump(&mut self, offset: usize, line: usize) -> Result<(), OctoError> {
+        let here = self.here();
+        if here > 0xFFF {
+            return Err(OctoError::OutOfRange {
+                line,
+                value: here as i64,
+                bits: 12,
+            });
+        }
+        self.program[offset..offset + 2].copy_from_slice(&(0x1000 | here).to_be_bytes());
+        Ok(())
+    }
+
+    /// A number or constant.
+    fn known_value(&self, token: Token) -> Option<i64> {
+        number(token.text).or_else(|| self.constants.get(token.text).copied())
+    }
+
+    /// A number or constant that has to fit in `bits` bits.
+    fn value(&self, token: Token, bits: u32) -> Result<u16, OctoError> {
+        let value = self
+            .known_value(token)
+            .ok_or_else(|| unexpected(token, "a number"))?;
+        check_range(value, bits, token.line)
+    }
+
+    /// `v0` to `vF`, or an `:alias` for one.
+    fn register(&self, token: Token) -> Option<u8> {
+        if let Some(&vx) = self.aliases.get(token.text) {
+            return Some(vx);
+        }
+        let digit = token
+            .text
+            .strip_prefix(['v', 'V'])
+            .filter(|digit| digit.len() == 1)?;
+        u8::from_str_radix(digit, 16).ok()
+    }
+
+    fn expect_register(&self, token: Token) -> Result<u8, OctoError> {
+        self.register(token)
+            .ok_or_else(|| unexpected(token, "a register"))
+    }
+
+    fn define(&mut self, name: Token<'a>) -> Result<(), OctoError> {
+        let taken = self.labels.contains_key(name.text)
+            || self.constants.contains_key(name.text)
+            || self.aliases.contains_key(name.text);
+        if taken {
+            return Err(OctoError::DuplicateName {
+                line: name.line,
+                name: name.text.to_string(),
+            });
+        }
+        if number(name.text).is_some() || self.register(name).is_some() {
+            return Err(unexpected(name, "a name"));
+        }
+        Ok(())
+    }
+
+    fn statement(
+        &mut self,
+        token: Token<'a>,
+        tokens: &mut impl Iterator<Item = Token<'a>>,
+    ) -> Result<(), OctoError> {
+        let mut next = |expected| tokens.next().ok_or(OctoError::UnexpectedEnd { expected });
+
+        match token.text {
+            ":" => {
+                let name = next("a label name")?;
+                self.define(name)?;
+                self.labels.insert(name.text, self.here());
+            }
+            ":alias" => {
+                let name = next("an alias name")?;
+                self.define(name)?;
+                let vx = self.expect_register(next("a register")?)?;
+                self.aliases.insert(name.text, vx);
+            }
+            ":const" => {
+                let name = next("a constant name")?;
+                self.define(name)?;
+                let value = next("a number")?;
+                let value = self
+                    .known_value(value)
+                    .ok_or_else(|| unexpected(value, "a number"))?;
+                self.constants.insert(name.text, value);
+            }
+            directive if directive.starts_with(':') => return Err(unsupported(token)),
+            // The emulator can't run these yet, so assembling them would
+            // only make the program misbehave later.
+            "scroll-up" | "exit" => return Err(unsupported(token)),
+
+            "clear" => self.emit(0x00E0),
+            "return" | ";" => self.emit(0x00EE),
+            "scroll-down" => {
+                let n = self.value(next("a number of rows")?, 4)?;
+                self.emit(0x00C0 | n);
+            }
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-left" => self.emit(0x00FC),
+            "lores" => self.emit(0x00FE),
+            "hires" => self.emit(0x00FF),
+            "audio" => self.emit(0xF002),
+            "plane" => {
+                let planes = self.value(next("a plane mask")?, 4)?;
+                self.emit(0xF001 | planes << 8);
+            }
+            "native" => self.emit_address(0x0000, next("an address")?)?,
+            "jump" => self.emit_address(0x1000, next("an address")?)?,
+            "jump0" => self.emit_address(0xB000, next("an address")?)?,
+            "sprite" => {
+                let vx = self.expect_register(next("a register")?)?;
+                let vy = self.expect_register(next("a register")?)?;
+                let n = self.value(next("a sprite height")?, 4)?;
+                self.emit(0xD000 | (vx as u16) << 8 | (vy as u16) << 4 | n);
+            }
+            "bcd" | "save" | "load" | "saveflags" | "loadflags" => {
+                let opcode = match token.text {
+                    "bcd" => 0xF033,
+                    "save" => 0xF055,
+                    "load" => 0xF065,
+                    "saveflags" => 0xF075,
+                    _ => 0xF085,
+                };
+                let vx = self.expect_register(next("a register")?)?;
+                self.emit(opcode | (vx as u16) << 8);
+            }
+            "delay" | "buzzer" | "pitch" => {
+                let assign = next("`:=`")?;
+                if assign.text != ":=" {
+                    return Err(unexpected(assign, "`:=`"));
+                }
+                let opcode = match token.text {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                let vx = self.expect_register(next("a register")?)?;
+                self.emit(opcode | (vx as u16) << 8);
+            }
+            "i" => {
+                let operator = next("`:=` or `+=`")?;
+                match operator.text {
+                    ":=" => {
+                        let source = next("an address")?;
+                        match source.text {
+                            "long" => {
+                                self.emit(0xF000);
+                                self.emit_long_address(next("an address")?)?;
+                            }
+                            "hex" | "bighex" => {
+                                let opcode = if source.text == "hex" { 0xF029 } else { 0xF030 };
+                                let vx = self.expect_register(next("a register")?)?;
+                                self.emit(opcode | (vx as u16) << 8);
+                            }
+                            _ => self.emit_address(0xA000, source)?,
+                        }
+                    }
+                    "+=" => {
+                        let vx = self.expect_register(next("a register")?)?;
+                        self.emit(0xF01E | (vx as u16) << 8);
+                    }
+                    _ => return Err(unexpected(operator, "`:=` or `+=`")),
+                }
+            }
+
+            "if" => {
+                let condition = self.condition(&mut next)?;
+                let body = next("`then` or `begin`")?;
+                match body.text {
+                    "then" => self.emit(condition.skip_unless()),
+                    "begin" => {
+                        self.emit(condition.skip_when());
+                        self.blocks.push(Block::If {
+                            jump: self.program.len(),
+                            line: body.line,
+                        });
+                        self.emit(0x1000);
+                    }
+                    _ => return Err(unexpected(body, "`then` or `begin`")),
+                }
+            }
+            "else" => {
+                let Some(Block::If { jump, .. }) = self.blocks.pop() else {
+                    return Err(unexpected(token, "`else` to follow `if ... begin`"));
+                };
+                let own_jump = self.program.len();
+                self.emit(0x1000);
+                self.patch_jump(jump, token.line)?;
+                self.blocks.push(Block::Else {
+                    jump: own_jump,
+                    line: token.line,
+                });
+            }
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump, .. } | Block::Else { jump, .. }) => {
+                    self.patch_jump(jump, token.line)?
+                }
+                _ => return Err(unexpected(token, "`end` to follow `begin`")),
+            },
+            "loop" => self.blocks.push(Block::Loop {
+                start: self.here(),
+                exits: Vec::new(),
+                line: token.line,
+            }),
+            "while" => {
+                let condition = self.condition(&mut next)?;
+                self.emit(condition.skip_when());
+                let jump = self.program.len();
+                self.emit(0x1000);
+                let Some(Block::Loop { exits, .. }) = self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|block| matches!(block, Block::Loop { .. }))
+                else {
+                    return Err(unexpected(token, "`while` inside a `loop`"));
+                };
+                exits.push(jump);
+            }
+            "again" => {
+                let Some(Block::Loop { start, exits, .. }) = self.blocks.pop() else {
+                    return Err(unexpected(token, "`again` to follow `loop`"));
+                };
+                self.emit(0x1000 | start);
+                for exit in exits {
+                    self.patch_jump(exit, token.line)?;
+                }
+            }
+
+            _ if number(token.text).is_some() => {
+                let byte = self.value(token, 8)?;
+                self.program.push(byte as u8);
+            }
+            _ if self.register(token).is_some() => {
+                let vx = self.expect_register(token)?;
+                self.register_statement(vx, &mut next)?;
+            }
+            // Anything else is a label to call.
+            _ => self.emit_address(0x2000, token)?,
+        }
+        Ok(())
+    }
+
+    /// Everything that starts with a register, like `v0 += 1`.
+    fn register_statement(
+        &mut self,
+        vx: u8,
+        next: &mut impl FnMut(&'static str) -> Result<Token<'a>, OctoError>,
+    ) -> Result<(), OctoError> {
+        let x = (vx as u16) << 8;
+        let operator = next("an operator")?;
+        let operand = next("a register or number")?;
+        let vy = self.register(operand).map(|vy| (vy as u16) << 4);
+
+        let word = match (operator.text, vy) {
+            (":=", _) if operand.text == "random" => {
+                0xC000 | x | self.value(next("a number")?, 8)?
+            }
+            (":=", _) if operand.text == "delay" => 0xF007 | x,
+            (":=", _) if operand.text == "key" => 0xF00A | x,
+            (":=", Some(y)) => 0x8000 | x | y,
+            (":=", None) => 0x6000 | x | self.value(operand, 8)?,
+            ("+=", Some(y)) => 0x8004 | x | y,
+            ("+=", None) => 0x7000 | x | self.value(operand, 8)?,
+            ("-=", Some(y)) => 0x8005 | x | y,
+            // There's no subtract immediate, so add the negative instead.
+            ("-=", None) => 0x7000 | x | (self.value(operand, 8)? as u8).wrapping_neg() as u16,
+            ("=-", Some(y)) => 0x8007 | x | y,
+            ("|=", Some(y)) => 0x8001 | x | y,
+            ("&=", Some(y)) => 0x8002 | x | y,
+            ("^=", Some(y)) => 0x8003 | x | y,
+            (">>=", Some(y)) => 0x8006 | x | y,
+            ("<<=", Some(y)) => 0x800E | x | y,
+            ("=-" | "|=" | "&=" | "^=" | ">>=" | "<<=", None) => {
+                return Err(unexpected(operand, "a register"))
+            }
+            _ => return Err(unexpected(operator, "an operator")),
+        };
+        self.emit(word);
+        Ok(())
+    }
+
+    /// The condition after `if` or `while`, like `v0 == 3` or `v1 key`.
+    fn condition(
+        &self,
+        next: &mut impl FnMut(&'static str) -> Result<Token<'a>, OctoError>,
+    ) -> Result<Condition, OctoError> {
+        let vx = self.expect_register(next("a register")?)?;
+        let operator = next("a comparison")?;
+        Ok(match operator.text {
+            "key" => Condition::Key(vx),
+            "-key" => Condition::NotKey(vx),
+            "==" | "!=" => {
+                let operand = next("a register or number")?;
+                let equals = operator.text == "==";
+                match self.register(operand) {
+                    Some(vy) if equals => Condition::RegisterEquals(vx, vy),
+                    Some(vy) => Condition::RegisterNotEquals(vx, vy),
+                    None if equals => Condition::Equals(vx, self.value(operand, 8)? as u8),
+                    None => Condition::NotEquals(vx, self.value(operand, 8)? as u8),
+                }
+            }
+            "<" | ">" | "<=" | ">=" => return Err(unsupported(operator)),
+            _ => return Err(unexpected(operator, "a comparison")),
+        })
+    }
+
+    /// Fills in the labels that were used before they were defined.
+    fn finish(mut self) -> Result<Vec<u8>, OctoError> {
+        if let Some(block) = self.blocks.first() {
+            let (line, token) = match block {
+                Block::If { line, .. } | Block::Else { line, .. } => (*line, "begin"),
+                Block::Loop { line, .. } => (*line, "loop"),
+            };
+            return Err(OctoError::Unclosed { line, token });
+        }
+
+        for fixup in &self.fixups {
+            let Some(&address) = self.labels.get(fixup.label.text) else {
+                return Err(OctoError::UnknownLabel {
+                    line: fixup.label.line,
+                    name: fixup.label.text.to_string(),
+                });
+            };
+            let word = &mut self.program[fixup.offset..fixup.offset + 2];
+            let address = if fixup.long {
+                address
+            } else {
+                check_range(address as i64, 12, fixup.label.line)?
+                    | u16::from_be_bytes([word[0], word[1]])
+            };
+            word.copy_from_slice(&address.to_be_bytes());
+        }
+        Ok(self.program)
+    }
+}
+
+/// Checks that `value` fits in `bits` bits, allowing negative numbers in
+/// two's complement, and returns those bits.
+fn check_range(value: i64, bits: u32, line: usize) -> Result<u16, OctoError> {
+    let max = (1 << bits) - 1;
+    let min = -(1 << (bits - 1));
+    if !(min..=max).contains(&value) {
+        return Err(OctoError::OutOfRange { line, value, bits });
+    }
+    Ok((value & max) as u16)
+}
+
+fn unexpected(token: Token, expected: &'static str) -> OctoError {
+    OctoError::Unexpected {
+        line: token.line,
+        expected,
+        found: token.text.to_string(),
+    }
+}
+
+fn unsupported(token: Token) -> OctoError {
+    OctoError::Unsupported {
+        line: token.line,
+        token: token.text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::{assemble, OctoError};
+
+    #[test]
+    fn assembles_instructions() {
+        let source = "
+            # Draws a digit.
+            : main
+                clear
+                v0 := 5
+                v1 += -1
+                v2 -= 1
+                v3 := random 0xFF
+                i := hex v0
+                sprite v0 v1 5
+                delay := v0
+                v4 := key
+                i := long 0x1234
+                0xAB 0b1
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            program,
+            [
+                0x00, 0xE0, 0x60, 0x05, 0x71, 0xFF, 0x72, 0xFF, 0xC3, 0xFF, 0xF0, 0x29, 0xD0, 0x15,
+                0xF0, 0x15, 0xF4, 0x0A, 0xF0, 0x00, 0x12, 0x34, 0xAB, 0x01,
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_labels_and_jumps_to_main() {
+        let source = "
+            : sprite-data 0xF0 0x90
+            :alias x v3
+            :const SPEED 2
+            : main
+                x := SPEED
+                i := sprite-data
+                draw
+                jump main
+            : draw
+                sprite x x 2
+                return
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            program,
+            [
+                0x12, 0x04, // jump main
+                0xF0, 0x90, // sprite-data
+                0x63, 0x02, 0xA2, 0x02, 0x22, 0x0C, 0x12, 0x04, 0xD3, 0x32, 0x00, 0xEE,
+            ]
+        );
+    }
+
+    #[test]
+    fn assembles_control_flow() {
+        let source = "
+            : main
+                if v0 == 1 then v1 := 2
+                if v0 key begin
+                    v1 := 3
+                else
+                    v1 := 4
+                end
+                loop
+                    v0 += 1
+                    while v0 != 8
+                again
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            program,
+            [
+                0x40, 0x01, 0x61, 0x02, // if then
+                0xE0, 0x9E, 0x12, 0x0C, 0x61, 0x03, 0x12, 0x0E, 0x61, 0x04, // begin/else/end
+                0x70, 0x01, 0x40, 0x08, 0x12, 0x16, 0x12, 0x0E, // loop
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_lines() {
+        assert_eq!(
+            assemble(": main\n  jump nowhere"),
+            Err(OctoError::UnknownLabel {
+                line: 2,
+                name: "nowhere".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("v0 := 256"),
+            Err(OctoError::OutOfRange {
+                line: 1,
+                value: 256,
+                bits: 8
+            })
+        );
+        assert_eq!(
+            assemble("loop v0 += 1"),
+            Err(OctoError::Unclosed {
+                line: 1,
+                token: "loop"
+            })
+        );
+        assert!(matches!(
+            assemble(":macro foo { }"),
+            Err(OctoError::Unsupported { line: 1, .. })
+        ));
+        for source in [": main\n  scroll-up 4", ": main\n  exit"] {
+            assert!(matches!(
+                assemble(source),
+                Err(OctoError::Unsupported { line: 2, .. })
+            ));
+        }
+    }
+}
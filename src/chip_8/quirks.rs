@@ -0,0 +1,173 @@
+//! Behaviors that differ between CHIP-8 interpreters.
+//!
+//! ROMs were written against whichever interpreter the author had, so some
+//! of them rely on behavior that other interpreters changed. The defaults
+//! match modern interpreters (CHIP-48 and SUPER-CHIP onwards), which most
+//! ROMs floating around today expect. [`Quirks::preset`] sets them all at
+//! once for a given [`Platform`], and [`Platform::detect`] can pick the
+//! platform for ROMs we know about.
+
+use super::sha1::sha1;
+
+/// Toggles for the instructions that behave differently across interpreters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// If true, `8XY6` and `8XYE` copy VY into VX before shifting, like the
+    /// original COSMAC VIP. Otherwise VX is shifted in place.
+    pub shift_uses_vy: bool,
+    /// If true, `FX55` and `FX65` leave I pointing just past the last
+    /// register they touched (I = I + X + 1), like the original COSMAC VIP.
+    /// Otherwise I is left unchanged.
+    pub load_store_increments_i: bool,
+    /// If true, `BXNN` jumps to XNN plus VX, like SUPER-CHIP. Otherwise
+    /// `BNNN` jumps to NNN plus V0, like every other interpreter, which is
+    /// why this one is off by default.
+    pub jump_uses_vx: bool,
+    /// If true, `FX1E` sets VF to 1 when I goes past 0xFFF (and to 0
+    /// otherwise), like the Amiga interpreter. A few ROMs such as Spacefight
+    /// 2091! rely on this, but most expect VF to be left alone.
+    pub index_overflow_sets_vf: bool,
+    /// If true, `8XY1`, `8XY2` and `8XY3` reset VF to 0, like the original
+    /// COSMAC VIP. Otherwise VF is left alone.
+    pub logic_resets_vf: bool,
+    /// If true, `DXYN` waits for the next 60Hz timer tick before the next
+    /// instruction runs, like the COSMAC VIP waiting for the vertical blank.
+    /// This caps drawing at 60 sprites a second, which slows down (and
+    /// un-flickers) draw-heavy ROMs.
+    pub display_wait: bool,
+    /// If true, parts of a sprite that go past the right or bottom edge of
+    /// the screen wrap around to the other side. Otherwise they are clipped,
+    /// which is what nearly every ROM expects. The starting coordinate
+    /// always wraps either way.
+    pub wrap_sprites: bool,
+    /// If true, `DXY0` draws a 16x16 sprite like SUPER-CHIP. Otherwise it
+    /// draws nothing, since N is 0 rows tall.
+    pub large_sprites: bool,
+    /// If true, `DXYN` takes longer the more rows it draws, and longer
+    /// again when X isn't a multiple of 8, roughly like the COSMAC VIP
+    /// interpreter. See [`Chip8::cycle_cost`](crate::Chip8::cycle_cost).
+    /// Otherwise every instruction takes the same time.
+    pub draw_timing: bool,
+    /// If true, there are 64 KB of memory like XO-CHIP, instead of 4 KB.
+    /// Only `F000 NNNN` can point I past 0xFFF, but I can also get there
+    /// by adding to it.
+    pub extended_memory: bool,
+    /// If true, the buzzer plays the pattern a program loads with `F002`
+    /// at the pitch set by `FX3A`, like XO-CHIP. Otherwise both still run,
+    /// but the buzzer keeps playing its own tone.
+    pub audio_pattern: bool,
+}
+
+/// Interpreters (and the machines they ran on) that ROMs were written for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "desktop", derive(clap::ValueEnum))]
+pub enum Platform {
+    /// Modern CHIP-8, as run by CHIP-48 and most interpreters since. These
+    /// are the default quirks.
+    #[default]
+    Chip8,
+    /// SUPER-CHIP 1.1 on the HP-48 calculators.
+    Schip,
+    /// Octo's XO-CHIP.
+    #[cfg_attr(feature = "desktop", value(name = "xochip"))]
+    XoChip,
+    /// The original CHIP-8 interpreter on the COSMAC VIP.
+    Cosmac,
+}
+
+/// ROMs that need something other than the default quirks, by the SHA-1
+/// of the whole file. Only add ROMs here once they're known to need it, and
+/// the hash has been checked against the actual file.
+#[rustfmt::skip]
+const KNOWN_ROMS: &[([u8; 20], Platform)] = &[
+    // roms/scroll.sc8
+    ([0xB9, 0x59, 0x02, 0x9E, 0xE6, 0x69, 0x1D, 0x4A, 0xD6, 0x66, 0xE4, 0x4F, 0xE2, 0x9B, 0xF9, 0xD6, 0xFB, 0x28, 0x7E, 0xC8], Platform::Schip),
+    // roms/planes.xo8
+    ([0xE0, 0xFD, 0x19, 0x0F, 0xD6, 0x14, 0x2D, 0xD4, 0x3C, 0xDB, 0x83, 0xE2, 0x8F, 0x3C, 0xAC, 0xC8, 0x80, 0xB3, 0x2B, 0x04], Platform::XoChip),
+];
+
+impl Platform {
+    /// The platform `program` was written for, if it's in our table of
+    /// known ROMs.
+    pub fn detect(program: &[u8]) -> Option<Self> {
+        let hash = sha1(program);
+        KNOWN_ROMS
+            .iter()
+            .find(|(known, _)| *known == hash)
+            .map(|&(_, platform)| platform)
+    }
+}
+
+impl Quirks {
+    /// The quirks `platform` had.
+    pub fn preset(platform: Platform) -> Self {
+        match platform {
+            Platform::Chip8 => Self::default(),
+            Platform::Schip => Self {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: true,
+                index_overflow_sets_vf: false,
+                logic_resets_vf: false,
+                display_wait: false,
+                wrap_sprites: false,
+                large_sprites: true,
+                draw_timing: false,
+                extended_memory: false,
+                audio_pattern: false,
+            },
+            Platform::XoChip => Self {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                index_overflow_sets_vf: false,
+                logic_resets_vf: false,
+                display_wait: false,
+                wrap_sprites: true,
+                large_sprites: true,
+                draw_timing: false,
+                extended_memory: true,
+                audio_pattern: true,
+            },
+            Platform::Cosmac => Self {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                index_overflow_sets_vf: false,
+                logic_resets_vf: true,
+                display_wait: true,
+                wrap_sprites: false,
+                large_sprites: false,
+                draw_timing: true,
+                extended_memory: false,
+                audio_pattern: false,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::{Platform, Quirks};
+
+    #[test]
+    fn does_not_detect_unknown_roms() {
+        assert_eq!(Platform::detect(&[0x12, 0x00]), None);
+    }
+
+    #[test]
+    fn detects_the_roms_we_ship() {
+        let scroll = include_bytes!("../../roms/scroll.sc8");
+        assert_eq!(Platform::detect(scroll), Some(Platform::Schip));
+        let planes = include_bytes!("../../roms/planes.xo8");
+        assert_eq!(Platform::detect(planes), Some(Platform::XoChip));
+    }
+
+    #[test]
+    fn presets_differ_from_the_default() {
+        assert_eq!(Quirks::preset(Platform::default()), Quirks::default());
+        assert!(Quirks::preset(Platform::Schip).jump_uses_vx);
+        assert!(Quirks::preset(Platform::XoChip).wrap_sprites);
+        assert!(Quirks::preset(Platform::Cosmac).display_wait);
+    }
+}
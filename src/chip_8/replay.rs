@@ -0,0 +1,220 @@
+//! Records keypad input against the cycle counter, so a run can be played
+//! back exactly.
+//!
+//! Events are tied to [`Chip8::cycle_count`] rather than wall-clock time,
+//! so playback doesn't depend on how fast the host is. The log also keeps the
+//! random seed and the instructions per second, since a run only plays out
+//! the same way if those match too.
+//!
+//! A log is saved as plain text:
+//!
+//! ```text
+//! seed 1234
+//! cps 720
+//! 1500 5
+//! 1580 5 8
+//! 1620 none
+//! ```
+//!
+//! Each event line is the cycle the keys changed on, then the keys that
+//! were held from then on.
+
+use std::collections::VecDeque;
+
+use crate::{Chip8, Keypad};
+
+/// Something wrong with a saved input log.
+#[derive(Clone, Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ReplayError {
+    /// A line that isn't a header or an event.
+    #[error("Line {line}: expected `seed N`, `cps N` or `CYCLE KEY`")]
+    Syntax {
+        /// The line number, starting at 1.
+        line: usize,
+    },
+    /// The log doesn't say what seed or speed it was recorded with.
+    #[error("Input log is missing its `{0}` line")]
+    MissingHeader(&'static str),
+}
+
+/// A change in the held keys at a specific cycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    /// The [`Chip8::cycle_count`] the keys were read on.
+    pub cycle: u64,
+    /// The keys held down from then on.
+    pub keys: Keypad,
+}
+
+/// Every key change of a run, along with what is needed to reproduce it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputLog {
+    /// The seed passed to [`Chip8::set_seed`].
+    pub seed: u64,
+    /// How many instructions ran per second.
+    pub cycles_per_second: u32,
+    /// The key changes, in order.
+    pub events: Vec<InputEvent>,
+}
+
+impl InputLog {
+    /// Creates an empty log.
+    pub fn new(seed: u64, cycles_per_second: u32) -> Self {
+        Self {
+            seed,
+            cycles_per_second,
+            events: Vec::new(),
+        }
+    }
+
+    /// Records the keys the emulator read on `cycle`, if they are different
+    /// from the last ones.
+    pub fn record(&mut self, cycle: u64, keys: Keypad) {
+        let last_keys = self.events.last().map(|event| event.keys);
+        if keys != last_keys.unwrap_or_default() {
+            self.events.push(InputEvent { cycle, keys });
+        }
+    }
+
+    /// Records the keys the emulator read on its last cycle. Call this right
+    /// after [`Chip8::cycle`].
+    pub fn record_cycle(&mut self, chip_8: &Chip8) {
+        if let Some(cycle) = chip_8.cycle_count().checked_sub(1) {
+            self.record(cycle, chip_8.keypad);
+        }
+    }
+
+    /// Writes the log in the format described in the [module docs](self).
+    pub fn to_text(&self) -> String {
+        let mut text = format!("seed {}\ncps {}\n", self.seed, self.cycles_per_second);
+        for event in &self.events {
+            let keys: Vec<String> = event.keys.pressed().map(|key| format!("{key:X}")).collect();
+            if keys.is_empty() {
+                text += &format!("{} none\n", event.cycle);
+            } else {
+                text += &format!("{} {}\n", event.cycle, keys.join(" "));
+            }
+        }
+        text
+    }
+
+    /// Reads a log written by [`Self::to_text`].
+    pub fn parse(text: &str) -> Result<Self, ReplayError> {
+        let mut seed = None;
+        let mut cycles_per_second = None;
+        let mut events = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let syntax_error = ReplayError::Syntax { line: index + 1 };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (left, right) = line.split_once(' ').ok_or(syntax_error.clone())?;
+            match left {
+                "seed" => seed = Some(right.parse().map_err(|_| syntax_error)?),
+                "cps" => cycles_per_second = Some(right.parse().map_err(|_| syntax_error)?),
+                cycle => {
+                    let cycle = cycle.parse().map_err(|_| syntax_error.clone())?;
+                    let mut keys = Keypad::default();
+                    if right != "none" {
+                        for key in right.split_whitespace() {
+                            let key = u8::from_str_radix(key, 16)
+                                .ok()
+                                .filter(|&key| key <= 0xF)
+                                .ok_or(syntax_error.clone())?;
+                            keys.set(key, true);
+                        }
+                    }
+                    events.push(InputEvent { cycle, keys });
+                }
+            }
+        }
+
+        Ok(Self {
+            seed: seed.ok_or(ReplayError::MissingHeader("seed"))?,
+            cycles_per_second: cycles_per_second.ok_or(ReplayError::MissingHeader("cps"))?,
+            events,
+        })
+    }
+}
+
+/// Hands back the events of an [`InputLog`] as their cycles come up.
+#[derive(Debug)]
+pub struct Player {
+    events: VecDeque<InputEvent>,
+}
+
+impl Player {
+    /// Plays back `log` from the start.
+    pub fn new(log: InputLog) -> Self {
+        Self {
+            events: log.events.into(),
+        }
+    }
+
+    /// Returns the keys to give the emulator before it runs `cycle`, if
+    /// they change then.
+    pub fn keys_at(&mut self, cycle: u64) -> Option<Keypad> {
+        let mut keys = None;
+        while let Some(event) = self.events.front().filter(|event| event.cycle <= cycle) {
+            keys = Some(event.keys);
+            self.events.pop_front();
+        }
+        keys
+    }
+
+    /// True once every event has been played.
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::{InputLog, Player, ReplayError};
+    use crate::Keypad;
+
+    fn keys(pressed: &[u8]) -> Keypad {
+        let mut keys = Keypad::default();
+        for &key in pressed {
+            keys.set(key, true);
+        }
+        keys
+    }
+
+    #[test]
+    fn round_trips_and_plays_back() {
+        let mut log = InputLog::new(42, 720);
+        log.record(10, keys(&[0x5]));
+        log.record(11, keys(&[0x5]));
+        log.record(30, keys(&[]));
+        log.record(31, keys(&[0xF]));
+        log.record(40, keys(&[0x2, 0x8]));
+        assert_eq!(log.events.len(), 4);
+
+        let text = log.to_text();
+        assert_eq!(text, "seed 42\ncps 720\n10 5\n30 none\n31 F\n40 2 8\n");
+        assert_eq!(InputLog::parse(&text), Ok(log.clone()));
+
+        let mut player = Player::new(log);
+        assert_eq!(player.keys_at(9), None);
+        assert_eq!(player.keys_at(10), Some(keys(&[0x5])));
+        assert_eq!(player.keys_at(11), None);
+        assert_eq!(player.keys_at(30), Some(keys(&[])));
+        assert_eq!(player.keys_at(31), Some(keys(&[0xF])));
+        assert!(!player.is_finished());
+        assert_eq!(player.keys_at(40), Some(keys(&[0x2, 0x8])));
+        assert!(player.is_finished());
+
+        assert_eq!(
+            InputLog::parse("cps 720\n"),
+            Err(ReplayError::MissingHeader("seed"))
+        );
+        assert_eq!(
+            InputLog::parse("seed 1\ncps 720\n12 G\n"),
+            Err(ReplayError::Syntax { line: 3 })
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Rewinding, by keeping the last few seconds of save states around.
+
+use std::collections::VecDeque;
+
+use crate::chip_8::{Chip8, Chip8Error};
+
+/// By default we keep 10 seconds of snapshots at 60 a second.
+pub const DEFAULT_CAPACITY: usize = 600;
+
+/// A ring buffer of the most recent [`Chip8::save_state`] snapshots.
+///
+/// A snapshot is almost all memory and the screen: about 6KB with the usual
+/// 4KB of memory, so the default capacity costs around 3.6MB. With
+/// XO-CHIP's 64KB of memory (see
+/// [`Quirks::extended_memory`](crate::quirks::Quirks::extended_memory)) a
+/// snapshot is about 66KB, and the default capacity costs around 40MB.
+#[derive(Debug)]
+pub struct Rewind {
+    snapshots: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl Rewind {
+    /// Creates a buffer that keeps the last `capacity` snapshots. A capacity
+    /// of 0 disables rewinding.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Snapshots the emulator, dropping the oldest snapshot if the buffer is
+    /// full.
+    pub fn push(&mut self, chip_8: &Chip8) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(chip_8.save_state());
+    }
+
+    /// Restores the most recent snapshot and forgets it, so calling this
+    /// repeatedly steps further back. Returns false once there is nothing
+    /// left to rewind to.
+    pub fn rewind(&mut self, chip_8: &mut Chip8) -> Result<bool, Chip8Error> {
+        match self.snapshots.pop_back() {
+            Some(snapshot) => {
+                chip_8.load_state(&snapshot)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// How many snapshots there are to rewind through.
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    /// True if there is nothing to rewind to.
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::Rewind;
+    use crate::Chip8;
+
+    #[test]
+    fn rewinds_newest_first_and_drops_oldest() {
+        let mut chip_8 = Chip8::headless();
+        chip_8.initialize().unwrap();
+        chip_8
+            .load_program(vec![
+                0x70, 0x01, // ADD V0, 0x01
+                0x12, 0x00, // JP 0x200
+            ])
+            .unwrap();
+
+        let mut rewind = Rewind::new(3);
+        for _ in 0..5 {
+            chip_8.cycle().unwrap();
+            chip_8.cycle().unwrap();
+            rewind.push(&chip_8);
+        }
+        assert_eq!(rewind.len(), 3);
+
+        for expected in [5, 4, 3] {
+            assert!(rewind.rewind(&mut chip_8).unwrap());
+            assert_eq!(chip_8.registers()[0x0], expected);
+        }
+        assert!(!rewind.rewind(&mut chip_8).unwrap());
+        assert_eq!(chip_8.registers()[0x0], 3);
+    }
+}
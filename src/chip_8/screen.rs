@@ -1,46 +1,217 @@
-use crate::Chip8;
-use crate::HEIGHT;
-use crate::WIDTH;
+use crate::{HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, WIDTH};
+use log::debug;
 
-/// The memory used for the screen. Each value is
+/// The memory used for the screen. Each pixel is
 /// a boolean and represents a 1 for white, and 0 for black.
 ///
-/// The 0th memory location maps to the top left corner
-/// of the screen.
-/// A memory location is given by `location = WIDTH*y + x`.
+/// Each row is packed into the bits of a `u128` (pixel x is bit x), so
+/// drawing a sprite row is a single XOR and collision is checking whether
+/// the sprite and the row have any bits in common. A row is wide enough for
+/// the SUPER-CHIP high resolution mode, but only the first `width` bits (and
+/// `height` rows) are used.
+///
+/// XO-CHIP adds a second bitplane, so there is a set of rows for each plane.
+/// [`Self::pixels`] expands them to one value per pixel: bit 0 is the first
+/// plane and bit 1 is the second. Programs that never select the second
+/// plane only ever see 0 and 1.
 #[derive(Debug)]
-pub struct Screen([u8; (WIDTH * HEIGHT) as usize]);
+pub struct Screen {
+    planes_rows: [[u128; HIRES_HEIGHT as usize]; 2],
+    hires: bool,
+    /// The planes that drawing and clearing affect, as a bitmask.
+    planes: u8,
+    /// True if the pixels changed since the last [`Self::take_dirty`].
+    dirty: bool,
+}
 
 impl Default for Screen {
-    /// Initializes screen to black.
+    /// Initializes screen to black, in low resolution. A new screen starts
+    /// out dirty, since nothing has shown it yet.
     fn default() -> Self {
-        Self([0; (WIDTH * HEIGHT) as usize])
+        Self {
+            planes_rows: [[0; HIRES_HEIGHT as usize]; 2],
+            hires: false,
+            planes: 0b01,
+            dirty: true,
+        }
     }
 }
 
 impl Screen {
     /// Clears the screen.
     pub fn clear(&mut self) {
-        for b in self.0.iter_mut() {
-            *b = 0x00;
+        self.planes_rows = [[0; HIRES_HEIGHT as usize]; 2];
+        self.dirty = true;
+    }
+
+    /// Returns whether the screen changed since the last call, and resets it.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// Clears only the selected planes.
+    pub fn clear_planes(&mut self) {
+        for (index, rows) in self.planes_rows.iter_mut().enumerate() {
+            if self.planes & (1 << index) != 0 {
+                *rows = [0; HIRES_HEIGHT as usize];
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// The planes that are selected, as a bitmask.
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// Selects which of the two planes get drawn to, as a bitmask.
+    pub fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & 0b11;
+    }
+
+    /// The width of the screen at the current resolution.
+    pub fn width(&self) -> u32 {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            WIDTH
+        }
+    }
+
+    /// The height of the screen at the current resolution.
+    pub fn height(&self) -> u32 {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            HEIGHT
+        }
+    }
+
+    /// True if the screen is in SUPER-CHIP's 128x64 mode.
+    pub fn hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Switches between 64x32 and 128x64. This clears the screen, since the
+    /// old pixels wouldn't line up with the new resolution anyway.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.clear();
+    }
+
+    /// The bits of a row that are on the screen.
+    fn row_mask(&self) -> u128 {
+        u128::MAX >> (128 - self.width())
+    }
+
+    /// The rows that are on the screen, for every plane. This marks the
+    /// screen as dirty, since it's only used to change them.
+    fn visible_rows(&mut self) -> impl Iterator<Item = &mut [u128]> {
+        let height = self.height() as usize;
+        self.dirty = true;
+        self.planes_rows
+            .iter_mut()
+            .map(move |rows| &mut rows[..height])
+    }
+
+    /// Moves everything down by `lines`, leaving blank lines at the top.
+    pub fn scroll_down(&mut self, lines: usize) {
+        for rows in self.visible_rows() {
+            let shift = lines.min(rows.len());
+            let len = rows.len();
+
+            rows.copy_within(..len - shift, shift);
+            rows[..shift].fill(0);
+        }
+    }
+
+    /// Moves everything right by `columns`, leaving blank columns on the left.
+    pub fn scroll_right(&mut self, columns: usize) {
+        let mask = self.row_mask();
+        for rows in self.visible_rows() {
+            for row in rows {
+                *row = row.checked_shl(columns as u32).unwrap_or(0) & mask;
+            }
+        }
+    }
+
+    /// Moves everything left by `columns`, leaving blank columns on the right.
+    pub fn scroll_left(&mut self, columns: usize) {
+        for rows in self.visible_rows() {
+            for row in rows {
+                *row = row.checked_shr(columns as u32).unwrap_or(0);
+            }
         }
     }
 
-    /// Inverts a pixel at a given x and y.
+    /// XORs one row of a sprite onto one plane (bit 0b01 or 0b10) of the
+    /// screen, starting at the given x and y. The sprite row is
+    /// `sprite_width` bits wide (8 or 16), with the leftmost pixel in the
+    /// most significant bit. Pixels past the right edge are clipped, or
+    /// wrapped around to the left if `wrap` is true.
     ///
-    /// Returns the new value of the pixel (1 for white and
-    /// 0 for black). This is important as we change the value
-    /// of VF to 1 if we turned a pixel off that used to be on.
-    pub fn invert(&mut self, x: u8, y: u8) -> bool {
-        let address = (y as usize * WIDTH as usize) + x as usize;
+    /// Returns whether any pixel was turned off. This is important as we
+    /// change the value of VF to 1 if we turned a pixel off that used to
+    /// be on.
+    ///
+    /// Returns `None` and leaves the screen alone if the starting pixel is
+    /// off the screen.
+    pub fn xor_row(
+        &mut self,
+        x: u8,
+        y: u8,
+        sprite_row: u16,
+        sprite_width: u8,
+        plane: u8,
+        wrap: bool,
+    ) -> Option<bool> {
+        let width = self.width();
+        if x as u32 >= width || y as u32 >= self.height() {
+            debug!("Tried to draw at pixel ({x}, {y}), which is off the screen");
+            return None;
+        }
+
+        // Pixel x is bit x, which is the opposite order to sprites, so flip
+        // the sprite around first.
+        let bits = (sprite_row.reverse_bits() >> (16 - sprite_width as u32)) as u128;
+        let inside = (bits << x) & self.row_mask();
+        let overflow = bits.checked_shr(width - x as u32).unwrap_or(0);
+        let sprite = if wrap { inside | overflow } else { inside };
 
-        let new_state = self.0[address] != 1;
-        self.0[address] = new_state as u8;
+        let row = &mut self.planes_rows[plane.trailing_zeros() as usize][y as usize];
+        let collided = *row & sprite != 0;
+        *row ^= sprite;
+        self.dirty |= sprite != 0;
 
-        new_state
+        Some(collided)
     }
 
-    pub fn get(&self) -> &[u8; (WIDTH * HEIGHT) as usize] {
-        &self.0
+    /// The pixels at the current resolution, one byte per pixel starting
+    /// from the top left corner, with a bit for each plane.
+    pub fn pixels(&self) -> Vec<u8> {
+        let (width, height) = (self.width() as usize, self.height() as usize);
+        let mut pixels = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            let [first, second] = [self.planes_rows[0][y], self.planes_rows[1][y]];
+            pixels.extend(
+                (0..width).map(|x| ((first >> x) & 1) as u8 | (((second >> x) & 1) as u8) << 1),
+            );
+        }
+
+        pixels
     }
-}
\ No newline at end of file
+
+    /// Replaces the screen with pixels laid out like [`Self::pixels`].
+    pub fn set_pixels(&mut self, pixels: &[u8]) {
+        let width = self.width() as usize;
+        self.clear();
+
+        for (y, row) in pixels.chunks_exact(width).enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                self.planes_rows[0][y] |= ((pixel & 1) as u128) << x;
+                self.planes_rows[1][y] |= (((pixel >> 1) & 1) as u128) << x;
+            }
+        }
+    }
+}
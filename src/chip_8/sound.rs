@@ -1,3 +1,290 @@
-// implement way to play a buzzer sound here
+//! The buzzer used by the sound timer.
+//!
+//! Audio is only produced when the crate is built with the `audio` feature,
+//! otherwise the buzzer silently keeps track of whether it should be playing.
+//!
+//! XO-CHIP programs can also give the buzzer a pattern of 128 1-bit samples
+//! to loop instead of its tone, at a rate set by the pitch.
 
-pub fn play_buzzer() {}
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use std::sync::Arc;
+
+#[cfg(feature = "audio")]
+use std::{sync::mpsc::Sender, thread::JoinHandle};
+
+/// The default frequency of the buzzer tone in Hz.
+pub const DEFAULT_FREQUENCY: u32 = 440;
+/// The XO-CHIP pitch programs start with, which plays the pattern at 4000
+/// samples a second.
+pub const DEFAULT_PITCH: u8 = 64;
+#[cfg(feature = "audio")]
+const SAMPLE_RATE: u32 = 44_100;
+/// Keeps the tone from being painfully loud.
+#[cfg(feature = "audio")]
+const AMPLITUDE: f32 = 0.2;
+
+/// The shape of the buzzer tone.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "desktop", derive(clap::ValueEnum))]
+#[repr(u8)]
+pub enum Waveform {
+    /// The classic harsh beep.
+    #[default]
+    Square,
+    /// A soft, pure tone.
+    Sine,
+    /// Somewhere in between a square and a sine wave.
+    Triangle,
+}
+
+impl Waveform {
+    #[cfg_attr(not(feature = "audio"), allow(dead_code))]
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Sine,
+            2 => Self::Triangle,
+            _ => Self::Square,
+        }
+    }
+
+    /// The value of the wave at `phase` (from 0 to 1 over one period),
+    /// between -1 and 1.
+    #[cfg_attr(not(feature = "audio"), allow(dead_code))]
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Self::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Self::Sine => (phase * std::f32::consts::TAU).sin(),
+            Self::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+        }
+    }
+}
+
+/// How many samples a second XO-CHIP plays the audio pattern at for
+/// `pitch`. Every 48 up doubles it.
+pub fn pattern_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - DEFAULT_PITCH as f32) / 48.0)
+}
+
+/// Settings shared between the emulator and the audio thread. These are
+/// atomics so the audio callback never has to wait on a lock.
+#[derive(Debug)]
+#[cfg_attr(not(feature = "audio"), allow(dead_code))]
+struct ToneState {
+    playing: AtomicBool,
+    /// Silences the buzzer without it losing track of whether it's playing.
+    muted: AtomicBool,
+    /// The tone frequency in Hz. A frequency of 0 mutes the buzzer.
+    frequency: AtomicU32,
+    /// A [`Waveform`] stored as a `u8`.
+    waveform: AtomicU8,
+    /// Whether to play `pattern` instead of the tone.
+    use_pattern: AtomicBool,
+    /// The XO-CHIP audio pattern, most significant bit first.
+    pattern: [AtomicU8; 16],
+    /// The XO-CHIP pitch, see [`pattern_rate`].
+    pitch: AtomicU8,
+}
+
+impl Default for ToneState {
+    fn default() -> Self {
+        Self {
+            playing: AtomicBool::new(false),
+            muted: AtomicBool::new(false),
+            frequency: AtomicU32::new(DEFAULT_FREQUENCY),
+            waveform: AtomicU8::new(Waveform::default() as u8),
+            use_pattern: AtomicBool::new(false),
+            pattern: Default::default(),
+            pitch: AtomicU8::new(DEFAULT_PITCH),
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+impl ToneState {
+    /// Whether anything should be heard right now.
+    fn sounding(&self) -> bool {
+        self.playing.load(Ordering::Relaxed) && !self.muted.load(Ordering::Relaxed)
+    }
+}
+
+/// Plays a tone for as long as the sound timer is above 0.
+///
+/// The output stream lives on its own thread (audio streams can't be moved
+/// between threads on every platform), and we just flip an atomic flag to
+/// start and stop the tone. Dropping the buzzer shuts the stream down.
+///
+/// Defaults to a 440Hz square wave.
+#[derive(Debug, Default)]
+pub struct Buzzer {
+    state: Arc<ToneState>,
+    #[cfg(feature = "audio")]
+    audio_thread: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+impl Buzzer {
+    /// Opens the default audio output. If there is no audio device (or the
+    /// `audio` feature is disabled), the buzzer stays silent.
+    pub fn new() -> Self {
+        let state = Arc::new(ToneState::default());
+
+        #[cfg(feature = "audio")]
+        let audio_thread = {
+            let (shutdown_sender, shutdown_receiver) = std::sync::mpsc::channel::<()>();
+            let tone = Tone {
+                state: state.clone(),
+                phase: 0.0,
+                position: 0.0,
+            };
+
+            let handle = std::thread::spawn(move || {
+                let (_stream, stream_handle) = match rodio::OutputStream::try_default() {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        log::warn!("Could not open audio output, sound is disabled: {e}");
+                        return;
+                    }
+                };
+                let sink = match rodio::Sink::try_new(&stream_handle) {
+                    Ok(sink) => sink,
+                    Err(e) => {
+                        log::warn!("Could not create audio sink, sound is disabled: {e}");
+                        return;
+                    }
+                };
+                sink.append(tone);
+
+                // Keep the stream alive until the buzzer is dropped, which
+                // disconnects the channel.
+                let _ = shutdown_receiver.recv();
+            });
+
+            Some((shutdown_sender, handle))
+        };
+
+        Self {
+            state,
+            #[cfg(feature = "audio")]
+            audio_thread,
+        }
+    }
+
+    /// Starts or stops the tone.
+    pub fn set_playing(&self, playing: bool) {
+        self.state.playing.store(playing, Ordering::Relaxed);
+    }
+
+    /// Mutes or unmutes the buzzer. The sound timer keeps counting down
+    /// either way, so unmuting in the middle of a beep plays the rest of it.
+    pub fn set_muted(&self, muted: bool) {
+        self.state.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// Changes the tone of the buzzer. A frequency of 0 mutes it entirely.
+    pub fn set_tone(&self, frequency: u32, waveform: Waveform) {
+        self.state.frequency.store(frequency, Ordering::Relaxed);
+        self.state.waveform.store(waveform as u8, Ordering::Relaxed);
+    }
+
+    /// Plays an XO-CHIP audio pattern at [`pattern_rate`] for `pitch`
+    /// instead of the tone, or goes back to the tone if there's no pattern.
+    pub fn set_pattern(&self, pattern: Option<[u8; 16]>, pitch: u8) {
+        for (stored, &byte) in self.state.pattern.iter().zip(&pattern.unwrap_or_default()) {
+            stored.store(byte, Ordering::Relaxed);
+        }
+        self.state.pitch.store(pitch, Ordering::Relaxed);
+        self.state
+            .use_pattern
+            .store(pattern.is_some(), Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "audio")]
+impl Drop for Buzzer {
+    fn drop(&mut self) {
+        if let Some((shutdown_sender, handle)) = self.audio_thread.take() {
+            drop(shutdown_sender);
+            let _ = handle.join();
+        }
+    }
+}
+
+/// An endless wave that outputs silence while the buzzer is off.
+///
+/// Checking the state on every sample means the tone starts and stops
+/// within one audio buffer of the sound timer changing.
+#[cfg(feature = "audio")]
+struct Tone {
+    state: Arc<ToneState>,
+    /// How far we are through the current period, from 0 to 1.
+    phase: f32,
+    /// Which sample of the audio pattern we're on, from 0 to 128.
+    position: f32,
+}
+
+#[cfg(feature = "audio")]
+impl Iterator for Tone {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.state.use_pattern.load(Ordering::Relaxed) {
+            return Some(self.next_pattern_sample());
+        }
+
+        let frequency = self.state.frequency.load(Ordering::Relaxed);
+        self.phase = (self.phase + frequency as f32 / SAMPLE_RATE as f32) % 1.0;
+
+        if !self.state.sounding() || frequency == 0 {
+            return Some(0.0);
+        }
+
+        let waveform = Waveform::from_u8(self.state.waveform.load(Ordering::Relaxed));
+        Some(waveform.sample(self.phase) * AMPLITUDE)
+    }
+}
+
+#[cfg(feature = "audio")]
+impl Tone {
+    /// The next sample of the audio pattern, which is a square wave of
+    /// sorts: each bit is either all the way up or all the way down.
+    fn next_pattern_sample(&mut self) -> f32 {
+        let rate = pattern_rate(self.state.pitch.load(Ordering::Relaxed));
+        self.position = (self.position + rate / SAMPLE_RATE as f32) % 128.0;
+
+        if !self.state.sounding() {
+            return 0.0;
+        }
+
+        let bit = self.position as usize;
+        let byte = self.state.pattern[bit / 8].load(Ordering::Relaxed);
+        if byte & (0x80 >> (bit % 8)) != 0 {
+            AMPLITUDE
+        } else {
+            -AMPLITUDE
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+impl rodio::Source for Tone {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
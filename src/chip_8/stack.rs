@@ -1,14 +1,30 @@
 use crate::chip_8::{Chip8, Chip8Error};
 
-// For the stack, the bottom of our stack if at 0x1FE (must be an even number
-// if we want to increase the stack by 2 at a time), and the
-// top is at 0x000. I've always wanted to actually implement
-// a stack and I wanted to have it grow downwards for the
-// true stack experience.
-pub(crate) const STACK_WINDOW_BOTTOM: u16 = 0x1FE;
-pub(crate) const STACK_WINDOW_TOP: u16 = 0x000;
+/// How many return addresses fit on the stack. The COSMAC VIP had room for
+/// 12, later interpreters usually have 16.
+pub const STACK_DEPTH: u16 = 16;
+
+// The stack pointer points at the most recent entry, so an empty stack
+// points just past the window at 0x200 (an even number, since we push 2 bytes
+// at a time). The stack then grows downwards towards 0x1E0, well clear of the
+// font. I've always wanted to actually implement a stack and I wanted to
+// have it grow downwards for the true stack experience.
+pub(crate) const STACK_WINDOW_BOTTOM: u16 = 0x200;
+pub(crate) const STACK_WINDOW_TOP: u16 = STACK_WINDOW_BOTTOM - 2 * STACK_DEPTH;
 
 impl Chip8 {
+    /// The return addresses on the stack, starting with the oldest one.
+    ///
+    /// The stack lives in interpreter memory, so this reads the addresses
+    /// back out of it.
+    pub fn stack(&self) -> Vec<u16> {
+        (self.stack_pointer..STACK_WINDOW_BOTTOM)
+            .step_by(2)
+            .rev()
+            .map(|address| self.memory.word(address as usize))
+            .collect()
+    }
+
     pub(crate) fn push(&mut self, word: u16) -> Result<(), Chip8Error> {
         if self.stack_pointer == STACK_WINDOW_TOP {
             return Err(Chip8Error::StackOverflow);
@@ -16,6 +32,8 @@ impl Chip8 {
 
         self.stack_pointer -= 2;
         self.memory.set_word(self.stack_pointer as usize, word);
+        let depth = (STACK_WINDOW_BOTTOM - self.stack_pointer) / 2;
+        self.stats.max_stack_depth = self.stats.max_stack_depth.max(depth);
 
         Ok(())
     }
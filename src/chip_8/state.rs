@@ -0,0 +1,203 @@
+//! Save states, which snapshot the whole machine so it can be resumed later.
+//!
+//! The format is deliberately simple: a magic number and a version byte,
+//! followed by every part of the machine in a fixed order. Multi-byte values
+//! are big endian, like the rest of CHIP-8.
+
+use crate::chip_8::{
+    memory::{Memory, EXTENDED_MEMORY_SIZE, MEMORY_SIZE},
+    stack, Chip8, Chip8Error, EmulatorState,
+};
+use crate::{HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, WIDTH};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+/// Bump this whenever the layout changes, so old states get rejected instead
+/// of loading garbage.
+const VERSION: u8 = 4;
+/// Magic, version, registers, I, PC, SP, both timers and whether memory is
+/// extended. Memory comes next, then the selected planes and the resolution,
+/// and then the screen, whose size depends on the resolution.
+const FIXED_HEADER_SIZE: usize = 4 + 1 + 16 + 2 + 2 + 2 + 1 + 1 + 1;
+
+/// The size of everything before the screen, with `memory_size` bytes of
+/// memory.
+const fn header_size(memory_size: usize) -> usize {
+    FIXED_HEADER_SIZE + memory_size + 1 + 1
+}
+
+impl Chip8 {
+    /// Snapshots the registers, I, PC, stack, memory, screen (with its
+    /// resolution and selected planes) and both timers.
+    /// Restore it with [`Self::load_state`].
+    ///
+    /// Settings like [`Self::quirks`] and breakpoints aren't part of the
+    /// state.
+    pub fn save_state(&self) -> Vec<u8> {
+        let screen = self.screen.pixels();
+        let mut state = Vec::with_capacity(header_size(self.memory.len()) + screen.len());
+
+        state.extend_from_slice(MAGIC);
+        state.push(VERSION);
+        state.extend_from_slice(&self.registers);
+        state.extend_from_slice(&self.index_register.to_be_bytes());
+        state.extend_from_slice(&self.program_counter.to_be_bytes());
+        state.extend_from_slice(&self.stack_pointer.to_be_bytes());
+        state.push(self.delay_timer.value());
+        state.push(self.sound_timer.value());
+        state.push((self.memory.len() == EXTENDED_MEMORY_SIZE) as u8);
+        state.extend_from_slice(self.memory.as_slice());
+        state.push(self.screen.planes());
+        state.push(self.screen.hires() as u8);
+        state.extend_from_slice(&screen);
+
+        state
+    }
+
+    /// Restores a snapshot made by [`Self::save_state`]. The state is checked
+    /// before anything is changed, so the emulator is left alone if it is
+    /// invalid.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), Chip8Error> {
+        let invalid = |reason| Chip8Error::InvalidSaveState { reason };
+
+        if state.len() < MAGIC.len() + 1 || &state[..MAGIC.len()] != MAGIC {
+            return Err(invalid("not a save state"));
+        }
+        if state[MAGIC.len()] != VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        if state.len() < FIXED_HEADER_SIZE {
+            return Err(invalid("wrong length"));
+        }
+        let memory_size = match state[FIXED_HEADER_SIZE - 1] {
+            0 => MEMORY_SIZE,
+            1 => EXTENDED_MEMORY_SIZE,
+            _ => return Err(invalid("unknown memory size")),
+        };
+        let header_size = header_size(memory_size);
+        if state.len() < header_size {
+            return Err(invalid("wrong length"));
+        }
+        let screen_size = match state[header_size - 1] {
+            0 => (WIDTH * HEIGHT) as usize,
+            1 => (HIRES_WIDTH * HIRES_HEIGHT) as usize,
+            _ => return Err(invalid("unknown resolution")),
+        };
+        if state.len() != header_size + screen_size {
+            return Err(invalid("wrong length"));
+        }
+
+        let mut rest = &state[MAGIC.len() + 1..];
+        let mut take = |len: usize| {
+            let (taken, remaining) = rest.split_at(len);
+            rest = remaining;
+            taken
+        };
+        let word = |bytes: &[u8]| u16::from_be_bytes([bytes[0], bytes[1]]);
+
+        let registers = take(16);
+        let index_register = word(take(2));
+        let program_counter = word(take(2));
+        let stack_pointer = word(take(2));
+        let delay_timer = take(1)[0];
+        let sound_timer = take(1)[0];
+        take(1);
+        let memory = take(memory_size);
+        let planes = take(1)[0];
+        let hires = take(1)[0] == 1;
+        let screen = take(screen_size);
+
+        if program_counter as usize >= memory_size {
+            return Err(invalid("program counter is out of memory"));
+        }
+        if !(stack::STACK_WINDOW_TOP..=stack::STACK_WINDOW_BOTTOM).contains(&stack_pointer)
+            || stack_pointer % 2 != 0
+        {
+            return Err(invalid("stack pointer is outside the stack"));
+        }
+        if planes > 0b11 {
+            return Err(invalid("unknown planes"));
+        }
+        if screen.iter().any(|&pixel| pixel > 0b11) {
+            return Err(invalid("screen pixels must be between 0 and 3"));
+        }
+
+        self.registers.copy_from_slice(registers);
+        self.index_register = index_register;
+        self.program_counter = program_counter;
+        self.stack_pointer = stack_pointer;
+        self.delay_timer.set(delay_timer);
+        self.sound_timer.set(sound_timer);
+        self.memory = Memory::new(memory_size);
+        self.memory.as_mut_slice().copy_from_slice(memory);
+        self.screen.set_hires(hires);
+        self.screen.select_planes(planes);
+        self.screen.set_pixels(screen);
+
+        self.breakpoint_hit = None;
+        self.waiting_for_vblank = false;
+        self.idle = false;
+        self.awaited_key = None;
+        self.emulator_state = EmulatorState::ProgramLoaded;
+        self.update_buzzer();
+        self.send_frame();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use crate::{Chip8, Chip8Error};
+
+    fn load(program: &[u8]) -> Chip8 {
+        let mut chip_8 = Chip8::headless();
+        chip_8.initialize().unwrap();
+        chip_8.load_program(program.to_vec()).unwrap();
+        chip_8
+    }
+
+    #[test]
+    fn restores_saved_state() {
+        let program = [
+            0x60, 0x05, // LD V0, 0x05
+            0xF0, 0x15, // LD DT, V0
+            0x22, 0x08, // CALL 0x208
+            0x00, 0x00, 0xF0, 0x29, // LD F, V0
+            0xD0, 0x05, // DRW V0, V0, 5
+            0x60, 0x00, // LD V0, 0x00
+        ];
+        let mut chip_8 = load(&program);
+        for _ in 0..5 {
+            chip_8.cycle().unwrap();
+        }
+        let state = chip_8.save_state();
+
+        let mut restored = load(&[]);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.registers(), chip_8.registers());
+        assert_eq!(restored.index(), chip_8.index());
+        assert_eq!(restored.program_counter(), chip_8.program_counter());
+        assert_eq!(restored.stack(), chip_8.stack());
+        assert_eq!(restored.memory(), chip_8.memory());
+        assert_eq!(restored.screen(), chip_8.screen());
+        assert_eq!(restored.delay_timer.0, chip_8.delay_timer.0);
+
+        restored.cycle().unwrap();
+        chip_8.cycle().unwrap();
+        assert_eq!(restored.save_state(), chip_8.save_state());
+    }
+
+    #[test]
+    fn rejects_corrupt_state() {
+        let mut chip_8 = load(&[0x60, 0x05]);
+        let state = chip_8.save_state();
+
+        for corrupt in [&state[..100], &state[1..], &[]] {
+            assert!(matches!(
+                chip_8.load_state(corrupt),
+                Err(Chip8Error::InvalidSaveState { .. })
+            ));
+        }
+        assert_eq!(chip_8.program_counter(), 0x200);
+    }
+}
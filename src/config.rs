@@ -0,0 +1,370 @@
+//! Settings that are remembered between runs, kept in the user's config
+//! directory, and the TOML file given with `--config`.
+//!
+//! Remembered settings are plain `key = value` lines. Anything we don't
+//! understand is skipped rather than treated as an error, so an old or hand
+//! edited file never stops the emulator from starting. The `--config` file
+//! is written by hand on purpose, so it's strict about what's in it instead.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use log::{debug, warn};
+use serde::Deserialize;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::window::Window;
+
+const APP_NAME: &str = "chip-8-emulator";
+const WINDOW_FILE: &str = "window.conf";
+/// Each ROM gets its own file in here, named after the ROM.
+const ROMS_DIR: &str = "roms";
+
+/// Where our config files go, following each platform's convention:
+/// `%APPDATA%` on Windows, `~/Library/Application Support` on macOS and
+/// `$XDG_CONFIG_HOME` (or `~/.config`) everywhere else.
+pub fn dir() -> Option<PathBuf> {
+    let env = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    let base = if cfg!(windows) {
+        env("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|base| base.join(APP_NAME))
+}
+
+/// Writes `text` to `file` in the config directory, making the directory
+/// if needed. Returns where it went.
+fn write(file: impl AsRef<Path>, text: &str) -> io::Result<PathBuf> {
+    let path = dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?
+        .join(file);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, text)?;
+    Ok(path)
+}
+
+/// The size and position the window was left at.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Geometry {
+    pub size: Option<PhysicalSize<u32>>,
+    /// Not every platform lets us see or set where the window is (Wayland
+    /// doesn't), so this can be missing even when the size isn't.
+    pub position: Option<PhysicalPosition<i32>>,
+}
+
+impl Geometry {
+    /// Reads the geometry saved by the last run. Missing or broken files
+    /// give an empty geometry.
+    pub fn load() -> Self {
+        let Some(path) = dir().map(|dir| dir.join(WINDOW_FILE)) else {
+            return Self::default();
+        };
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+
+        let values: HashMap<_, _> = parse(&text).collect();
+
+        Self {
+            size: number(&values, "width")
+                .zip(number(&values, "height"))
+                .filter(|&(width, height)| width > 0 && height > 0)
+                .map(|(width, height)| PhysicalSize::new(width, height)),
+            position: number(&values, "x")
+                .zip(number(&values, "y"))
+                .map(|(x, y)| PhysicalPosition::new(x, y)),
+        }
+    }
+
+    /// Takes the geometry of `window`. Fullscreen windows give an empty
+    /// geometry, since that size isn't the one to come back to.
+    pub fn of(window: &Window) -> Self {
+        if window.fullscreen().is_some() {
+            return Self::default();
+        }
+        Self {
+            size: Some(window.inner_size()),
+            position: window.outer_position().ok(),
+        }
+    }
+
+    /// Writes the geometry for the next run. Failing to only logs a
+    /// warning, since it's not worth bothering anyone about while they quit.
+    pub fn save(&self) {
+        let Some(size) = self.size else {
+            return;
+        };
+        let mut text = format!("width = {}\nheight = {}\n", size.width, size.height);
+        if let Some(position) = self.position {
+            text += &format!("x = {}\ny = {}\n", position.x, position.y);
+        }
+        match write(WINDOW_FILE, &text) {
+            Ok(path) => debug!("Saved the window size to {}", path.display()),
+            Err(e) => warn!("Could not save the window size: {e}"),
+        }
+    }
+}
+
+/// Command line flags remembered for one ROM, like `fg = #33FF33` or
+/// `quirk-wrap-sprites = true`, keyed by the flag's long name. ROMs are
+/// told apart by their file name, so a ROM keeps its settings while it's
+/// being edited with `--watch`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RomSettings {
+    flags: Vec<(String, String)>,
+}
+
+impl RomSettings {
+    /// Reads the settings saved for `rom`. ROMs that haven't been run
+    /// before get empty settings.
+    pub fn load(rom: &Path) -> Self {
+        let text = Self::file(rom)
+            .and_then(|file| std::fs::read_to_string(dir()?.join(file)).ok())
+            .unwrap_or_default();
+        let mut settings = Self::default();
+        for (flag, value) in parse(&text) {
+            settings.set(flag, value);
+        }
+        settings
+    }
+
+    /// Every remembered flag and its value, in the order they were set.
+    pub fn flags(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.flags
+            .iter()
+            .map(|(flag, value)| (flag.as_str(), value.as_str()))
+    }
+
+    /// Remembers `value` for `flag`, replacing what was there.
+    pub fn set(&mut self, flag: &str, value: &str) {
+        match self.flags.iter_mut().find(|(found, _)| found == flag) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.flags.push((flag.to_string(), value.to_string())),
+        }
+    }
+
+    /// Writes the settings for the next time `rom` is run. Failing to only
+    /// logs a warning, like with [`Geometry::save`].
+    pub fn save(&self, rom: &Path) {
+        let Some(file) = Self::file(rom) else {
+            return;
+        };
+        let text: String = self
+            .flags()
+            .map(|(flag, value)| format!("{flag} = {value}\n"))
+            .collect();
+        match write(file, &text) {
+            Ok(path) => debug!("Saved the settings for this ROM to {}", path.display()),
+            Err(e) => warn!("Could not save the settings for this ROM: {e}"),
+        }
+    }
+
+    /// Where the settings for `rom` go, relative to the config directory.
+    fn file(rom: &Path) -> Option<PathBuf> {
+        let mut name = rom.file_name()?.to_os_string();
+        name.push(".conf");
+        Some(Path::new(ROMS_DIR).join(name))
+    }
+}
+
+/// The settings in a `--config` file, which are the same as the command
+/// line flags of the same name:
+///
+/// ```toml
+/// rom = "games/pong.ch8"
+/// cps = 1000
+/// fg = "#33FF33"
+///
+/// [quirks]
+/// wrap-sprites = true
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    rom: Option<String>,
+    scale: Option<u32>,
+    cps: Option<u32>,
+    fps: Option<u32>,
+    fg: Option<String>,
+    bg: Option<String>,
+    fg2: Option<String>,
+    blend: Option<String>,
+    platform: Option<String>,
+    keymap: Option<String>,
+    beep_hz: Option<u32>,
+    waveform: Option<String>,
+    mute: Option<bool>,
+    quirks: QuirkConfig,
+}
+
+/// The `[quirks]` table of a [`Config`], one key per `--quirk-*` flag.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct QuirkConfig {
+    shift_uses_vy: Option<bool>,
+    load_store_increments_i: Option<bool>,
+    jump_uses_vx: Option<bool>,
+    index_overflow_sets_vf: Option<bool>,
+    logic_resets_vf: Option<bool>,
+    display_wait: Option<bool>,
+    wrap_sprites: Option<bool>,
+    large_sprites: Option<bool>,
+    draw_timing: Option<bool>,
+    extended_memory: Option<bool>,
+    audio_pattern: Option<bool>,
+}
+
+impl Config {
+    /// Reads the config file at `path`. Unknown keys and values of the
+    /// wrong type are errors, with the line they're on.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Every setting in the file and its value, keyed by the flag's long
+    /// name. They're passed along as `--flag=value`, so they go through the
+    /// same checks as the command line does.
+    pub fn flags(&self) -> Vec<(&'static str, String)> {
+        let quirks = &self.quirks;
+        let values: [(&str, Option<String>); 24] = [
+            ("rom", self.rom.clone()),
+            ("scale", self.scale.map(|scale| scale.to_string())),
+            ("cps", self.cps.map(|cps| cps.to_string())),
+            ("fps", self.fps.map(|fps| fps.to_string())),
+            ("fg", self.fg.clone()),
+            ("bg", self.bg.clone()),
+            ("fg2", self.fg2.clone()),
+            ("blend", self.blend.clone()),
+            ("platform", self.platform.clone()),
+            ("keymap", self.keymap.clone()),
+            ("beep-hz", self.beep_hz.map(|hz| hz.to_string())),
+            ("waveform", self.waveform.clone()),
+            ("mute", self.mute.map(|mute| mute.to_string())),
+            (
+                "quirk-shift-uses-vy",
+                quirks.shift_uses_vy.map(|q| q.to_string()),
+            ),
+            (
+                "quirk-load-store-increments-i",
+                quirks.load_store_increments_i.map(|q| q.to_string()),
+            ),
+            (
+                "quirk-jump-uses-vx",
+                quirks.jump_uses_vx.map(|q| q.to_string()),
+            ),
+            (
+                "quirk-index-overflow-sets-vf",
+                quirks.index_overflow_sets_vf.map(|q| q.to_string()),
+            ),
+            (
+                "quirk-logic-resets-vf",
+                quirks.logic_resets_vf.map(|q| q.to_string()),
+            ),
+            (
+                "quirk-display-wait",
+                quirks.display_wait.map(|q| q.to_string()),
+            ),
+            (
+                "quirk-wrap-sprites",
+                quirks.wrap_sprites.map(|q| q.to_string()),
+            ),
+            (
+                "quirk-large-sprites",
+                quirks.large_sprites.map(|q| q.to_string()),
+            ),
+            (
+                "quirk-draw-timing",
+                quirks.draw_timing.map(|q| q.to_string()),
+            ),
+            (
+                "quirk-extended-memory",
+                quirks.extended_memory.map(|q| q.to_string()),
+            ),
+            (
+                "quirk-audio-pattern",
+                quirks.audio_pattern.map(|q| q.to_string()),
+            ),
+        ];
+        values
+            .into_iter()
+            .filter_map(|(flag, value)| Some((flag, value?)))
+            .collect()
+    }
+}
+
+/// Splits `key = value` lines, skipping blank lines and `#` comments.
+fn parse(text: &str) -> impl Iterator<Item = (&str, &str)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim()))
+}
+
+/// Parses the value of `key`, if it's there and makes sense.
+fn number<T: FromStr>(values: &HashMap<&str, &str>, key: &str) -> Option<T> {
+    values.get(key).and_then(|value| value.parse().ok())
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn turns_settings_into_flags() {
+        let config: Config = toml::from_str(
+            "cps = 1000\nfg = \"#33FF33\"\nmute = true\n[quirks]\nwrap-sprites = false\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.flags(),
+            [
+                ("cps", "1000".to_string()),
+                ("fg", "#33FF33".to_string()),
+                ("mute", "true".to_string()),
+                ("quirk-wrap-sprites", "false".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let error = toml::from_str::<Config>("cps = 1000\ncolour = \"#33FF33\"\n").unwrap_err();
+        assert!(
+            error.to_string().contains("unknown field `colour`"),
+            "{error}"
+        );
+        assert!(error.to_string().contains("line 2"), "{error}");
+
+        let error = toml::from_str::<Config>("[quirks]\nwrap = true\n").unwrap_err();
+        assert!(
+            error.to_string().contains("unknown field `wrap`"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn rejects_values_of_the_wrong_type() {
+        let error = toml::from_str::<Config>("cps = \"fast\"\n").unwrap_err();
+        assert!(error.to_string().contains("invalid type"), "{error}");
+        assert!(error.to_string().contains("line 1"), "{error}");
+
+        let error = Config::load(Path::new("missing.toml")).unwrap_err();
+        assert!(error.starts_with("missing.toml: "), "{error}");
+    }
+
+    #[test]
+    fn skips_what_it_does_not_understand_in_remembered_settings() {
+        let values: Vec<_> = parse("# comment\n\nscale = 4\nnonsense\n fg =  #33FF33 \n").collect();
+        assert_eq!(values, [("scale", "4"), ("fg", "#33FF33")]);
+    }
+}
@@ -0,0 +1,58 @@
+//! A phosphor effect that makes pixels fade out instead of snapping off.
+//!
+//! CHIP-8 games erase and redraw sprites all the time, which flickers badly.
+//! Letting unlit pixels fade over a few frames, like on a CRT, hides most of
+//! it.
+
+/// Remembers the color of every pixel on screen so they can fade out.
+#[derive(Debug)]
+pub struct Fade {
+    colors: Vec<[f32; 4]>,
+    /// How much of a pixel's brightness is left after each frame, from 0
+    /// (no fading) to 1 (never fade).
+    decay: f32,
+}
+
+impl Fade {
+    /// Creates a fade where pixels keep `decay` of their brightness every
+    /// frame.
+    pub fn new(decay: f32) -> Self {
+        Self {
+            colors: Vec::new(),
+            decay,
+        }
+    }
+
+    /// Moves every pixel one frame closer to `frame`, and writes the result
+    /// to the RGBA buffer `out`. Lit pixels show up right away, while unlit
+    /// ones fade towards their color.
+    pub fn update(&mut self, frame: &[u8], palette: &[[u8; 4]; 4], out: &mut [u8]) {
+        // Start over if the resolution changed.
+        if self.colors.len() != frame.len() {
+            self.colors = frame
+                .iter()
+                .map(|&pixel| palette[pixel as usize].map(|channel| channel as f32))
+                .collect();
+        }
+
+        for ((color, &pixel), rgba) in self
+            .colors
+            .iter_mut()
+            .zip(frame)
+            .zip(out.chunks_exact_mut(4))
+        {
+            let target = palette[pixel as usize].map(|channel| channel as f32);
+            if pixel == 0 {
+                for (channel, target) in color.iter_mut().zip(target) {
+                    *channel = target + (*channel - target) * self.decay;
+                }
+            } else {
+                *color = target;
+            }
+
+            for (out, channel) in rgba.iter_mut().zip(*color) {
+                *out = channel.round() as u8;
+            }
+        }
+    }
+}
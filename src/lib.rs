@@ -0,0 +1,7 @@
+//! A CHIP-8 emulator. See [`Chip8`] to get started.
+
+mod chip_8;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use chip_8::*;
@@ -1,118 +1,760 @@
-use chip_8::{Chip8, Chip8Error};
-use chip_8::{HEIGHT, WIDTH};
-use clap::Parser;
+use chip_8_emulator::keypad::{self, Hotkey, Keymap};
+use chip_8_emulator::quirks::{Platform, Quirks};
+use chip_8_emulator::replay::{InputLog, Player};
+use chip_8_emulator::rewind::{self, Rewind};
+use chip_8_emulator::sound::{self, Waveform};
+use chip_8_emulator::{Chip8, Chip8Error, CycleOutcome, Instruction, Keypad, RunState, Stats};
+use chip_8_emulator::{HEIGHT, TIMER_INTERVAL, WIDTH};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgAction, CommandFactory, FromArgMatches};
 use env_logger::Env;
-use log::{error, info};
+use log::{debug, error, info, trace, warn};
 use pixels::{Pixels, SurfaceTexture};
+use std::ffi::OsString;
 use std::io::Write;
-use std::sync::mpsc::{channel, TryRecvError};
-use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex, PoisonError};
 use std::thread::sleep;
 use std::time::{Duration, Instant};
 use winit::{
     dpi::LogicalSize,
     event::Event,
     event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    window::{Fullscreen, Window, WindowBuilder},
 };
 use winit_input_helper::WinitInputHelper;
 
-mod chip_8;
+mod capture;
+mod config;
+mod fade;
+mod overlay;
+mod scaling;
+#[cfg(feature = "tui")]
+mod tui;
+mod watch;
 
-// We scale everything up by a factor of 8
-const SCALE: u32 = 8;
-const HZ: u32 = 30;
-const CYCLES_PER_SECOND: u32 = 720;
-const CYCLES_PER_FRAME: u32 = CYCLES_PER_SECOND / HZ;
-const CYCLES_PER_CLOCK: u32 = CYCLES_PER_SECOND / 60;
+use capture::Recording;
+use config::{Config, Geometry, RomSettings};
+use fade::Fade;
+use overlay::Readout;
+use scaling::{Scaler, Scaling};
+
+// By default we scale everything up by a factor of 8
+const DEFAULT_SCALE: u32 = 8;
+const DEFAULT_FPS: u32 = 60;
+const DEFAULT_CYCLES_PER_SECOND: u32 = 720;
+/// The file extensions ROMs are expected to have, the last being Octo source.
+const ROM_EXTENSIONS: [&str; 4] = ["ch8", "sc8", "xo8", "8o"];
+/// Flags that are remembered for each ROM, so they only have to be given
+/// once. Anything given on the command line wins over what was remembered.
+const REMEMBERED_FLAGS: &[&str] = &[
+    "scale",
+    "fg",
+    "bg",
+    "fg2",
+    "blend",
+    "platform",
+    "quirk-shift-uses-vy",
+    "quirk-load-store-increments-i",
+    "quirk-jump-uses-vx",
+    "quirk-index-overflow-sets-vf",
+    "quirk-logic-resets-vf",
+    "quirk-display-wait",
+    "quirk-wrap-sprites",
+    "quirk-large-sprites",
+    "quirk-draw-timing",
+    "quirk-extended-memory",
+    "quirk-audio-pattern",
+];
 #[derive(clap::Parser, Debug)]
 struct Args {
     /// Path to the ROM that will be loaded.
     #[arg(short, long)]
     rom: String,
+    /// TOML file with settings to use instead of the defaults, like
+    /// `cps = 1000` or a `[quirks]` table. Flags given on the command line
+    /// win over it, and it wins over the settings remembered for the ROM.
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// How many times to scale up the 64x32 display for the window [default:
+    /// 8]. Without it, the window opens at the size it was last closed at.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    scale: Option<u32>,
+    /// How to fit the picture into the window when it is resized.
+    #[arg(long, value_enum, default_value_t = Scaling::Integer)]
+    scaling: Scaling,
+    /// How many times a second to redraw the window. This doesn't change
+    /// how fast the game runs, which is --cps, and the timers always count
+    /// down at 60Hz. GIF recordings are made at this rate too.
+    #[arg(long, default_value_t = DEFAULT_FPS, value_parser = clap::value_parser!(u32).range(1..=240))]
+    fps: u32,
+    /// How many instructions to run per second.
+    #[arg(long, visible_alias = "cycles-per-second", default_value_t = DEFAULT_CYCLES_PER_SECOND, value_parser = clap::value_parser!(u32).range(1..))]
+    cps: u32,
+    /// Change the speed as the game goes on, like `0:300,500:720` for 300
+    /// instructions per second until cycle 500 and 720 after. Before the
+    /// first entry, --cps is used. Only applies to the game loop, not to
+    /// --bench, --hash-after or --tui, and has to be given again to
+    /// replay a recording made with it.
+    #[arg(long, value_name = "CYCLE:CPS,...", value_parser = parse_cps_schedule)]
+    cps_schedule: Option<CpsSchedule>,
+    /// Never run more than this many instructions per second of real time,
+    /// whatever --cps or --cps-schedule ask for. Turbo can go over it by
+    /// the --turbo multiplier, but no further. Like --cps-schedule, this
+    /// only applies to the game loop.
+    #[arg(long, value_name = "CPS", value_parser = clap::value_parser!(u32).range(1..))]
+    max_cps: Option<u32>,
+    /// Color of lit pixels, as a hex code like `#33FF33`.
+    #[arg(long, default_value = "#FFFFFF", value_parser = parse_color)]
+    fg: [u8; 4],
+    /// Color of unlit pixels, as a hex code like `#000000`.
+    #[arg(long, default_value = "#000000", value_parser = parse_color)]
+    bg: [u8; 4],
+    /// Color of pixels lit only on the second XO-CHIP plane.
+    #[arg(long, default_value = "#FF6600", value_parser = parse_color)]
+    fg2: [u8; 4],
+    /// Color of pixels lit on both XO-CHIP planes.
+    #[arg(long, default_value = "#662200", value_parser = parse_color)]
+    blend: [u8; 4],
+    /// Fade pixels out over a few frames instead of turning them off right
+    /// away, which hides most of the flicker.
+    #[arg(long)]
+    fade: bool,
+    /// Only show the screen once a frame, with everything drawn during the
+    /// frame, so sprites that are erased and drawn again don't flicker.
+    #[arg(long)]
+    coalesce_draws: bool,
+    /// How much brightness a fading pixel keeps each frame, from 0 to 1.
+    /// Higher values fade slower.
+    #[arg(long, default_value_t = 0.6, value_parser = parse_decay)]
+    decay: f32,
+    /// Start with the debugging overlay (F3) showing the program counter,
+    /// I, the registers, the stack depth and the cycle count.
+    #[arg(long)]
+    overlay: bool,
+    /// Color of the overlay's text, to stand out against the palette.
+    #[arg(long, default_value = "#FFFF00", value_parser = parse_color)]
+    overlay_color: [u8; 4],
+    /// Frequency of the buzzer in Hz. A frequency of 0 mutes the buzzer.
+    #[arg(long, default_value_t = sound::DEFAULT_FREQUENCY)]
+    beep_hz: u32,
+    /// Shape of the buzzer tone.
+    #[arg(long, value_enum, default_value_t = Waveform::Square)]
+    waveform: Waveform,
+    /// Start with the buzzer muted. Press F8 to unmute it.
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        default_value_t = false,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    mute: bool,
+    /// Start with the display frozen, while the game keeps running. Press
+    /// F4 to unfreeze it.
+    #[arg(long)]
+    freeze_display: bool,
+    /// Start with emulation paused. Press P to resume, N to run a single
+    /// instruction or M to run a single frame.
+    #[arg(long)]
+    paused: bool,
+    /// Where to load the ROM and start running it from. Defaults to 0x200;
+    /// ETI-660 programs use 0x600.
+    #[arg(long, value_name = "ADDRESS", value_parser = parse_address)]
+    load_address: Option<u16>,
+    /// Pause when the program counter reaches this address (like 0x2A0).
+    /// Can be given more than once.
+    #[arg(long = "break", value_name = "ADDRESS", value_parser = parse_address)]
+    breakpoints: Vec<u16>,
+    /// Pause right after an instruction changes this register (like V3).
+    /// Can be given more than once.
+    #[arg(long, value_name = "REGISTER", value_parser = parse_register)]
+    watch_register: Vec<u8>,
+    /// Pause right after an instruction changes the byte at this address
+    /// (like 0x3A0). Can be given more than once.
+    #[arg(long, value_name = "ADDRESS", value_parser = parse_address)]
+    watch_memory: Vec<u16>,
+    /// Warn when the program runs into the fonts below 0x200, or into bytes
+    /// it wrote itself. Handy for tracking down broken jumps.
+    #[arg(long)]
+    guard_code: bool,
+    /// Keep track of which addresses run, and print them as ranges when F7
+    /// is pressed. Whatever never runs is most likely data.
+    #[arg(long)]
+    coverage: bool,
+    /// Print how many instructions, draws and collisions there were (and
+    /// how deep the stack got) on exit.
+    #[arg(long)]
+    stats: bool,
+    /// Stop with an error on 0NNN machine code calls, instead of skipping
+    /// them with a warning.
+    #[arg(long)]
+    strict_machine_code: bool,
+    /// Skip invalid opcodes with a warning, instead of stopping with an
+    /// error.
+    #[arg(long)]
+    lenient: bool,
+    /// Which interpreter's quirks to start from. The --quirk-* flags below
+    /// change individual quirks on top of it (like --quirk-wrap-sprites=false).
+    /// Defaults to whatever the ROM is known to need, or chip8.
+    #[arg(long, value_enum)]
+    platform: Option<Platform>,
+    /// Don't look the ROM up in the table of ROMs that need a specific
+    /// platform.
+    #[arg(long)]
+    no_autodetect: bool,
+    /// Make 8XY6 and 8XYE shift VY into VX, like the original COSMAC VIP.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    quirk_shift_uses_vy: Option<bool>,
+    /// Make FX55 and FX65 increment I past the registers they touch, like
+    /// the original COSMAC VIP.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    quirk_load_store_increments_i: Option<bool>,
+    /// Make BXNN jump to XNN plus VX, like SUPER-CHIP, instead of NNN plus V0.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    quirk_jump_uses_vx: Option<bool>,
+    /// Make FX1E set VF when I goes past 0xFFF, like the Amiga interpreter.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    quirk_index_overflow_sets_vf: Option<bool>,
+    /// Make 8XY1, 8XY2 and 8XY3 reset VF, like the original COSMAC VIP.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    quirk_logic_resets_vf: Option<bool>,
+    /// Make DXYN wait for the next 60Hz tick, like the COSMAC VIP waiting
+    /// for the vertical blank. Off by default so ROMs run at full speed.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    quirk_display_wait: Option<bool>,
+    /// Wrap sprites around the edges of the screen instead of clipping them.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    quirk_wrap_sprites: Option<bool>,
+    /// Make DXY0 draw a 16x16 sprite, like SUPER-CHIP.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    quirk_large_sprites: Option<bool>,
+    /// Make DXYN take longer the taller the sprite, roughly like the COSMAC
+    /// VIP, for ROMs tuned to its speed.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    quirk_draw_timing: Option<bool>,
+    /// Give programs 64 KB of memory, like XO-CHIP.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    quirk_extended_memory: Option<bool>,
+    /// Play the sample pattern XO-CHIP programs load with F002, at the
+    /// pitch set with FX3A, instead of the buzzer tone.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    quirk_audio_pattern: Option<bool>,
+    /// How many frames of history to keep for rewinding (hold Backspace),
+    /// at 60 frames a second. 0 turns rewinding off. Each frame takes about
+    /// 6KB, or 66KB for XO-CHIP ROMs, so the default is around 3.6MB (or
+    /// 40MB).
+    #[arg(long, default_value_t = rewind::DEFAULT_CAPACITY)]
+    rewind_frames: usize,
+    /// How many times faster to run while Space is held down. The timers
+    /// speed up too, so games still behave the same.
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    turbo: u32,
+    /// Seed for the random number generator, to make runs reproducible.
+    /// Picked at random if not given.
+    #[arg(long)]
+    seed: Option<u64>,
+    /// File that binds CHIP-8 keys to keyboard keys, one `key = value` per
+    /// line (like `C = Key4`). Defaults to the 1234/QWER/ASDF/ZXCV grid.
+    #[arg(long)]
+    keymap: Option<String>,
+    /// Record every key press, tied to the cycle it happened on, and save it
+    /// to this file on exit. Timers follow emulated time instead of real
+    /// time while recording, so the run can be replayed exactly.
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    record: Option<String>,
+    /// Play back input saved with --record instead of reading the keyboard.
+    /// The seed and speed it was recorded with are used too.
+    #[arg(long, value_name = "FILE")]
+    replay: Option<String>,
+    /// Restart with the new ROM whenever the file changes, for a quicker
+    /// edit-run loop while writing a game.
+    #[arg(long)]
+    watch: bool,
+    /// Draw the screen in the terminal instead of opening a window. P, N and
+    /// M pause, step and step a frame like they do in the window.
+    #[cfg(feature = "tui")]
+    #[arg(long)]
+    tui: bool,
+    /// Print the ROM as assembly and exit without running it.
+    #[arg(long)]
+    disassemble: bool,
+    /// Print what's in the ROM, like which platform's instructions it uses,
+    /// and exit without running it.
+    #[arg(long, conflicts_with = "disassemble")]
+    info: bool,
+    /// Run this many cycles as fast as possible without a window, then
+    /// print how long they took and exit.
+    #[arg(long, value_name = "CYCLES")]
+    bench: Option<u64>,
+    /// Run this many cycles as fast as possible without a window, then
+    /// print a hash of the screen and exit. Without --seed, a fixed seed is
+    /// used so the hash is the same every run.
+    #[arg(long, value_name = "CYCLES")]
+    hash_after: Option<u64>,
+    /// Log more: -v for info (like the CPS), -vv for key presses and -vvv
+    /// for every cycle. RUST_LOG overrides this.
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let env = Env::default().default_filter_or("warn");
+    let (args, mut rom_settings, settings_error) = parse_args();
 
-    env_logger::Builder::from_env(env)
+    // Only for our own logs, since wgpu has plenty to say at info level.
+    let level = match args.verbose {
+        0 => "warn",
+        1 => "warn,chip_8_emulator=info",
+        2 => "warn,chip_8_emulator=debug",
+        _ => "warn,chip_8_emulator=trace",
+    };
+    env_logger::Builder::from_env(Env::default().default_filter_or(level))
         .format(|buf, record| writeln!(buf, "{}: {}", record.level(), record.args()))
         .init();
+    if let Some(e) = settings_error {
+        warn!("Ignoring the settings saved for this ROM: {e}");
+    }
+
+    if args.disassemble {
+        for (address, instruction) in chip_8_emulator::disassemble(&read_program(&args.rom)?) {
+            println!("0x{address:03X}  {instruction}");
+        }
+        return Ok(());
+    }
+    if args.info {
+        print_info(&args.rom, &read_program(&args.rom)?, args.load_address);
+        return Ok(());
+    }
 
-    let args = Args::parse();
+    let keymap = match &args.keymap {
+        Some(path) => Keymap::parse(&std::fs::read_to_string(path)?)?,
+        None => Keymap::default(),
+    };
 
     let (frame_sender, frame_receiver) = channel();
     let (input_sender, input_receiver) = channel();
 
-    // I'm sorry I put this in a mutex, I need to multithread and the Chip8 doesn't
-    // care about the performance loss.
-    let mut chip_8 = Chip8::new(frame_sender, input_receiver);
+    // Benchmarking doesn't show anything, so there's no one to send frames to.
+    let mut chip_8 = if args.bench.is_some() || args.hash_after.is_some() {
+        Chip8::headless()
+    } else {
+        Chip8::new(frame_sender, input_receiver)
+    };
+    chip_8.set_buzzer_tone(args.beep_hz, args.waveform);
+    chip_8.set_buzzer_muted(args.mute);
 
+    if args.paused {
+        chip_8.run_state = RunState::Paused;
+    }
+    if let Some(seed) = args.seed {
+        chip_8.set_seed(seed);
+    } else if args.hash_after.is_some() {
+        chip_8.set_seed(0);
+    }
+
+    // Recording needs a seed to save, and replaying brings its own along
+    // with the speed it was recorded at.
+    let mut cycles_per_second = args.cps;
+    let mut input_log = None;
+    let mut player = None;
+    if args.record.is_some() {
+        let seed = args.seed.unwrap_or_else(rand::random);
+        chip_8.set_seed(seed);
+        input_log = Some(InputLog::new(seed, cycles_per_second));
+    }
+    if let Some(path) = &args.replay {
+        let log = InputLog::parse(&std::fs::read_to_string(path)?)?;
+        chip_8.set_seed(log.seed);
+        if log.cycles_per_second != cycles_per_second {
+            info!(
+                "Replaying at {} instructions per second, like the recording",
+                log.cycles_per_second
+            );
+            cycles_per_second = log.cycles_per_second;
+        }
+        player = Some(Player::new(log));
+    }
+    // With the timers following real time, how far they get between two
+    // instructions depends on the host.
+    let emulated_timers = input_log.is_some() || player.is_some();
+    let replaying = player.is_some();
+    let program = read_program(&args.rom)?;
+    let detected = (!args.no_autodetect)
+        .then(|| Platform::detect(&program))
+        .flatten();
+    if let (None, Some(platform)) = (args.platform, detected) {
+        info!("Recognized the ROM, using the {platform:?} quirks");
+    }
+    // The quirk flags override whatever the platform had.
+    let quirks = &mut chip_8.quirks;
+    *quirks = Quirks::preset(args.platform.or(detected).unwrap_or_default());
+    quirks.shift_uses_vy = args.quirk_shift_uses_vy.unwrap_or(quirks.shift_uses_vy);
+    quirks.load_store_increments_i = args
+        .quirk_load_store_increments_i
+        .unwrap_or(quirks.load_store_increments_i);
+    quirks.jump_uses_vx = args.quirk_jump_uses_vx.unwrap_or(quirks.jump_uses_vx);
+    quirks.index_overflow_sets_vf = args
+        .quirk_index_overflow_sets_vf
+        .unwrap_or(quirks.index_overflow_sets_vf);
+    quirks.logic_resets_vf = args.quirk_logic_resets_vf.unwrap_or(quirks.logic_resets_vf);
+    quirks.display_wait = args.quirk_display_wait.unwrap_or(quirks.display_wait);
+    quirks.wrap_sprites = args.quirk_wrap_sprites.unwrap_or(quirks.wrap_sprites);
+    quirks.large_sprites = args.quirk_large_sprites.unwrap_or(quirks.large_sprites);
+    quirks.draw_timing = args.quirk_draw_timing.unwrap_or(quirks.draw_timing);
+    quirks.extended_memory = args.quirk_extended_memory.unwrap_or(quirks.extended_memory);
+    quirks.audio_pattern = args.quirk_audio_pattern.unwrap_or(quirks.audio_pattern);
+    // Memory is sized to fit the quirks.
     chip_8.initialize()?;
+    for &address in &args.breakpoints {
+        chip_8.add_breakpoint(address);
+    }
+    for &vx in &args.watch_register {
+        chip_8.add_register_watch(vx);
+    }
+    for &address in &args.watch_memory {
+        chip_8.add_memory_watch(address)?;
+    }
+    chip_8.set_code_guard(args.guard_code);
+    chip_8.set_coverage(args.coverage);
+    chip_8.set_coalesce_draws(args.coalesce_draws);
+    chip_8.set_strict_machine_code(args.strict_machine_code);
+    chip_8.set_lenient(args.lenient);
+
+    // Save states go next to the ROM, like `pong.ch8` -> `pong.state`.
+    let state_path = Path::new(&args.rom).with_extension("state");
+    let rom_path = Path::new(&args.rom).to_path_buf();
+    match args.load_address {
+        Some(address) => chip_8.load_program_at(program, address)?,
+        None => chip_8.load_program(program)?,
+    }
 
-    let program_bytes = std::fs::read(args.rom)?;
-    chip_8.load_program(program_bytes.clone())?;
+    if let Some(cycles) = args.bench {
+        bench(chip_8, cycles, cycles_per_second);
+    }
+    if let Some(cycles) = args.hash_after {
+        hash_after(chip_8, cycles, cycles_per_second);
+    }
+
+    // Indexed by the pixel value, which has a bit for each XO-CHIP plane.
+    let mut palette = [args.bg, args.fg, args.fg2, args.blend];
+
+    #[cfg(feature = "tui")]
+    if args.tui {
+        let result = tui::run(
+            &mut chip_8,
+            frame_receiver,
+            input_sender,
+            &keymap,
+            palette,
+            cycles_per_second,
+        );
+        rom_settings.save(&rom_path);
+        if args.stats {
+            print_stats(chip_8.stats());
+        }
+        return result;
+    }
 
     // Hang on to this example for dear life:
     // https://github.com/parasyte/pixels/blob/main/examples/minimal-winit/src/main.rs
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
 
+    let scale = args.scale.unwrap_or(DEFAULT_SCALE);
     let window = {
-        let size = LogicalSize::new((WIDTH * SCALE) as f64, (HEIGHT * SCALE) as f64);
-
-        WindowBuilder::new()
+        let size = LogicalSize::new((WIDTH * scale) as f64, (HEIGHT * scale) as f64);
+        let mut builder = WindowBuilder::new()
             .with_title("CHIP-8 Emulator")
             .with_inner_size(size)
-            .with_min_inner_size(size)
-            .build(&event_loop)
-            .unwrap()
+            .with_min_inner_size(size);
+
+        // Asking for a scale means asking for that size, so only come back
+        // to the old size without one.
+        let geometry = Geometry::load();
+        if let Some(size) = geometry.size.filter(|_| args.scale.is_none()) {
+            builder = builder.with_inner_size(size);
+        }
+        if let Some(position) = geometry.position {
+            builder = builder.with_position(position);
+        }
+
+        builder.build(&event_loop).unwrap()
     };
 
+    let screen_size = chip_8.screen_size();
     let mut pixels = {
         let window_size = window.inner_size();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(WIDTH, HEIGHT, surface_texture)?
+        let (width, height) = match args.overlay {
+            true => (overlay::WIDTH, overlay::HEIGHT),
+            false => screen_size,
+        };
+        Pixels::new(width, height, surface_texture)?
     };
+    let scaling = args.scaling;
+    let mut scaler = Scaler::new(&pixels, scaling, window.inner_size().into());
 
     let mut instant = Instant::now();
     let mut last_cycle = Instant::now();
     let mut cycles = 0;
-    let _game_loop = std::thread::spawn(move || loop {
-        // Check for if we need to restart the program.
-        if chip_8.needs_program_restart {
-            chip_8.initialize().unwrap();
-            info!("Restarting program...");
-            chip_8.load_program(program_bytes.clone()).unwrap();
+    // The timers run off of real time so they stay at 60Hz no matter how
+    // fast the CPU is going.
+    let mut last_timer_update = Instant::now();
+
+    // I'm sorry I put this in a mutex, I need to multithread and the Chip8 doesn't
+    // care about the performance loss.
+    let chip_8 = Arc::new(Mutex::new(chip_8));
+    let game_loop_chip_8 = chip_8.clone();
+
+    if args.watch {
+        let chip_8 = chip_8.clone();
+        let watched_path = rom_path.clone();
+        watch::spawn(&rom_path, move |bytes| {
+            let program = match to_program(&watched_path, bytes) {
+                Ok(program) => program,
+                Err(e) => return error!("Could not load the new ROM: {e}"),
+            };
+            let mut chip_8 = chip_8.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Err(e) = chip_8
+                .initialize()
+                .and_then(|()| chip_8.load_program(program))
+            {
+                error!("Could not load the new ROM: {e}");
+            }
+        });
+    }
+
+    // Lets the event loop stop the game loop on exit, so that the emulator
+    // (and its audio stream) gets dropped properly.
+    let running = Arc::new(AtomicBool::new(true));
+    let game_loop_running = running.clone();
+    let rewinding = Arc::new(AtomicBool::new(false));
+    let game_loop_rewinding = rewinding.clone();
+    let turbo = Arc::new(AtomicBool::new(false));
+    let game_loop_turbo = turbo.clone();
+    let turbo_multiplier = args.turbo;
+    let mut rewind = Rewind::new(args.rewind_frames);
+    let mut last_snapshot = Instant::now();
+    let cps_schedule = args.cps_schedule;
+    // The ceiling is checked every 60th of a second, so hitting it doesn't
+    // stall for the rest of a whole second.
+    let max_cps = args.max_cps;
+    let mut ceiling_start = Instant::now();
+    let mut ceiling_cycles = 0;
+    let mut cycle_duration = Duration::from_secs_f64(1f64 / (cycles_per_second as f64));
+    let mut game_loop = Some(std::thread::spawn(move || loop {
+        if !game_loop_running.load(Ordering::Relaxed) {
+            break input_log;
         }
 
-        let current_cycle = Instant::now();
-        if (current_cycle - last_cycle) < Duration::from_secs_f64(1f64 / (CYCLES_PER_SECOND as f64))
-        {
+        // Make sure we don't hold onto the lock while sleeping.
+        let (waiting, idle, speed) = {
+            let mut chip_8 = game_loop_chip_8
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+
+            let speed = if game_loop_turbo.load(Ordering::Relaxed) {
+                turbo_multiplier
+            } else {
+                1
+            };
+
+            if let Some(cps) = cps_schedule
+                .as_ref()
+                .and_then(|schedule| schedule.at(chip_8.cycle_count()))
+                .filter(|&cps| cps != cycles_per_second)
+            {
+                info!("Switching to {cps} instructions per second");
+                cycles_per_second = cps;
+                cycle_duration = Duration::from_secs_f64(1f64 / (cycles_per_second as f64));
+            }
+
+            let now = Instant::now();
+            if !emulated_timers {
+                chip_8.update_timers((now - last_timer_update) * speed);
+            }
+            last_timer_update = now;
+
+            // Take a snapshot every frame, or step back one while rewinding.
+            let rewinding = game_loop_rewinding.load(Ordering::Relaxed);
+            if now - last_snapshot >= TIMER_INTERVAL {
+                last_snapshot = now;
+                if rewinding {
+                    if let Err(e) = rewind.rewind(&mut chip_8) {
+                        error!("Could not rewind: {e}");
+                    }
+                } else if chip_8.run_state == RunState::Running {
+                    rewind.push(&chip_8);
+                }
+            }
+
+            let current_cycle = Instant::now();
+            if current_cycle - ceiling_start >= TIMER_INTERVAL {
+                ceiling_start = current_cycle;
+                ceiling_cycles = 0;
+            }
+            let over_ceiling = max_cps
+                .is_some_and(|max| ceiling_cycles >= (max.saturating_mul(speed) / 60).max(1));
+            let waiting = match chip_8.run_state {
+                _ if rewinding => true,
+                RunState::Running => {
+                    over_ceiling
+                        || current_cycle.saturating_duration_since(last_cycle)
+                            < cycle_duration / speed
+                }
+                RunState::Paused => true,
+                RunState::Step => {
+                    if let Err(e) = chip_8.step() {
+                        error!("{e}");
+                    }
+                    chip_8.run_state = RunState::Paused;
+                    true
+                }
+                RunState::StepFrame => {
+                    if let Err(e) = chip_8.step_frame((cycles_per_second / 60).max(1)) {
+                        error!("{e}");
+                    }
+                    chip_8.run_state = RunState::Paused;
+                    true
+                }
+            };
+
+            let mut idle = None;
+            if !waiting {
+                if let Some(keys) = player
+                    .as_mut()
+                    .and_then(|p| p.keys_at(chip_8.cycle_count()))
+                {
+                    chip_8.keypad = keys;
+                }
+                // A bad ROM shouldn't take the whole emulator down with it.
+                // Pausing leaves it where it failed, to look at or step past.
+                let address = chip_8.program_counter();
+                match chip_8.cycle() {
+                    Ok(outcome) => {
+                        trace!("0x{address:03X}: {outcome:?}");
+                        if outcome == CycleOutcome::Idle {
+                            idle = Some(chip_8.time_to_next_tick() / speed);
+                        }
+                    }
+                    Err(e) => {
+                        error!("{e}, pausing");
+                        chip_8.run_state = RunState::Paused;
+                    }
+                }
+                if let Some(input_log) = &mut input_log {
+                    input_log.record_cycle(&chip_8);
+                }
+                // Slow instructions push the next cycle back, and count for
+                // more on the emulated timers.
+                let cost = chip_8.cycle_cost();
+                if emulated_timers {
+                    chip_8.update_timers(cycle_duration * cost);
+                    // Skip ahead to the tick we're about to sleep until.
+                    if idle.is_some() {
+                        let next_tick = chip_8.time_to_next_tick();
+                        chip_8.update_timers(next_tick);
+                    }
+                }
+                if Instant::now() - instant > Duration::from_secs(1) {
+                    info!("CPS: {}", cycles);
+                    cycles = 0;
+                    instant = Instant::now();
+                }
+                cycles += 1;
+                ceiling_cycles += 1;
+                last_cycle = Instant::now() + cycle_duration / speed * cost.saturating_sub(1);
+            }
+
+            (waiting, idle, speed)
+        };
+
+        // A program spinning on a jump to itself won't do anything new
+        // before the next timer tick. Keys pressed in the meantime are
+        // picked up on the cycle after it.
+        if let Some(idle) = idle {
+            sleep(idle);
+        } else if waiting {
             sleep(Duration::from_secs_f64(
-                1_f64 / (2 * CYCLES_PER_SECOND) as f64,
+                1_f64 / (2.0 * cycles_per_second as f64 * speed as f64),
             ));
-            continue;
+        } else {
+            // At high speeds we never wait, so give the window a chance to
+            // take the lock between cycles.
+            std::thread::yield_now();
         }
-
-        chip_8.cycle().unwrap();
-        if Instant::now() - instant > Duration::from_secs(1) {
-            info!("CPS: {}", cycles);
-            cycles = 0;
-            instant = Instant::now();
+    }));
+    // Redraw on a fixed schedule rather than whenever events come in, so
+    // the picture keeps moving while nobody touches the keyboard.
+    let fps = args.fps;
+    let frame_interval = Duration::from_secs_f64(1f64 / fps as f64);
+    let mut next_frame = Instant::now();
+    let mut buffer_size = screen_size;
+    // The frame on screen right now, and the GIF being recorded (if any).
+    let mut latest_frame: Box<[u8]> = vec![0; (screen_size.0 * screen_size.1) as usize].into();
+    let mut recording: Option<Recording> = None;
+    // The keys the emulator has been told are held.
+    let mut held_keys = Keypad::default();
+    let mut fade = args.fade.then(|| Fade::new(args.decay));
+    // While the overlay is up, the picture is drawn here first so it can be
+    // blown up to make room for the text.
+    let mut show_overlay = args.overlay;
+    let overlay_color = args.overlay_color;
+    let mut picture = Vec::new();
+    let record_path = args.record;
+    let print_stats_on_exit = args.stats;
+    let mut muted = args.mute;
+    // While the display is frozen, the newest frame waits here so it shows
+    // up as soon as the display is unfrozen.
+    let mut display_frozen = args.freeze_display;
+    let mut held_frame: Option<Box<[u8]>> = None;
+    event_loop.run(move |event, _, control_flow| {
+        if let Event::LoopDestroyed = event {
+            Geometry::of(&window).save();
+            rom_settings.save(&rom_path);
+            running.store(false, Ordering::Relaxed);
+            if let Some(game_loop) = game_loop.take() {
+                if let (Ok(Some(input_log)), Some(path)) = (game_loop.join(), &record_path) {
+                    match std::fs::write(path, input_log.to_text()) {
+                        Ok(()) => info!("Saved input recording to {path}"),
+                        Err(e) => error!("Could not save input recording to {path}: {e}"),
+                    }
+                }
+            }
+            // winit exits the process without dropping anything, so drop the
+            // emulator ourselves to shut down its audio stream. If the game
+            // loop panicked while holding the lock, the emulator is still
+            // fine to drop.
+            let mut chip_8 = chip_8.lock().unwrap_or_else(PoisonError::into_inner);
+            if print_stats_on_exit {
+                print_stats(chip_8.stats());
+            }
+            drop(std::mem::take(&mut *chip_8));
+            return;
         }
-        cycles += 1;
-        last_cycle = Instant::now();
-        if (cycles % 12) == 0 {
-            chip_8.delay_timer.decrement();
-            chip_8.sound_timer.decrement();
+
+        // The game loop only stops on its own if it panicked, and there's
+        // nothing left to show after that.
+        if game_loop
+            .as_ref()
+            .is_some_and(|game_loop| game_loop.is_finished())
+        {
+            error!("The emulator stopped unexpectedly, exiting");
+            *control_flow = ControlFlow::Exit;
+            return;
         }
-    });
-    let mut last_frame = Instant::now();
-    event_loop.run(move |event, _, control_flow| {
+
         // Draw the current frame
         if let Event::RedrawRequested(_) = event {
-            if let Err(err) = pixels.render() {
+            let render = pixels.render_with(|encoder, render_target, _| {
+                scaler.render(encoder, render_target);
+                Ok(())
+            });
+            if let Err(err) = render {
                 log_pixels_error("pixels.render", err);
                 *control_flow = ControlFlow::Exit;
                 return;
@@ -122,12 +764,162 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         // Handle input events
         if input.update(&event) {
             // keyboard events
-            let keycode_opt = crate::chip_8::keypad::handle_keyboard_input(&input, control_flow);
+            // While replaying, the game loop sets the recorded keys instead.
+            match keypad::handle_keyboard_input(&input, &keymap, &mut held_keys, control_flow) {
+                Ok(events) => {
+                    for event in events {
+                        debug!("{event:X?}");
+                        if !replaying {
+                            input_sender
+                                .send(Ok(event))
+                                .unwrap_or_else(|e| error!("Could not send input: {e}"));
+                        }
+                    }
+                }
+                Err(Chip8Error::ProgramRestartRequested) => {
+                    info!("Restarting program...");
+                    if let Err(e) = chip_8
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .reset()
+                    {
+                        error!("Could not restart: {e}");
+                    }
+                }
+                Err(e) if !replaying => input_sender
+                    .send(Err(e))
+                    .unwrap_or_else(|e| error!("Could not send input: {e}")),
+                Err(_) => {}
+            }
 
-            dbg!(&keycode_opt);
+            for hotkey in keypad::handle_hotkeys(&input) {
+                match hotkey {
+                    Hotkey::TogglePause => {
+                        let mut chip_8 = chip_8.lock().unwrap_or_else(PoisonError::into_inner);
+                        chip_8.run_state = match chip_8.run_state {
+                            RunState::Running => {
+                                info!("Paused");
+                                RunState::Paused
+                            }
+                            RunState::Paused | RunState::Step | RunState::StepFrame => {
+                                info!("Resumed");
+                                RunState::Running
+                            }
+                        };
+                    }
+                    Hotkey::Step => {
+                        let mut chip_8 = chip_8.lock().unwrap_or_else(PoisonError::into_inner);
+                        if chip_8.run_state == RunState::Paused {
+                            chip_8.run_state = RunState::Step;
+                        }
+                    }
+                    Hotkey::StepFrame => {
+                        let mut chip_8 = chip_8.lock().unwrap_or_else(PoisonError::into_inner);
+                        if chip_8.run_state == RunState::Paused {
+                            chip_8.run_state = RunState::StepFrame;
+                        }
+                    }
+                    Hotkey::SaveState => {
+                        let state = chip_8
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .save_state();
+                        match std::fs::write(&state_path, state) {
+                            Ok(()) => info!("Saved state to {}", state_path.display()),
+                            Err(e) => error!("Could not save state: {e}"),
+                        }
+                    }
+                    Hotkey::LoadState => match std::fs::read(&state_path) {
+                        Ok(state) => match chip_8
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .load_state(&state)
+                        {
+                            Ok(()) => info!("Loaded state from {}", state_path.display()),
+                            Err(e) => error!("Could not load state: {e}"),
+                        },
+                        Err(e) => error!("Could not read {}: {e}", state_path.display()),
+                    },
+                    Hotkey::Screenshot => {
+                        let (frame, size) = {
+                            let chip_8 = chip_8.lock().unwrap_or_else(PoisonError::into_inner);
+                            (chip_8.screen(), chip_8.screen_size())
+                        };
+                        let path = capture::timestamped_path(&rom_path, "png");
+                        match capture::save_png(&path, &frame, size, &palette, scale) {
+                            Ok(()) => info!("Saved screenshot to {}", path.display()),
+                            Err(e) => error!("Could not save screenshot: {e}"),
+                        }
+                    }
+                    Hotkey::ToggleRecording => match recording.take() {
+                        Some(finished) => save_recording(finished, &rom_path, palette, scale, fps),
+                        None => {
+                            info!(
+                                "Recording, press F10 again to stop (at most {} seconds)",
+                                capture::MAX_RECORDING_FRAMES as u32 / fps
+                            );
+                            recording = Some(Recording::default());
+                        }
+                    },
+                    // The window gets resized on the way in and out, which
+                    // refits the picture the same as any other resize.
+                    Hotkey::ToggleFullscreen => match window.fullscreen() {
+                        Some(_) => window.set_fullscreen(None),
+                        None => window.set_fullscreen(Some(Fullscreen::Borderless(None))),
+                    },
+                    Hotkey::DumpCoverage => match chip_8
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .coverage()
+                    {
+                        Some(coverage) => print_coverage(coverage),
+                        None => warn!("Coverage isn't being tracked, run with --coverage"),
+                    },
+                    Hotkey::ToggleMute => {
+                        muted = !muted;
+                        chip_8
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .set_buzzer_muted(muted);
+                        info!("{}", if muted { "Muted" } else { "Unmuted" });
+                    }
+                    Hotkey::ToggleFreezeDisplay => {
+                        display_frozen = !display_frozen;
+                        match display_frozen {
+                            true => info!("Display frozen, press F4 again to unfreeze"),
+                            false => info!("Display unfrozen"),
+                        }
+                    }
+                    Hotkey::SwapColors => {
+                        palette.swap(0, 1);
+                        rom_settings.set("bg", &format_color(palette[0]));
+                        rom_settings.set("fg", &format_color(palette[1]));
+                        if fade.is_none() && !show_overlay {
+                            draw_frame(pixels.frame_mut(), &latest_frame, &palette);
+                        }
+                    }
+                    Hotkey::ToggleOverlay => {
+                        show_overlay = !show_overlay;
+                        let (width, height) = match show_overlay {
+                            true => (overlay::WIDTH, overlay::HEIGHT),
+                            false => buffer_size,
+                        };
+                        if let Err(err) =
+                            resize_buffer(&mut pixels, &mut scaler, scaling, &window, width, height)
+                        {
+                            log_pixels_error("pixels.resize_buffer", err);
+                            *control_flow = ControlFlow::Exit;
+                            return;
+                        }
+                        if fade.is_none() && !show_overlay {
+                            draw_frame(pixels.frame_mut(), &latest_frame, &palette);
+                        }
+                    }
+                }
+            }
 
-            //dbg!(keycode_opt);
-            input_sender.send(keycode_opt).unwrap();
+            rewinding.store(keypad::rewind_held(&input), Ordering::Relaxed);
+            turbo.store(keypad::turbo_held(&input), Ordering::Relaxed);
 
             // Resize the window
             if let Some(size) = input.window_resized() {
@@ -136,27 +928,462 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     *control_flow = ControlFlow::Exit;
                     return;
                 }
+                scaler.resize(&pixels, size.width, size.height);
             }
-            if let Ok(frame) = frame_receiver.try_recv() {
-                draw_frame(&mut pixels, &frame);
+            // SUPER-CHIP programs can switch resolution, which changes the
+            // size of the frames we get. A frame sent before the switch is
+            // already out of date, and the one after it is on its way.
+            let size = chip_8
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .screen_size();
+            // Only the newest frame gets shown, so skip any that piled up
+            // since the last redraw.
+            if let Some(frame) = frame_receiver.try_iter().last() {
+                held_frame = Some(frame);
             }
-            if last_frame.elapsed() > Duration::from_secs_f64(1f64 / HZ as f64) {
-                last_frame = Instant::now();
-                window.request_redraw();
+            if let Some(frame) = held_frame
+                .take_if(|_| !display_frozen)
+                .filter(|frame| frame.len() == (size.0 * size.1) as usize)
+            {
+                // The overlay is always the same size.
+                if size != buffer_size && !show_overlay {
+                    if let Err(err) =
+                        resize_buffer(&mut pixels, &mut scaler, scaling, &window, size.0, size.1)
+                    {
+                        log_pixels_error("pixels.resize_buffer", err);
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                }
+                buffer_size = size;
+                if fade.is_none() && !show_overlay {
+                    draw_frame(pixels.frame_mut(), &frame, &palette);
+                }
+                latest_frame = frame;
+            }
+            let now = Instant::now();
+            if now >= next_frame {
+                // Stay on the schedule, unless we've fallen a whole frame
+                // behind it.
+                next_frame += frame_interval;
+                if next_frame < now {
+                    next_frame = now + frame_interval;
+                }
+                // Fading changes the picture every frame, even if the
+                // emulator didn't draw anything, and so does the overlay.
+                // A frozen display stays exactly as it was.
+                if !display_frozen {
+                    if show_overlay {
+                        picture.resize(latest_frame.len() * 4, 0);
+                        match &mut fade {
+                            Some(fade) => fade.update(&latest_frame, &palette, &mut picture),
+                            None => draw_frame(&mut picture, &latest_frame, &palette),
+                        }
+                        overlay::upscale(&picture, buffer_size.0, pixels.frame_mut());
+                        let readout =
+                            Readout::of(&chip_8.lock().unwrap_or_else(PoisonError::into_inner));
+                        readout.draw(overlay_color, pixels.frame_mut());
+                    } else if let Some(fade) = &mut fade {
+                        fade.update(&latest_frame, &palette, pixels.frame_mut());
+                    }
+                    window.request_redraw();
+                }
+
+                if let Some(current) = &mut recording {
+                    if !current.push(&latest_frame, buffer_size.0) {
+                        warn!("Recording is at the length limit, stopping");
+                        save_recording(recording.take().unwrap(), &rom_path, palette, scale, fps);
+                    }
+                }
+            }
+            if *control_flow != ControlFlow::Exit {
+                *control_flow = ControlFlow::WaitUntil(next_frame);
             }
         }
     });
 }
 
-fn draw_frame(winit_frame: &mut Pixels, chip_8_frame: &[u8]) {
-    for (i, pixel) in winit_frame.frame_mut().chunks_exact_mut(4).enumerate() {
-        let rgba = match chip_8_frame[i] {
-            0 => [0, 0, 0, 0xFF],
-            1 => [0xFF, 0xFF, 0xFF, 0xFF],
-            _ => panic!("Invalid screen memory value."),
+/// Runs `cycles` cycles back to back for `--bench` and prints how fast that
+/// was. The timers count down as if running at `cycles_per_second`, so
+/// ROMs that wait on them still make progress. Exits with an error code if
+/// a cycle fails.
+fn bench(mut chip_8: Chip8, cycles: u64, cycles_per_second: u32) -> ! {
+    let start = Instant::now();
+    let (ran, error) = run_headless(&mut chip_8, cycles, cycles_per_second);
+
+    let elapsed = start.elapsed();
+    println!(
+        "Ran {ran} cycles in {:.3}s ({:.0} cycles/s)",
+        elapsed.as_secs_f64(),
+        ran as f64 / elapsed.as_secs_f64()
+    );
+    exit_with(error)
+}
+
+/// Runs `cycles` cycles like [`bench`] for `--hash-after`, then prints
+/// [`Chip8::screen_hash`] so it can be compared with a known good run.
+fn hash_after(mut chip_8: Chip8, cycles: u64, cycles_per_second: u32) -> ! {
+    let (_, error) = run_headless(&mut chip_8, cycles, cycles_per_second);
+    println!("{:016x}", chip_8.screen_hash());
+    exit_with(error)
+}
+
+/// Runs up to `cycles` cycles back to back, with the timers counting down
+/// as if running at `cycles_per_second`. Returns how many ran, and the
+/// error that stopped them early if there was one.
+fn run_headless(
+    chip_8: &mut Chip8,
+    cycles: u64,
+    cycles_per_second: u32,
+) -> (u64, Option<Chip8Error>) {
+    let cycle_duration = Duration::from_secs_f64(1f64 / (cycles_per_second as f64));
+    let mut ran = 0;
+    while ran < cycles {
+        if let Err(e) = chip_8.cycle() {
+            return (ran, Some(e));
+        }
+        chip_8.update_timers(cycle_duration * chip_8.cycle_cost());
+        ran += 1;
+    }
+    (ran, None)
+}
+
+/// Exits with an error code and logs `error` if there is one.
+fn exit_with(error: Option<Chip8Error>) -> ! {
+    match error {
+        Some(e) => {
+            error!("{e}");
+            std::process::exit(1)
+        }
+        None => std::process::exit(0),
+    }
+}
+
+/// Encodes a recording on another thread, since a long one can take a while.
+fn save_recording(
+    recording: Recording,
+    rom_path: &Path,
+    palette: [[u8; 4]; 4],
+    scale: u32,
+    fps: u32,
+) {
+    let path = capture::timestamped_path(rom_path, "gif");
+    info!("Saving {} frames to {}", recording.len(), path.display());
+
+    std::thread::spawn(
+        move || match recording.save_gif(&path, &palette, scale, fps) {
+            Ok(()) => info!("Saved recording to {}", path.display()),
+            Err(e) => error!("Could not save recording: {e}"),
+        },
+    );
+}
+
+/// Resizes the pixels buffer to `width` by `height`. The old texture goes
+/// away with it, so the scaler is made again for the new one.
+fn resize_buffer(
+    pixels: &mut Pixels,
+    scaler: &mut Scaler,
+    scaling: Scaling,
+    window: &Window,
+    width: u32,
+    height: u32,
+) -> Result<(), pixels::TextureError> {
+    pixels.resize_buffer(width, height)?;
+    *scaler = Scaler::new(pixels, scaling, window.inner_size().into());
+    Ok(())
+}
+
+fn draw_frame(winit_frame: &mut [u8], chip_8_frame: &[u8], palette: &[[u8; 4]; 4]) {
+    for (i, pixel) in winit_frame.chunks_exact_mut(4).enumerate() {
+        let rgba = match palette.get(chip_8_frame[i] as usize) {
+            Some(rgba) => rgba,
+            None => panic!("Invalid screen memory value."),
+        };
+
+        pixel.copy_from_slice(rgba);
+    }
+}
+
+/// Parses the command line, filling in the `--config` file's settings and
+/// the [`REMEMBERED_FLAGS`] saved for the ROM that weren't given. Also
+/// returns the settings to save on exit, which are the saved ones updated
+/// with what was given this time, and why the saved ones couldn't be used if
+/// they were broken.
+fn parse_args() -> (Args, RomSettings, Option<clap::Error>) {
+    let command_line: Vec<OsString> = std::env::args_os().collect();
+    parse_args_from(&command_line, RomSettings::load).unwrap_or_else(|e| e.exit())
+}
+
+/// [`parse_args`] for `command_line`, with the settings remembered for a ROM
+/// coming from `load_settings`.
+fn parse_args_from(
+    command_line: &[OsString],
+    load_settings: impl FnOnce(&Path) -> RomSettings,
+) -> Result<(Args, RomSettings, Option<clap::Error>), clap::Error> {
+    // Just the command line, which may be missing --rom if the config file
+    // has it.
+    let given_matches = Args::command()
+        .ignore_errors(true)
+        .get_matches_from(command_line);
+    // Arguments are named after their fields, which use underscores.
+    let id = |flag: &str| flag.replace('-', "_");
+    let given =
+        |flag: &str| given_matches.value_source(&id(flag)) == Some(ValueSource::CommandLine);
+
+    // A flag can only be given once, so anything that gets overridden is
+    // left out rather than passed along twice.
+    let config = match given_matches.get_one::<PathBuf>("config") {
+        Some(path) => Config::load(path).map_err(|e| Args::command().error(ErrorKind::Io, e))?,
+        None => Config::default(),
+    };
+    let config_flags: Vec<_> = config
+        .flags()
+        .into_iter()
+        .filter(|(flag, _)| !given(flag))
+        .collect();
+    let in_config = |flag: &str| config_flags.iter().any(|&(config, _)| config == flag);
+    let config_args = config_flags
+        .iter()
+        .map(|(flag, value)| OsString::from(format!("--{flag}={value}")));
+
+    let with_config: Vec<_> = command_line[..1]
+        .iter()
+        .cloned()
+        .chain(config_args.clone())
+        .chain(command_line[1..].iter().cloned())
+        .collect();
+    let matches = Args::command().try_get_matches_from(&with_config)?;
+    let rom = matches.get_one::<String>("rom").expect("--rom is required");
+    let mut settings = load_settings(Path::new(rom));
+
+    let remembered_flags: Vec<_> = settings
+        .flags()
+        .filter(|&(flag, _)| REMEMBERED_FLAGS.contains(&flag) && !given(flag) && !in_config(flag))
+        .map(|(flag, value)| (flag.to_string(), value.to_string()))
+        .collect();
+    for &flag in REMEMBERED_FLAGS.iter().filter(|&&flag| given(flag)) {
+        if let Some(value) = matches
+            .get_raw(&id(flag))
+            .and_then(|mut values| values.next())
+        {
+            settings.set(flag, &value.to_string_lossy());
+        }
+    }
+
+    // The command line wins over the config file, which wins over what was
+    // remembered for the ROM, which wins over the defaults.
+    let remembered = remembered_flags
+        .iter()
+        .map(|(flag, value)| OsString::from(format!("--{flag}={value}")));
+    let with_remembered: Vec<_> = command_line[..1]
+        .iter()
+        .cloned()
+        .chain(remembered)
+        .chain(config_args)
+        .chain(command_line[1..].iter().cloned())
+        .collect();
+    let (matches, error) = match Args::command().try_get_matches_from(with_remembered) {
+        Ok(with_remembered) => (with_remembered, None),
+        Err(e) => (matches, Some(e)),
+    };
+    let args = Args::from_arg_matches(&matches)?;
+    Ok((args, settings, error))
+}
+
+/// Reads the ROM at `path`, assembling it first if it's Octo source.
+/// Warns about extensions ROMs don't usually have, in case it's the wrong
+/// file, but reads it anyway.
+fn read_program(path: impl AsRef<Path>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let path = path.as_ref();
+    if path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_none_or(|extension| !ROM_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+    {
+        warn!(
+            "{} doesn't look like a ROM, they usually end in .{}",
+            path.display(),
+            ROM_EXTENSIONS.join(", .")
+        );
+    }
+    Ok(to_program(path, std::fs::read(path)?)?)
+}
+
+/// Turns the contents of the file at `path` into a program. `.8o` files are
+/// Octo source, which needs the `octo` feature; anything else is already a
+/// program.
+fn to_program(path: &Path, bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+    if path.extension().is_none_or(|extension| extension != "8o") {
+        return Ok(bytes);
+    }
+    #[cfg(feature = "octo")]
+    {
+        let source = String::from_utf8(bytes).map_err(|e| format!("{}: {e}", path.display()))?;
+        chip_8_emulator::octo::assemble(&source).map_err(|e| format!("{}: {e}", path.display()))
+    }
+    #[cfg(not(feature = "octo"))]
+    Err(format!(
+        "{} is Octo source, which needs the emulator built with `--features octo`",
+        path.display()
+    ))
+}
+
+/// Prints every stretch of memory that has run as instructions, one range
+/// per line.
+fn print_coverage(coverage: &[bool]) {
+    let mut start = None;
+    // One past the end, so a range running up to the last byte still ends.
+    for (address, &ran) in coverage.iter().chain([&false]).enumerate() {
+        match (ran, start) {
+            (true, None) => start = Some(address),
+            (false, Some(from)) => {
+                println!("0x{from:03X}-0x{:03X}", address - 1);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Prints what [`Stats`] counted over the run.
+fn print_stats(stats: Stats) {
+    println!("Instructions:    {}", stats.instructions);
+    println!("Draws:           {}", stats.draws);
+    println!("Collisions:      {}", stats.collisions);
+    println!("Max stack depth: {}", stats.max_stack_depth);
+}
+
+/// Prints a summary of the program read from `path`, to help pick the
+/// right quirks before running it. `load_address` is where `--load-address`
+/// puts it, if anywhere but the usual 0x200.
+fn print_info(path: &str, program: &[u8], load_address: Option<u16>) {
+    let instructions = chip_8_emulator::disassemble(program);
+    let decoded = instructions
+        .iter()
+        .filter(|(_, instruction)| !matches!(instruction, Instruction::Unknown { .. }))
+        .count();
+    let count = |platform| {
+        instructions
+            .iter()
+            .filter(|(_, instruction)| instruction.platform() == platform)
+            .count()
+    };
+    let (schip, xochip) = (count(Platform::Schip), count(Platform::XoChip));
+
+    println!("ROM:          {path}");
+    println!("Size:         {} bytes", program.len());
+    print!("Instructions: {decoded} of {} decode", instructions.len());
+    if decoded < instructions.len() {
+        print!(", the rest is probably data");
+    }
+    println!();
+    match instructions
+        .first()
+        .map(|&(address, instruction)| (load_address.unwrap_or(address), instruction))
+    {
+        Some((address, Instruction::Jump { nnn })) => {
+            println!("Entry point:  0x{address:03X}, jumping straight to 0x{nnn:03X}")
+        }
+        Some((address, _)) => println!("Entry point:  0x{address:03X}"),
+        None => println!("Entry point:  none, the ROM is empty"),
+    }
+    println!("SUPER-CHIP:   {schip} instructions");
+    println!("XO-CHIP:      {xochip} instructions");
+    // Data can decode as anything, so this only goes by the opcodes when
+    // the ROM isn't one we know.
+    match Platform::detect(program) {
+        Some(platform) => println!("Platform:     {platform:?} (a known ROM)"),
+        None if xochip > 0 => println!("Platform:     probably {:?}", Platform::XoChip),
+        None if schip > 0 => println!("Platform:     probably {:?}", Platform::Schip),
+        None => println!("Platform:     probably {:?}", Platform::Chip8),
+    }
+}
+
+/// Formats a color the way [`parse_color`] reads it.
+fn format_color([r, g, b, _]: [u8; 4]) -> String {
+    format!("#{r:02X}{g:02X}{b:02X}")
+}
+
+/// Parses a hex color like `#33FF33` (the `#` is optional) into RGBA.
+fn parse_color(hex: &str) -> Result<[u8; 4], String> {
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!(
+            "'{hex}' is not a hex color, expected something like #33FF33"
+        ));
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).unwrap();
+    Ok([channel(0), channel(2), channel(4), 0xFF])
+}
+
+/// Parses a fade decay, which has to be between 0 and 1.
+fn parse_decay(decay: &str) -> Result<f32, String> {
+    match decay.parse::<f32>() {
+        Ok(decay) if (0.0..=1.0).contains(&decay) => Ok(decay),
+        _ => Err(format!("'{decay}' is not between 0 and 1")),
+    }
+}
+
+/// Speeds to switch to as the cycle count goes up, for `--cps-schedule`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CpsSchedule(Vec<(u64, u32)>);
+
+impl CpsSchedule {
+    /// The speed from the last entry that has started by `cycle`, if any.
+    fn at(&self, cycle: u64) -> Option<u32> {
+        self.0
+            .iter()
+            .rev()
+            .find(|&&(start, _)| start <= cycle)
+            .map(|&(_, cps)| cps)
+    }
+}
+
+/// Parses a schedule like `0:300,500:720`, with the cycles going up.
+fn parse_cps_schedule(text: &str) -> Result<CpsSchedule, String> {
+    let mut entries: Vec<(u64, u32)> = Vec::new();
+    for entry in text.split(',') {
+        let parsed = entry.split_once(':').and_then(|(cycle, cps)| {
+            let cps = cps.trim().parse().ok().filter(|&cps| cps > 0)?;
+            Some((cycle.trim().parse().ok()?, cps))
+        });
+        let Some((cycle, cps)) = parsed else {
+            return Err(format!(
+                "'{entry}' is not a schedule entry, expected something like 500:720"
+            ));
         };
+        if entries.last().is_some_and(|&(last, _)| cycle <= last) {
+            return Err(format!("the cycles have to go up, but {cycle} doesn't"));
+        }
+        entries.push((cycle, cps));
+    }
+    Ok(CpsSchedule(entries))
+}
+
+/// Parses a hex address like `0x2A0` (the `0x` is optional).
+fn parse_address(hex: &str) -> Result<u16, String> {
+    let digits = hex.strip_prefix("0x").unwrap_or(hex);
+
+    match u16::from_str_radix(digits, 16) {
+        Ok(address) => Ok(address),
+        _ => Err(format!(
+            "'{hex}' is not an address, expected something like 0x2A0"
+        )),
+    }
+}
+
+fn parse_register(name: &str) -> Result<u8, String> {
+    let digit = name
+        .strip_prefix('V')
+        .or_else(|| name.strip_prefix('v'))
+        .unwrap_or(name);
 
-        pixel.copy_from_slice(&rgba);
+    match u8::from_str_radix(digit, 16) {
+        Ok(vx) if vx <= 0xF => Ok(vx),
+        _ => Err(format!("'{name}' is not a register, expected V0 to VF")),
     }
 }
 
@@ -166,3 +1393,72 @@ fn log_pixels_error<E: std::error::Error + 'static>(method_name: &str, err: E) {
         error!("  Caused by: {}", e);
     }
 }
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    /// Writes `text` to a config file named after the test, so tests don't
+    /// trip over each other.
+    fn config_file(test: &str, text: &str) -> OsString {
+        let path = std::env::temp_dir().join(format!(
+            "chip-8-emulator-{test}-{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(&path, text).unwrap();
+        format!("--config={}", path.display()).into()
+    }
+
+    fn parse(command_line: &[&str], remembered: &[(&str, &str)]) -> Args {
+        let command_line: Vec<OsString> = ["chip_8_emulator", "--rom=game.ch8"]
+            .iter()
+            .chain(command_line)
+            .map(OsString::from)
+            .collect();
+        let (args, _, error) = parse_args_from(&command_line, |_| {
+            let mut settings = RomSettings::default();
+            for (flag, value) in remembered {
+                settings.set(flag, value);
+            }
+            settings
+        })
+        .unwrap();
+        assert!(error.is_none());
+        args
+    }
+
+    #[test]
+    fn layers_the_command_line_over_the_config_over_remembered() {
+        let config = config_file(
+            "layers",
+            "scale = 5\ncps = 700\nfg = \"#112233\"\nmute = true\n",
+        );
+        let config = config.to_str().unwrap();
+        let remembered = [("scale", "3"), ("fg", "#000000"), ("bg", "#445566")];
+
+        let args = parse(&[config, "--cps=900"], &remembered);
+        assert_eq!(args.cps, 900);
+        assert_eq!(args.scale, Some(5));
+        assert_eq!(args.fg, parse_color("#112233").unwrap());
+        assert_eq!(args.bg, parse_color("#445566").unwrap());
+        assert!(args.mute);
+
+        let args = parse(&[config, "--scale=2", "--mute=false"], &remembered);
+        assert_eq!(args.scale, Some(2));
+        assert!(!args.mute);
+
+        let args = parse(&[], &remembered);
+        assert_eq!(args.scale, Some(3));
+        assert_eq!(args.cps, DEFAULT_CYCLES_PER_SECOND);
+    }
+
+    #[test]
+    fn rejects_bad_config_values() {
+        let config = config_file("bad-values", "cps = 0\n");
+        let command_line: Vec<OsString> =
+            vec!["chip_8_emulator".into(), "--rom=game.ch8".into(), config];
+        let error = parse_args_from(&command_line, |_| RomSettings::default()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ValueValidation);
+        assert!(error.to_string().contains("--cps"), "{error}");
+    }
+}
@@ -0,0 +1,148 @@
+//! A readout of the CPU drawn over the picture, for debugging. Toggled with
+//! F3, or turned on from the start with `--overlay`.
+//!
+//! The CHIP-8 screen is far too small to write on, so while the overlay is
+//! up the picture is blown up to [`WIDTH`] by [`HEIGHT`] and the text goes
+//! on top of that.
+
+use chip_8_emulator::Chip8;
+
+/// How big the picture gets while the overlay is up. Both resolutions
+/// divide into it evenly.
+pub const WIDTH: u32 = 512;
+pub const HEIGHT: u32 = 256;
+
+/// How many pixels each dot of a glyph takes up.
+const TEXT_SCALE: usize = 2;
+/// Glyphs are 3x5 dots, with a dot of space after each one and between
+/// lines.
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+const MARGIN: usize = 2 * TEXT_SCALE;
+
+/// What the overlay shows, copied out of the emulator so it doesn't have to
+/// stay locked while we draw.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Readout {
+    program_counter: u16,
+    index: u16,
+    stack_depth: usize,
+    registers: [u8; 16],
+    delay_timer: u8,
+    sound_timer: u8,
+    cycles: u64,
+}
+
+impl Readout {
+    /// Takes a readout of where `chip_8` is at.
+    pub fn of(chip_8: &Chip8) -> Self {
+        Self {
+            program_counter: chip_8.program_counter(),
+            index: chip_8.index(),
+            stack_depth: chip_8.stack().len(),
+            registers: *chip_8.registers(),
+            delay_timer: chip_8.delay_timer.value(),
+            sound_timer: chip_8.sound_timer.value(),
+            cycles: chip_8.cycle_count(),
+        }
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!(
+                "PC {:04X}  I {:04X}  SP {}",
+                self.program_counter, self.index, self.stack_depth
+            ),
+            format!(
+                "DT {:02X}  ST {:02X}  CYCLES {}",
+                self.delay_timer, self.sound_timer, self.cycles
+            ),
+        ];
+        for (row, values) in self.registers.chunks_exact(4).enumerate() {
+            let line: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| format!("V{:X} {value:02X}", row * 4 + i))
+                .collect();
+            lines.push(line.join("  "));
+        }
+        lines
+    }
+
+    /// Writes the readout into the top left corner of `out`, an RGBA
+    /// buffer [`WIDTH`] by [`HEIGHT`] pixels.
+    pub fn draw(&self, color: [u8; 4], out: &mut [u8]) {
+        let line_height = (GLYPH_HEIGHT + 1) * TEXT_SCALE;
+        let advance = (GLYPH_WIDTH + 1) * TEXT_SCALE;
+
+        for (line_number, line) in self.lines().iter().enumerate() {
+            let top = MARGIN + line_number * line_height;
+            for (column, character) in line.chars().enumerate() {
+                let left = MARGIN + column * advance;
+                for (dy, row) in glyph(character).into_iter().enumerate() {
+                    for dx in 0..GLYPH_WIDTH {
+                        if row & (0b100 >> dx) != 0 {
+                            fill_dot(out, left + dx * TEXT_SCALE, top + dy * TEXT_SCALE, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Blows up `picture`, an RGBA buffer `width` pixels across, to fill
+/// `out`, which is [`WIDTH`] by [`HEIGHT`].
+pub fn upscale(picture: &[u8], width: u32, out: &mut [u8]) {
+    let scale = (WIDTH / width) as usize;
+    for (y, row) in out.chunks_exact_mut(WIDTH as usize * 4).enumerate() {
+        let source = (y / scale) * width as usize;
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let i = (source + x / scale) * 4;
+            pixel.copy_from_slice(&picture[i..i + 4]);
+        }
+    }
+}
+
+/// Colors in one dot of a glyph, clipped to the buffer.
+fn fill_dot(out: &mut [u8], left: usize, top: usize, color: [u8; 4]) {
+    let (width, height) = (WIDTH as usize, HEIGHT as usize);
+    for y in top..(top + TEXT_SCALE).min(height) {
+        for x in left..(left + TEXT_SCALE).min(width) {
+            let i = (y * width + x) * 4;
+            out[i..i + 4].copy_from_slice(&color);
+        }
+    }
+}
+
+/// The dots of `character`, a row per byte with the leftmost dot in bit 2.
+/// Only hex digits and the letters the readout uses are here; anything
+/// else is blank.
+fn glyph(character: char) -> [u8; GLYPH_HEIGHT] {
+    match character {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        _ => [0; GLYPH_HEIGHT],
+    }
+}
@@ -0,0 +1,291 @@
+//! Fits the emulator's picture into the window, for `--scaling`.
+//!
+//! pixels' own renderer only scales by whole numbers, which leaves big bars
+//! around the picture in windows that are just short of the next size up.
+//! [`Scaler`] is the same renderer with a choice of how to fit the picture.
+
+use std::borrow::Cow;
+
+use pixels::wgpu::{self, util::DeviceExt};
+use pixels::Pixels;
+
+/// How the picture is fit into the window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Scaling {
+    /// Fill the whole window, even if that stretches the picture.
+    Stretch,
+    /// As big as fits without stretching, with bars on two sides.
+    Aspect,
+    /// Only whole number scales, so every CHIP-8 pixel is the same size.
+    Integer,
+}
+
+/// Maps the full-screen triangle onto the picture and samples the pixels
+/// texture. This is the shader pixels uses for its renderer.
+const SHADER: &str = "
+struct VertexOutput {
+    @location(0) tex_coord: vec2<f32>,
+    @builtin(position) position: vec4<f32>,
+}
+
+struct Locals {
+    transform: mat4x4<f32>,
+}
+@group(0) @binding(2) var<uniform> r_locals: Locals;
+
+@vertex
+fn vs_main(@location(0) position: vec2<f32>) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coord = fma(position, vec2<f32>(0.5, -0.5), vec2<f32>(0.5, 0.5));
+    out.position = r_locals.transform * vec4<f32>(position, 0.0, 1.0);
+    return out;
+}
+
+@group(0) @binding(0) var r_tex_color: texture_2d<f32>;
+@group(0) @binding(1) var r_tex_sampler: sampler;
+
+@fragment
+fn fs_main(@location(0) tex_coord: vec2<f32>) -> @location(0) vec4<f32> {
+    return textureSample(r_tex_color, r_tex_sampler, tex_coord);
+}
+";
+
+/// Draws the pixels texture to the window, scaled with [`Scaling`]. This
+/// holds onto the texture, so it has to be made again after
+/// [`Pixels::resize_buffer`].
+#[derive(Debug)]
+pub struct Scaler {
+    scaling: Scaling,
+    texture_size: (f32, f32),
+    vertex_buffer: wgpu::Buffer,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+    clip_rect: (u32, u32, u32, u32),
+}
+
+impl Scaler {
+    /// Sets up a renderer for `pixels`, drawing to a window of
+    /// `surface_size`.
+    pub fn new(pixels: &Pixels, scaling: Scaling, surface_size: (u32, u32)) -> Self {
+        let context = pixels.context();
+        let device = &context.device;
+        let texture_size = (
+            context.texture_extent.width as f32,
+            context.texture_extent.height as f32,
+        );
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("scaler_shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(SHADER)),
+        });
+        // Nearest neighbor, so the pixels stay sharp.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("scaler_sampler"),
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_max_clamp: 1.0,
+            ..Default::default()
+        });
+
+        // One triangle that covers the whole screen.
+        let vertices: [f32; 6] = [-1.0, -1.0, 3.0, -1.0, -1.0, 3.0];
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("scaler_vertex_buffer"),
+            contents: &floats_to_bytes(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let (transform, clip_rect) = fit(scaling, texture_size, surface_size);
+        let transform = floats_to_bytes(&transform);
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("scaler_uniform_buffer"),
+            contents: &transform,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("scaler_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(transform.len() as u64),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let texture_view = context
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("scaler_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("scaler_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("scaler_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: 2 * std::mem::size_of::<f32>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &[wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x2,
+                        offset: 0,
+                        shader_location: 0,
+                    }],
+                }],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: pixels.render_texture_format(),
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        Self {
+            scaling,
+            texture_size,
+            vertex_buffer,
+            uniform_buffer,
+            bind_group,
+            render_pipeline,
+            clip_rect,
+        }
+    }
+
+    /// Refits the picture to a window that is now `width` by `height`. Call
+    /// this along with [`Pixels::resize_surface`].
+    pub fn resize(&mut self, pixels: &Pixels, width: u32, height: u32) {
+        let (transform, clip_rect) = fit(self.scaling, self.texture_size, (width, height));
+        pixels
+            .context()
+            .queue
+            .write_buffer(&self.uniform_buffer, 0, &floats_to_bytes(&transform));
+        self.clip_rect = clip_rect;
+    }
+
+    /// Draws the picture, with black bars around it if it doesn't fill the
+    /// window.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, render_target: &wgpu::TextureView) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("scaler_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: render_target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(&self.render_pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        let (x, y, width, height) = self.clip_rect;
+        pass.set_scissor_rect(x, y, width, height);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+/// The transform that fits a texture into the surface, and the part of the
+/// surface it covers.
+fn fit(
+    scaling: Scaling,
+    (texture_width, texture_height): (f32, f32),
+    (surface_width, surface_height): (u32, u32),
+) -> ([f32; 16], (u32, u32, u32, u32)) {
+    let (surface_width, surface_height) =
+        (surface_width.max(1) as f32, surface_height.max(1) as f32);
+    let ratio = (surface_width / texture_width).min(surface_height / texture_height);
+    let (width, height) = match scaling {
+        Scaling::Stretch => (surface_width, surface_height),
+        Scaling::Aspect => (texture_width * ratio, texture_height * ratio),
+        Scaling::Integer => {
+            let scale = ratio.floor().max(1.0);
+            (texture_width * scale, texture_height * scale)
+        }
+    };
+
+    // The same half pixel nudge pixels uses, so odd sizes line up.
+    let sw = width / surface_width;
+    let sh = height / surface_height;
+    let tx = (surface_width / 2.0).fract() / surface_width;
+    let ty = (surface_height / 2.0).fract() / surface_height;
+    #[rustfmt::skip]
+    let transform = [
+        sw,  0.0, 0.0, 0.0,
+        0.0, sh,  0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        tx,  ty,  0.0, 1.0,
+    ];
+
+    let width = width.min(surface_width);
+    let height = height.min(surface_height);
+    let clip_rect = (
+        ((surface_width - width) / 2.0) as u32,
+        ((surface_height - height) / 2.0) as u32,
+        width as u32,
+        height as u32,
+    );
+    (transform, clip_rect)
+}
+
+fn floats_to_bytes(floats: &[f32]) -> Vec<u8> {
+    floats
+        .iter()
+        .flat_map(|float| float.to_ne_bytes())
+        .collect()
+}
@@ -0,0 +1,231 @@
+//! Runs the emulator in the terminal, for when there's no window to open
+//! (like over SSH). Enabled with the `tui` feature and `--tui`.
+//!
+//! Each character cell is an upper half block (▀) with the top pixel as its
+//! foreground color and the bottom pixel as its background, so the 64x32
+//! screen fits in 64x16 cells.
+
+use std::error::Error;
+use std::io::{self, Stdout, Write};
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use chip_8_emulator::keypad::{self, Keymap};
+use chip_8_emulator::{self as chip_8, Chip8, Chip8Error, Keypad, RunState};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::{cursor, queue, terminal};
+
+/// How often we check for input and redraw.
+const TICK: Duration = Duration::from_millis(1000 / 60);
+/// Most terminals only say when a key is pressed, and repeat it while it
+/// is held. Without release events, a key counts as held until it hasn't
+/// repeated for this long, which covers the delay before repeating starts.
+/// Those terminals only repeat the last key pressed, so chords need one
+/// that supports release events.
+const HOLD_TIME: Duration = Duration::from_millis(500);
+
+/// Puts the terminal back the way we found it, even if we panic.
+struct TerminalGuard {
+    stdout: Stdout,
+    enhanced_keyboard: bool,
+}
+
+impl TerminalGuard {
+    fn new() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        crossterm::execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+        // Terminals that support it can tell us when a key is released.
+        let enhanced_keyboard = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced_keyboard {
+            crossterm::execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+
+        Ok(Self {
+            stdout,
+            enhanced_keyboard,
+        })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        if self.enhanced_keyboard {
+            let _ = crossterm::execute!(self.stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = crossterm::execute!(
+            self.stdout,
+            ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Runs `chip_8` until Escape (or Ctrl+C) is pressed. Frames come in through
+/// `frames` and keys go out through `input`, just like with the window.
+pub fn run(
+    chip_8: &mut Chip8,
+    frames: Receiver<Box<[u8]>>,
+    input: Sender<Result<chip_8::KeyEvent, Chip8Error>>,
+    keymap: &Keymap,
+    palette: [[u8; 4]; 4],
+    cycles_per_second: u32,
+) -> Result<(), Box<dyn Error>> {
+    let mut terminal = TerminalGuard::new()?;
+
+    let cycle_duration = Duration::from_secs_f64(1.0 / cycles_per_second as f64);
+    let mut last_update = Instant::now();
+    // Real time we still owe the CPU, when each held key was last seen and
+    // the keys we told the emulator about.
+    let mut owed = Duration::ZERO;
+    let mut held: [Option<Instant>; 16] = [None; 16];
+    let mut sent_keys = Keypad::default();
+    let mut last_width = 0;
+
+    loop {
+        while event::poll(Duration::ZERO)? {
+            let Event::Key(key_event) = event::read()? else {
+                continue;
+            };
+            if is_quit(&key_event) {
+                return Ok(());
+            }
+
+            if key_event.code == KeyCode::Tab && key_event.kind == KeyEventKind::Press {
+                chip_8.reset()?;
+                continue;
+            }
+            if key_event.kind == KeyEventKind::Press {
+                handle_hotkey(chip_8, key_event.code, cycles_per_second)?;
+            }
+
+            let KeyCode::Char(character) = key_event.code else {
+                continue;
+            };
+            let Some(key) = keypad::parse_keyboard_key(&character.to_string())
+                .and_then(|keyboard_key| keymap.chip_8_key(keyboard_key))
+            else {
+                continue;
+            };
+            held[key as usize] = match key_event.kind {
+                KeyEventKind::Press | KeyEventKind::Repeat => Some(Instant::now()),
+                KeyEventKind::Release => None,
+            };
+        }
+
+        let mut keys = Keypad::default();
+        for (key, at) in held.iter_mut().enumerate() {
+            if !terminal.enhanced_keyboard && at.is_some_and(|at| at.elapsed() > HOLD_TIME) {
+                *at = None;
+            }
+            keys.set(key as u8, at.is_some());
+        }
+        for event in sent_keys.changes_to(keys) {
+            input.send(Ok(event))?;
+        }
+        sent_keys = keys;
+
+        let now = Instant::now();
+        let elapsed = now - last_update;
+        last_update = now;
+        chip_8.update_timers(elapsed);
+        if chip_8.run_state == RunState::Running {
+            owed += elapsed;
+        }
+        while owed >= cycle_duration && chip_8.run_state == RunState::Running {
+            owed -= cycle_duration;
+            chip_8.cycle()?;
+            owed = owed.saturating_sub(cycle_duration * chip_8.cycle_cost().saturating_sub(1));
+        }
+        // A breakpoint can pause us partway through.
+        if chip_8.run_state != RunState::Running {
+            owed = Duration::ZERO;
+        }
+
+        if let Some(frame) = frames.try_iter().last() {
+            let (width, _) = chip_8.screen_size();
+            // Every cell gets drawn over, unless the resolution got smaller.
+            if width != last_width {
+                queue!(terminal.stdout, terminal::Clear(terminal::ClearType::All))?;
+                last_width = width;
+            }
+            draw(&mut terminal.stdout, &frame, width, &palette)?;
+        }
+
+        std::thread::sleep(TICK.saturating_sub(now.elapsed()));
+    }
+}
+
+/// Pauses and steps like the window does: P pauses or resumes, N runs a
+/// single instruction and M a single frame while paused.
+fn handle_hotkey(
+    chip_8: &mut Chip8,
+    code: KeyCode,
+    cycles_per_second: u32,
+) -> Result<(), Chip8Error> {
+    let paused = chip_8.run_state != RunState::Running;
+    match code {
+        KeyCode::Char('p') => {
+            chip_8.run_state = if paused {
+                RunState::Running
+            } else {
+                RunState::Paused
+            };
+        }
+        KeyCode::Char('n') if paused => {
+            chip_8.step()?;
+            chip_8.run_state = RunState::Paused;
+        }
+        KeyCode::Char('m') if paused => {
+            chip_8.step_frame((cycles_per_second / 60).max(1))?;
+            chip_8.run_state = RunState::Paused;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn is_quit(key_event: &KeyEvent) -> bool {
+    key_event.code == KeyCode::Esc
+        || (key_event.code == KeyCode::Char('c')
+            && key_event.modifiers.contains(KeyModifiers::CONTROL))
+}
+
+/// Draws a frame two rows of pixels at a time.
+fn draw(stdout: &mut Stdout, frame: &[u8], width: u32, palette: &[[u8; 4]; 4]) -> io::Result<()> {
+    let color = |pixel: u8| {
+        let [r, g, b, _] = palette[pixel as usize & 0b11];
+        Color::Rgb { r, g, b }
+    };
+
+    for (row, pixels) in frame.chunks_exact(2 * width as usize).enumerate() {
+        let (top, bottom) = pixels.split_at(width as usize);
+        queue!(stdout, cursor::MoveTo(0, row as u16))?;
+        // Only change colors when they differ from the last cell's.
+        let mut colors = None;
+        for (&top, &bottom) in top.iter().zip(bottom) {
+            if colors != Some((top, bottom)) {
+                queue!(
+                    stdout,
+                    SetForegroundColor(color(top)),
+                    SetBackgroundColor(color(bottom))
+                )?;
+                colors = Some((top, bottom));
+            }
+            queue!(stdout, Print('▀'))?;
+        }
+        queue!(stdout, ResetColor)?;
+    }
+
+    stdout.flush()
+}
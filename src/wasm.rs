@@ -0,0 +1,67 @@
+//! A thin [`wasm-bindgen`](https://rustwasm.github.io/wasm-bindgen/) wrapper
+//! around [`Chip8`], for running the emulator in the browser. It's only
+//! built with the `wasm` feature.
+//!
+//! The page owns the loop: call [`WasmChip8::cycle`] as often as the game
+//! should run, [`WasmChip8::update_timers`] with the time that passed, and
+//! draw [`WasmChip8::screen`] whenever it likes.
+
+use std::time::Duration;
+
+use wasm_bindgen::prelude::*;
+
+use crate::Chip8;
+
+/// A headless [`Chip8`] that JavaScript can hold on to.
+#[wasm_bindgen]
+pub struct WasmChip8(Chip8);
+
+#[wasm_bindgen]
+impl WasmChip8 {
+    /// Creates an emulator with its memory initialized, ready for
+    /// [`Self::load_program`].
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<WasmChip8, JsError> {
+        let mut chip_8 = Chip8::headless();
+        chip_8.initialize()?;
+        Ok(Self(chip_8))
+    }
+
+    /// See [`Chip8::load_program`].
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), JsError> {
+        Ok(self.0.load_program(program.to_vec())?)
+    }
+
+    /// Runs one instruction, see [`Chip8::cycle`].
+    pub fn cycle(&mut self) -> Result<(), JsError> {
+        self.0.cycle()?;
+        Ok(())
+    }
+
+    /// Counts the timers down for `elapsed_ms` milliseconds of real time,
+    /// see [`Chip8::update_timers`].
+    pub fn update_timers(&mut self, elapsed_ms: f64) {
+        self.0
+            .update_timers(Duration::from_secs_f64(elapsed_ms.max(0.0) / 1000.0));
+    }
+
+    /// See [`Chip8::set_key`].
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        self.0.set_key(key, pressed);
+    }
+
+    /// One byte per pixel, row by row, see [`Chip8::screen`].
+    pub fn screen(&self) -> Vec<u8> {
+        self.0.screen()
+    }
+
+    /// How many pixels wide [`Self::screen`] is right now.
+    pub fn screen_width(&self) -> u32 {
+        self.0.screen_size().0
+    }
+
+    /// How many pixels tall [`Self::screen`] is right now.
+    pub fn screen_height(&self) -> u32 {
+        self.0.screen_size().1
+    }
+}
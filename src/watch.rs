@@ -0,0 +1,80 @@
+//! Reloads the ROM when it changes on disk, for `--watch`.
+
+use std::path::Path;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::Duration;
+
+use log::{error, info, warn};
+use notify::{EventKind, RecursiveMode, Watcher};
+
+/// How long the file has to stay quiet before we reload it. Saving a file
+/// usually shows up as a few events in a row (truncate, write, rename...).
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watches `rom` on its own thread, calling `on_change` with the new bytes
+/// every time it is saved. Failing to set up the watch only logs a warning,
+/// since the emulator works fine without it.
+pub fn spawn(rom: &Path, on_change: impl Fn(Vec<u8>) + Send + 'static) {
+    // Editors often save by writing a new file and renaming it over the old
+    // one, which a watch on the file itself would lose track of. Watching
+    // the directory catches that too.
+    let rom = match rom.canonicalize() {
+        Ok(rom) => rom,
+        Err(e) => {
+            warn!("Could not watch {}: {e}", rom.display());
+            return;
+        }
+    };
+    let directory = rom.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let (sender, receiver) = channel();
+    let mut watcher = match notify::recommended_watcher(sender) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            warn!("Could not watch {}: {e}", rom.display());
+            return;
+        }
+    };
+    if let Err(e) = watcher.watch(&directory, RecursiveMode::NonRecursive) {
+        warn!("Could not watch {}: {e}", rom.display());
+        return;
+    }
+
+    std::thread::spawn(move || {
+        // The watcher stops when it is dropped, so it lives on this thread.
+        let _watcher = watcher;
+        let changed = |event: notify::Result<notify::Event>| match event {
+            Ok(event) => !matches!(event.kind, EventKind::Access(_)) && event.paths.contains(&rom),
+            Err(e) => {
+                error!("Error watching {}: {e}", rom.display());
+                false
+            }
+        };
+
+        while let Ok(event) = receiver.recv() {
+            if !changed(event) {
+                continue;
+            }
+
+            // Wait for the events to die down before reading the file.
+            loop {
+                match receiver.recv_timeout(DEBOUNCE) {
+                    Ok(_) => continue,
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+
+            match std::fs::read(&rom) {
+                // Half-written files are usually empty, and the next event
+                // will pick up the finished one.
+                Ok(program) if program.is_empty() => {}
+                Ok(program) => {
+                    info!("{} changed, reloading...", rom.display());
+                    on_change(program);
+                }
+                Err(e) => warn!("Could not reload {}: {e}", rom.display()),
+            }
+        }
+    });
+}